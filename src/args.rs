@@ -0,0 +1,159 @@
+/// The usage printed by `--help`.
+pub const USAGE: &str = "\
+Usage: hecto [options] [+line[,column]] [--] [file]
+
+Options:
+  +<line>[,<column>]  Open the file at the line, e.g., +42 or +42,7
+  --encoding <label>  Open the file in the encoding, e.g., latin1
+  --errors <file>     Load the errors to go to from the file, or - for the standard input
+  -h, --help          Print this help and exit
+  -v, --version       Print the version and exit
+";
+
+/// The command-line arguments. Anything that isn't an option is the filename, as is everything
+/// after `--`, so that a file whose name starts with `-` can be opened.
+#[derive(Default)]
+pub struct Args {
+    pub filename: Option<String>,
+    /// The label of the `--encoding` option, given as `--encoding <label>` or `--encoding=<label>`.
+    pub encoding: Option<String>,
+    /// The file of the `--errors` option, given as `--errors <file>` or `--errors=<file>`; `-` for
+    /// the standard input.
+    pub errors: Option<String>,
+    /// The 1-based line to open the file at, given as `+<line>` or `+<line>,<column>`.
+    pub line: Option<usize>,
+    /// The 1-based column to open the file at.
+    pub column: Option<usize>,
+    pub show_help: bool,
+    pub show_version: bool,
+}
+
+impl Args {
+    /// Parses the arguments, excluding the program name.
+    pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Self {
+        let mut parsed = Self::default();
+        let mut are_options_ended = false;
+        while let Some(arg) = args.next() {
+            if are_options_ended {
+                parsed.filename = Some(arg);
+                continue;
+            }
+            match arg.as_str() {
+                "--" => are_options_ended = true,
+                "-h" | "--help" => parsed.show_help = true,
+                "-v" | "--version" => parsed.show_version = true,
+                "--encoding" => parsed.encoding = args.next(),
+                "--errors" => parsed.errors = args.next(),
+                _ => {
+                    if let Some(label) = arg.strip_prefix("--encoding=") {
+                        parsed.encoding = Some(label.to_owned());
+                    } else if let Some(filename) = arg.strip_prefix("--errors=") {
+                        parsed.errors = Some(filename.to_owned());
+                    } else if let Some((line, column)) = parse_position(&arg) {
+                        parsed.line = Some(line);
+                        parsed.column = column;
+                    } else {
+                        parsed.filename = Some(arg);
+                    }
+                }
+            }
+        }
+        parsed
+    }
+}
+
+/// Parses `+<line>` or `+<line>,<column>`; `None` if the argument isn't of the form, e.g., `+foo`,
+/// which is then a filename.
+fn parse_position(arg: &str) -> Option<(usize, Option<usize>)> {
+    let parse_number = |s: &str| {
+        s.bytes()
+            .all(|b| b.is_ascii_digit())
+            .then(|| s.parse::<usize>().ok())
+            .flatten()
+    };
+    let position = arg.strip_prefix('+')?;
+    match position.split_once(',') {
+        Some((line, column)) => Some((parse_number(line)?, Some(parse_number(column)?))),
+        None => Some((parse_number(position)?, None)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Args {
+        Args::parse(args.iter().map(|arg| (*arg).to_owned()))
+    }
+
+    #[test]
+    fn tells_flags_from_filenames() {
+        let args = parse(&["-h", "notes.txt"]);
+        assert!(args.show_help);
+        assert!(!args.show_version);
+        assert_eq!(args.filename.as_deref(), Some("notes.txt"));
+        let args = parse(&["--version"]);
+        assert!(args.show_version);
+        assert_eq!(args.filename, None);
+    }
+
+    #[test]
+    fn takes_everything_after_double_dash_as_the_filename() {
+        let args = parse(&["--", "-h"]);
+        assert!(!args.show_help);
+        assert_eq!(args.filename.as_deref(), Some("-h"));
+        let args = parse(&["--", "--"]);
+        assert_eq!(args.filename.as_deref(), Some("--"));
+    }
+
+    #[test]
+    fn takes_the_encoding_label_in_either_form() {
+        assert_eq!(
+            parse(&["--encoding", "latin1"]).encoding.as_deref(),
+            Some("latin1")
+        );
+        assert_eq!(
+            parse(&["--encoding=sjis", "a"]).encoding.as_deref(),
+            Some("sjis")
+        );
+    }
+
+    #[test]
+    fn takes_the_error_file_in_either_form() {
+        let args = parse(&["--errors", "-", "main.rs"]);
+        assert_eq!(args.errors.as_deref(), Some("-"));
+        assert_eq!(args.filename.as_deref(), Some("main.rs"));
+        assert_eq!(
+            parse(&["--errors=build.log"]).errors.as_deref(),
+            Some("build.log")
+        );
+    }
+
+    #[test]
+    fn takes_the_line_to_open_at() {
+        let args = parse(&["+42", "notes.txt"]);
+        assert_eq!(args.line, Some(42));
+        assert_eq!(args.column, None);
+        assert_eq!(args.filename.as_deref(), Some("notes.txt"));
+    }
+
+    #[test]
+    fn takes_the_line_and_column_to_open_at() {
+        let args = parse(&["+42,7", "notes.txt"]);
+        assert_eq!(args.line, Some(42));
+        assert_eq!(args.column, Some(7));
+        assert_eq!(args.filename.as_deref(), Some("notes.txt"));
+    }
+
+    #[test]
+    fn takes_a_malformed_position_as_the_filename() {
+        for arg in ["+foo", "+", "+42,", "+-1", "+4,x"] {
+            let args = parse(&[arg]);
+            assert_eq!(args.line, None);
+            assert_eq!(args.filename.as_deref(), Some(arg));
+        }
+        let args = parse(&["--", "+42"]);
+        assert_eq!(args.line, None);
+        assert_eq!(args.filename.as_deref(), Some("+42"));
+    }
+}
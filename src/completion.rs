@@ -0,0 +1,152 @@
+use std::fs;
+
+/// Completes a partially typed file path against the filesystem.
+/// Repeatedly completing the same input cycles through the candidates.
+#[derive(Default)]
+pub struct PathCompleter {
+    /// The matching paths of the last completion, sorted.
+    candidates: Vec<String>,
+    /// The candidate last given while cycling.
+    index: Option<usize>,
+    /// The input produced by the last completion, to tell whether the user is cycling.
+    last_completion: String,
+}
+
+impl PathCompleter {
+    /// Returns the completed input: the longest common prefix of the matching paths if it extends
+    /// the input; otherwise, the next matching path. Directories end with `/`.
+    /// `None` if nothing matches.
+    pub fn complete(&mut self, input: &str) -> Option<String> {
+        let is_cycling = !self.candidates.is_empty() && input == self.last_completion;
+        if !is_cycling {
+            self.candidates = Self::candidates(input);
+            self.index = None;
+        }
+        let completion = match self.candidates.as_slice() {
+            [] => return None,
+            [only] => only.clone(),
+            candidates => {
+                let prefix = longest_common_prefix(candidates);
+                if !is_cycling && prefix.chars().count() > input.chars().count() {
+                    prefix
+                } else {
+                    #[allow(clippy::arithmetic_side_effects)] // There are candidates.
+                    let index = self
+                        .index
+                        .map_or(0, |index| index.saturating_add(1) % candidates.len());
+                    self.index = Some(index);
+                    candidates.get(index)?.clone()
+                }
+            }
+        };
+        self.last_completion.clone_from(&completion);
+        Some(completion)
+    }
+
+    /// The paths in the directory of the input whose names start with the rest of the input.
+    /// Hidden entries are only listed if the name being completed starts with a dot.
+    fn candidates(input: &str) -> Vec<String> {
+        let (dir, name_prefix) = input.rsplit_once('/').unwrap_or(("", input));
+        let dir = if input.contains('/') {
+            format!("{dir}/")
+        } else {
+            String::new()
+        };
+        let Ok(entries) = fs::read_dir(if dir.is_empty() { "." } else { &dir }) else {
+            return Vec::new();
+        };
+        let mut candidates: Vec<String> = entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                let name = entry.file_name().into_string().ok()?;
+                if !name.starts_with(name_prefix)
+                    || (name.starts_with('.') && !name_prefix.starts_with('.'))
+                {
+                    return None;
+                }
+                let is_dir = entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+                Some(format!("{dir}{name}{}", if is_dir { "/" } else { "" }))
+            })
+            .collect();
+        candidates.sort();
+        candidates
+    }
+}
+
+fn longest_common_prefix(strings: &[String]) -> String {
+    let Some((first, rest)) = strings.split_first() else {
+        return String::new();
+    };
+    let mut prefix = first.clone();
+    for string in rest {
+        let common_len: usize = prefix
+            .chars()
+            .zip(string.chars())
+            .take_while(|(a, b)| a == b)
+            .map(|(c, _)| c.len_utf8())
+            .sum();
+        prefix.truncate(common_len);
+    }
+    prefix
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TempPath;
+    use std::path::Path;
+
+    /// A directory of a few files and a subdirectory, unique to the test of the name.
+    fn fixture_dir(name: &str) -> TempPath {
+        let dir = TempPath::new(name);
+        let path = Path::new(&*dir);
+        let created = fs::create_dir_all(path.join("src")).and_then(|()| {
+            ["foo.txt", "foobar.txt", "bar.txt", ".hidden"]
+                .iter()
+                .try_for_each(|file| fs::write(path.join(file), ""))
+        });
+        if let Err(err) = created {
+            panic!("failed to create {dir}: {err}");
+        }
+        dir
+    }
+
+    #[test]
+    fn completes_the_longest_common_prefix_then_cycles() {
+        let fixture = fixture_dir("complete-cycle");
+        let dir = format!("{fixture}/");
+        let mut completer = PathCompleter::default();
+        let prefix = completer.complete(&format!("{dir}f"));
+        assert_eq!(prefix, Some(format!("{dir}foo")));
+        let Some(prefix) = prefix else {
+            panic!("nothing completed");
+        };
+        let first = completer.complete(&prefix);
+        assert_eq!(first, Some(format!("{dir}foo.txt")));
+        let second = completer.complete(&format!("{dir}foo.txt"));
+        assert_eq!(second, Some(format!("{dir}foobar.txt")));
+        let wrapped = completer.complete(&format!("{dir}foobar.txt"));
+        assert_eq!(wrapped, Some(format!("{dir}foo.txt")));
+    }
+
+    #[test]
+    fn completes_a_directory_with_a_slash_and_skips_hidden_files() {
+        let fixture = fixture_dir("complete-dir");
+        let dir = format!("{fixture}/");
+        let mut completer = PathCompleter::default();
+        assert_eq!(
+            completer.complete(&format!("{dir}s")),
+            Some(format!("{dir}src/"))
+        );
+        assert_eq!(completer.complete(&format!("{dir}x")), None);
+        assert_eq!(
+            completer.complete(&format!("{dir}.h")),
+            Some(format!("{dir}.hidden"))
+        );
+        let candidates = PathCompleter::candidates(&dir);
+        assert_eq!(
+            candidates,
+            ["bar.txt", "foo.txt", "foobar.txt", "src/"].map(|name| format!("{dir}{name}"))
+        );
+    }
+}
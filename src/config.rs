@@ -0,0 +1,306 @@
+use crate::CursorShape;
+use crate::IndentStyle;
+use crate::Keymap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// The user preferences, read from `$XDG_CONFIG_HOME/hecto/config` (or `~/.config/hecto/config`).
+/// The file consists of `key = value` lines, with lines starting with `#` being comments.
+/// Unknown keys and malformed values are ignored, keeping the default.
+#[allow(clippy::struct_excessive_bools)]
+pub struct Config {
+    /// Starts in Normal mode and edits Vim-like, instead of always inserting.
+    pub modal: bool,
+    /// The cursor shape in Insert mode; Normal mode always uses a block.
+    pub cursor_shape: CursorShape,
+    /// The number of columns between tab stops.
+    pub tab_width: usize,
+    /// Inserts spaces up to the next tab stop instead of a tab.
+    pub soft_tabs: bool,
+    /// Whether `tab_width` or `soft_tabs` is set in the file, which then takes precedence over
+    /// the indentation of the opened file.
+    is_indent_set: bool,
+    /// The `soft_tabs` and `tab_width` before they were seeded from a file, which seeding from
+    /// another file starts over from.
+    unseeded_indent: Option<(bool, usize)>,
+    /// Indents a new line as the line it's broken from, and by one more level after an opening
+    /// bracket in code.
+    pub auto_indent: bool,
+    /// With `auto_indent`, indents a new line next to a blank one as the nearest non-blank line
+    /// above it instead.
+    pub auto_indent_from_nearest: bool,
+    /// Collapses the whitespace where lines are joined to a single space, or none next to a `(` or
+    /// `[` and its closing bracket.
+    pub smart_join: bool,
+    /// Saves a symbolic link by writing to the file it points to, keeping the link; otherwise, the
+    /// link is replaced with a regular file.
+    pub save_through_symlinks: bool,
+    /// Inserts the closing bracket or quote along with the opening one, and types over it.
+    pub auto_pair: bool,
+    /// Doesn't pair a quote typed right after a word character, e.g., the apostrophe in `don't`.
+    pub auto_pair_skip_after_word: bool,
+    /// Doesn't pair a quote typed inside a string, as highlighted.
+    pub auto_pair_skip_in_string: bool,
+    /// The number of lines kept in view above and below the cursor when scrolling, so that the
+    /// cursor stays at the same screen row while moving through the middle of the document.
+    pub scroll_off: usize,
+    /// The keys of the commands, set by the `key_<command>` keys, e.g., `key_save = ctrl-w`.
+    pub keymap: Keymap,
+    /// Requires pressing the quit key repeatedly even without unsaved changes.
+    pub confirm_quit_always: bool,
+    /// Searches regardless of case.
+    pub search_ignore_case: bool,
+    /// Searches regardless of case unless the query has an uppercase letter.
+    pub search_smart_case: bool,
+    /// Flashes the screen on an invalid action, e.g., moving past the end of the document.
+    pub visual_bell: bool,
+    /// Rings the bell on Backspace at the very start of the document, where there's nothing to
+    /// delete, rather than doing nothing: flashes the screen with `visual_bell`, or else rings the
+    /// audible bell.
+    pub bell_on_backspace_at_start: bool,
+    /// Shows how long ago the document was saved in the status bar, e.g., `saved 2m ago`.
+    pub show_last_saved: bool,
+    /// The number of blank lines kept at the end of the file on save, e.g., 0 to end with the last
+    /// non-blank line; `None`, set by `keep`, leaves them as they are.
+    pub max_trailing_blank_lines: Option<usize>,
+    /// The number of visual columns past which the rest of a line is marked as too long; `None`,
+    /// set by `off`, marks none.
+    pub max_line_length: Option<usize>,
+    /// The number of characters typed in a row that are undone together; whitespace and moving
+    /// the cursor also start a new undo step.
+    pub undo_coalesce_max: usize,
+    /// The number of words to write, whose progress is shown in the status bar; 0 hides it.
+    pub word_goal: usize,
+    /// The size in bytes above which a file isn't highlighted, as that's slow on huge files.
+    pub large_file_bytes: usize,
+    /// The number of seconds a message stays in the message bar; 0 keeps it until it's replaced.
+    pub message_timeout_secs: u64,
+    /// Normalizes the text to NFC when opening a file.
+    #[cfg(feature = "nfc")]
+    pub normalize_on_open: bool,
+    /// Normalizes the text to NFC before saving a file.
+    #[cfg(feature = "nfc")]
+    pub normalize_on_save: bool,
+    /// The wordlist to spell-check plain text and Markdown against, with one word per line.
+    #[cfg(feature = "spell")]
+    pub spell_dictionary: String,
+    /// The `strftime`-like format of the date inserted by the `insert_date` command.
+    #[cfg(feature = "datetime")]
+    pub date_format: String,
+    /// Drawn on the lines past the end of the document; nothing if empty.
+    pub empty_row_marker: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            modal: false,
+            cursor_shape: CursorShape::default(),
+            tab_width: 4,
+            soft_tabs: false,
+            is_indent_set: false,
+            unseeded_indent: None,
+            auto_indent: false,
+            auto_indent_from_nearest: false,
+            smart_join: true,
+            save_through_symlinks: true,
+            auto_pair: false,
+            auto_pair_skip_after_word: true,
+            auto_pair_skip_in_string: true,
+            scroll_off: 0,
+            keymap: Keymap::default(),
+            confirm_quit_always: false,
+            search_ignore_case: false,
+            search_smart_case: false,
+            visual_bell: false,
+            bell_on_backspace_at_start: false,
+            show_last_saved: false,
+            max_trailing_blank_lines: None,
+            max_line_length: None,
+            undo_coalesce_max: 20,
+            word_goal: 0,
+            large_file_bytes: 10 * 1024 * 1024,
+            message_timeout_secs: 5,
+            #[cfg(feature = "nfc")]
+            normalize_on_open: false,
+            #[cfg(feature = "nfc")]
+            normalize_on_save: false,
+            #[cfg(feature = "spell")]
+            spell_dictionary: "/usr/share/dict/words".to_owned(),
+            #[cfg(feature = "datetime")]
+            date_format: "%Y-%m-%d %H:%M".to_owned(),
+            empty_row_marker: "~".to_owned(),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the config file; the defaults are used if there's no such file.
+    #[must_use]
+    pub fn load() -> Self {
+        let mut config = Self::default();
+        if let Some(content) = Self::path().and_then(|path| fs::read_to_string(path).ok()) {
+            for line in content.lines() {
+                let line = line.trim();
+                if line.starts_with('#') {
+                    continue;
+                }
+                if let Some((key, value)) = line.split_once('=') {
+                    config.set(key.trim(), value.trim());
+                }
+            }
+        }
+        config
+    }
+
+    /// Indents as the opened file does, or as before any file was seeded from if it doesn't tell,
+    /// unless the indentation is set in the config file.
+    pub fn seed_indent(&mut self, style: Option<IndentStyle>) {
+        if self.is_indent_set {
+            return;
+        }
+        let (soft_tabs, tab_width) = *self
+            .unseeded_indent
+            .get_or_insert((self.soft_tabs, self.tab_width));
+        (self.soft_tabs, self.tab_width) = match style {
+            Some(IndentStyle::Tabs) => (false, tab_width),
+            Some(IndentStyle::Spaces(width)) => (true, width),
+            None => (soft_tabs, tab_width),
+        };
+    }
+
+    fn path() -> Option<PathBuf> {
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+            .map(|dir| dir.join("hecto").join("config"))
+    }
+
+    fn set(&mut self, key: &str, value: &str) {
+        match key {
+            "modal" => parse_into(&mut self.modal, value),
+            "cursor_shape" => parse_into(&mut self.cursor_shape, value),
+            "tab_width" => {
+                parse_into(&mut self.tab_width, value);
+                self.is_indent_set = true;
+            }
+            "soft_tabs" => {
+                parse_into(&mut self.soft_tabs, value);
+                self.is_indent_set = true;
+            }
+            "key_save" => parse_into(&mut self.keymap.save, value),
+            "key_quit" => parse_into(&mut self.keymap.quit, value),
+            "key_find" => parse_into(&mut self.keymap.find, value),
+            "key_command_palette" => parse_into(&mut self.keymap.command_palette, value),
+            "key_undo" => parse_into(&mut self.keymap.undo, value),
+            "key_redo" => parse_into(&mut self.keymap.redo, value),
+            "key_other_pane" => parse_into(&mut self.keymap.other_pane, value),
+            "key_close_pane" => parse_into(&mut self.keymap.close_pane, value),
+            "key_kill_line" => parse_into(&mut self.keymap.kill_line, value),
+            "key_kill_line_backward" => parse_into(&mut self.keymap.kill_line_backward, value),
+            "confirm_quit_always" => parse_into(&mut self.confirm_quit_always, value),
+            "search_ignore_case" => parse_into(&mut self.search_ignore_case, value),
+            "search_smart_case" => parse_into(&mut self.search_smart_case, value),
+            "visual_bell" => parse_into(&mut self.visual_bell, value),
+            "bell_on_backspace_at_start" => {
+                parse_into(&mut self.bell_on_backspace_at_start, value);
+            }
+            "show_last_saved" => parse_into(&mut self.show_last_saved, value),
+            "max_trailing_blank_lines" => {
+                if value == "keep" {
+                    self.max_trailing_blank_lines = None;
+                } else if let Ok(max) = value.parse() {
+                    self.max_trailing_blank_lines = Some(max);
+                }
+            }
+            "max_line_length" => {
+                if value == "off" {
+                    self.max_line_length = None;
+                } else if let Ok(max) = value.parse() {
+                    self.max_line_length = Some(max);
+                }
+            }
+            "auto_indent" => parse_into(&mut self.auto_indent, value),
+            "auto_indent_from_nearest" => parse_into(&mut self.auto_indent_from_nearest, value),
+            "smart_join" => parse_into(&mut self.smart_join, value),
+            "save_through_symlinks" => parse_into(&mut self.save_through_symlinks, value),
+            "auto_pair" => parse_into(&mut self.auto_pair, value),
+            "auto_pair_skip_after_word" => parse_into(&mut self.auto_pair_skip_after_word, value),
+            "auto_pair_skip_in_string" => parse_into(&mut self.auto_pair_skip_in_string, value),
+            "scroll_off" => parse_into(&mut self.scroll_off, value),
+            "undo_coalesce_max" => parse_into(&mut self.undo_coalesce_max, value),
+            "word_goal" => parse_into(&mut self.word_goal, value),
+            "large_file_bytes" => parse_into(&mut self.large_file_bytes, value),
+            "message_timeout_secs" => parse_into(&mut self.message_timeout_secs, value),
+            #[cfg(feature = "nfc")]
+            "normalize_on_open" => parse_into(&mut self.normalize_on_open, value),
+            #[cfg(feature = "nfc")]
+            "normalize_on_save" => parse_into(&mut self.normalize_on_save, value),
+            #[cfg(feature = "spell")]
+            "spell_dictionary" => parse_into(&mut self.spell_dictionary, value),
+            #[cfg(feature = "datetime")]
+            "date_format" => parse_into(&mut self.date_format, value),
+            "empty_row_marker" => parse_into(&mut self.empty_row_marker, value),
+            _ => (),
+        }
+    }
+}
+
+/// Overwrites the field only if the value can be parsed.
+fn parse_into<T: FromStr>(field: &mut T, value: &str) {
+    if let Ok(value) = value.parse() {
+        *field = value;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seeds_the_indentation_of_the_file() {
+        let mut config = Config::default();
+        config.seed_indent(Some(IndentStyle::Spaces(2)));
+        assert!(config.soft_tabs);
+        assert_eq!(config.tab_width, 2);
+        config.seed_indent(Some(IndentStyle::Tabs));
+        assert!(!config.soft_tabs);
+        assert_eq!(config.tab_width, 4);
+        config.seed_indent(Some(IndentStyle::Spaces(2)));
+        config.seed_indent(None);
+        assert!(!config.soft_tabs);
+        assert_eq!(config.tab_width, 4);
+    }
+
+    #[test]
+    fn indentation_set_in_the_config_file_wins() {
+        let mut config = Config::default();
+        config.set("soft_tabs", "true");
+        config.set("tab_width", "8");
+        config.seed_indent(Some(IndentStyle::Tabs));
+        assert!(config.soft_tabs);
+        config.seed_indent(Some(IndentStyle::Spaces(2)));
+        assert_eq!(config.tab_width, 8);
+    }
+
+    #[test]
+    fn empty_row_marker_may_be_set_to_nothing() {
+        let mut config = Config::default();
+        assert_eq!(config.empty_row_marker, "~");
+        config.set("empty_row_marker", "");
+        assert_eq!(config.empty_row_marker, "");
+    }
+
+    #[test]
+    fn max_line_length_may_be_turned_off() {
+        let mut config = Config::default();
+        config.set("max_line_length", "80");
+        assert_eq!(config.max_line_length, Some(80));
+        config.set("max_line_length", "long");
+        assert_eq!(config.max_line_length, Some(80));
+        config.set("max_line_length", "off");
+        assert_eq!(config.max_line_length, None);
+    }
+}
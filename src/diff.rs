@@ -0,0 +1,165 @@
+use crate::Row;
+use core::cmp;
+use core::fmt;
+use core::mem;
+use std::collections::HashMap;
+
+/// The most cells of the table of the longest common subsequence computed, past which the rows are
+/// matched regardless of their order, since the table takes the product of the row counts in time.
+const MAX_TABLE_CELLS: usize = 10_000_000;
+
+/// The numbers of lines added and removed to turn one text into another.
+#[derive(Default, PartialEq, Eq)]
+pub struct DiffSummary {
+    pub added: usize,
+    pub removed: usize,
+}
+
+impl DiffSummary {
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.added == 0 && self.removed == 0
+    }
+}
+
+impl fmt::Display for DiffSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "+{} -{}", self.added, self.removed)
+    }
+}
+
+/// Compares the rows of `a` with those of `b` by their text, counting the rows of `b` not in their
+/// longest common subsequence as added and those of `a` as removed. If too many rows differ for
+/// that, the rows are matched regardless of their order instead, so moved rows aren't counted.
+#[must_use]
+pub fn diff(a: &[Row], b: &[Row]) -> DiffSummary {
+    // The common ends are left out, as they usually make up most of the rows.
+    let prefix = a
+        .iter()
+        .zip(b)
+        .take_while(|(a, b)| a.as_str() == b.as_str())
+        .count();
+    let a = a.get(prefix..).unwrap_or_default();
+    let b = b.get(prefix..).unwrap_or_default();
+    let suffix = a
+        .iter()
+        .rev()
+        .zip(b.iter().rev())
+        .take_while(|(a, b)| a.as_str() == b.as_str())
+        .count();
+    let a = a.get(..a.len().saturating_sub(suffix)).unwrap_or_default();
+    let b = b.get(..b.len().saturating_sub(suffix)).unwrap_or_default();
+    let common = if a.len().saturating_mul(b.len()) > MAX_TABLE_CELLS {
+        common_row_count(a, b)
+    } else {
+        longest_common_subsequence(a, b)
+    };
+    DiffSummary {
+        added: b.len().saturating_sub(common),
+        removed: a.len().saturating_sub(common),
+    }
+}
+
+/// The length of the longest common subsequence, computed a row of the table at a time.
+fn longest_common_subsequence(a: &[Row], b: &[Row]) -> usize {
+    let mut previous = vec![0usize; b.len().saturating_add(1)];
+    let mut current = previous.clone();
+    for row_a in a {
+        for (j, row_b) in b.iter().enumerate() {
+            let next = j.saturating_add(1);
+            let length = if row_a.as_str() == row_b.as_str() {
+                previous
+                    .get(j)
+                    .copied()
+                    .unwrap_or_default()
+                    .saturating_add(1)
+            } else {
+                cmp::max(
+                    previous.get(next).copied().unwrap_or_default(),
+                    current.get(j).copied().unwrap_or_default(),
+                )
+            };
+            if let Some(cell) = current.get_mut(next) {
+                *cell = length;
+            }
+        }
+        mem::swap(&mut previous, &mut current);
+    }
+    previous.last().copied().unwrap_or_default()
+}
+
+/// The number of rows in both, each matched once, regardless of their order.
+fn common_row_count(a: &[Row], b: &[Row]) -> usize {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for row in a {
+        let count = counts.entry(row.as_str()).or_default();
+        *count = count.saturating_add(1);
+    }
+    let mut common = 0usize;
+    for row in b {
+        if let Some(count) = counts.get_mut(row.as_str()).filter(|count| **count > 0) {
+            *count = count.saturating_sub(1);
+            common = common.saturating_add(1);
+        }
+    }
+    common
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(text: &str) -> Vec<Row> {
+        text.lines().map(Row::from).collect()
+    }
+
+    #[test]
+    fn counts_the_added_and_removed_lines() {
+        let summary = diff(&rows("a\nb\nc\nd"), &rows("a\nx\nc\nd\ne"));
+        assert!(
+            summary
+                == DiffSummary {
+                    added: 2,
+                    removed: 1
+                }
+        );
+        assert_eq!(summary.to_string(), "+2 -1");
+    }
+
+    #[test]
+    fn same_rows_make_an_empty_summary() {
+        assert!(diff(&rows("a\nb"), &rows("a\nb")).is_empty());
+        assert!(diff(&[], &[]).is_empty());
+    }
+
+    #[test]
+    fn moved_rows_are_removed_and_added() {
+        let summary = diff(&rows("a\nb\nc"), &rows("c\na\nb"));
+        assert!(
+            summary
+                == DiffSummary {
+                    added: 1,
+                    removed: 1
+                }
+        );
+    }
+
+    #[test]
+    fn matches_the_rows_of_large_changes_regardless_of_order() {
+        let a: Vec<Row> = (0..5000usize)
+            .map(|n| Row::from(n.to_string().as_str()))
+            .collect();
+        let mut b: Vec<Row> = (0..5000usize)
+            .rev()
+            .map(|n| Row::from(n.to_string().as_str()))
+            .collect();
+        b.push(Row::from("new"));
+        assert!(
+            diff(&a, &b)
+                == DiffSummary {
+                    added: 1,
+                    removed: 0
+                }
+        );
+    }
+}
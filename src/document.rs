@@ -1,36 +1,313 @@
 use crate::row;
+#[cfg(feature = "spell")]
+use crate::Dictionary;
+use crate::Edit;
+use crate::EditOutcome;
+use crate::EditorError;
 use crate::FileType;
+use crate::History;
+use crate::IndentStyle;
 use crate::Position;
 use crate::Row;
+use crate::SearchOptions;
+use core::cell::Cell;
+use core::cmp;
+use core::fmt;
+#[cfg(feature = "spell")]
+use core::ops::Range;
+use core::ops::RangeInclusive;
+use core::str::FromStr;
+#[cfg(feature = "encoding")]
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+use std::env;
 use std::fs;
-use std::io::{Error, Write};
+use std::io::{self, ErrorKind, Write};
+#[cfg(unix)]
+use std::os::unix::fs::{fchown, MetadataExt, OpenOptionsExt};
+use std::path::{Component, Path, PathBuf};
+use std::process;
+use std::time::Instant;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// The line terminator of a file, which is preserved on save.
+#[derive(Default, PartialEq, Clone, Copy)]
+pub enum LineEnding {
+    /// `\n`, as on Unix.
+    #[default]
+    Lf,
+    /// `\r\n`, as on Windows.
+    CrLf,
+    /// `\r`, as on classic Mac OS.
+    Cr,
+}
+
+impl LineEnding {
+    /// Detects the line ending by the first line terminator; `Lf` if there's none.
+    #[must_use]
+    pub fn detect(content: &str) -> Self {
+        match content
+            .find(['\n', '\r'])
+            .map(|index| content.split_at(index).1)
+        {
+            Some(rest) if rest.starts_with("\r\n") => Self::CrLf,
+            Some(rest) if rest.starts_with('\r') => Self::Cr,
+            _ => Self::Lf,
+        }
+    }
+
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Lf => "\n",
+            Self::CrLf => "\r\n",
+            Self::Cr => "\r",
+        }
+    }
+
+    /// The name of the line ending, e.g., `CRLF`.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Lf => "LF",
+            Self::CrLf => "CRLF",
+            Self::Cr => "CR",
+        }
+    }
+}
+
+impl FromStr for LineEnding {
+    type Err = ();
+
+    /// Parses the name of the line ending, case-insensitively.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "lf" => Ok(Self::Lf),
+            "crlf" => Ok(Self::CrLf),
+            "cr" => Ok(Self::Cr),
+            _ => Err(()),
+        }
+    }
+}
+
+/// How a document is written to its file.
+pub struct SaveOptions {
+    /// Ends the last line with a newline.
+    pub final_newline: bool,
+    /// Writes to the file a symbolic link points to, keeping the link, instead of replacing the
+    /// link with a regular file.
+    pub through_symlinks: bool,
+}
+
+impl Default for SaveOptions {
+    fn default() -> Self {
+        Self {
+            final_newline: true,
+            through_symlinks: true,
+        }
+    }
+}
+
+/// A case to convert text to.
+#[derive(Clone, Copy)]
+pub enum Case {
+    Upper,
+    Lower,
+    /// The first letter of each word in uppercase, the others in lowercase.
+    Title,
+}
+
+impl Case {
+    /// Converts the text, which may change its length, e.g., `ß` is `SS` in uppercase.
+    #[must_use]
+    pub fn convert(self, text: &str) -> String {
+        match self {
+            Self::Upper => text.to_uppercase(),
+            // Converting the whole text, rather than a character at a time, lowercases a final
+            // sigma as such.
+            Self::Lower => text.to_lowercase(),
+            Self::Title => {
+                let mut converted = String::with_capacity(text.len());
+                let mut rest = text;
+                while let Some(start) = rest.find(char::is_alphanumeric) {
+                    let (before, word) = rest.split_at(start);
+                    converted.push_str(before);
+                    // An apostrophe inside a word, e.g., `don't`, doesn't start another one.
+                    let end = word
+                        .find(|c: char| !c.is_alphanumeric() && c != '\'')
+                        .unwrap_or(word.len());
+                    let (word, after) = word.split_at(end);
+                    let mut chars = word.chars();
+                    converted.extend(chars.next().into_iter().flat_map(char::to_uppercase));
+                    converted.push_str(&chars.as_str().to_lowercase());
+                    rest = after;
+                }
+                converted.push_str(rest);
+                converted
+            }
+        }
+    }
+}
 
 #[derive(Default)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Document {
     rows: Vec<Row>,
-    pub filename: Option<String>,
+    filename: Option<String>,
     /// Whether the document has been modified since the last save.
     is_dirty: bool,
     file_type: FileType,
+    /// Whether the text has been changed by Unicode normalization.
+    is_normalized: bool,
+    /// Whether the file is too large for the passes over every grapheme, e.g., highlighting.
+    is_large: bool,
+    /// Whether the opened file doesn't end with a newline. Saving always adds one.
+    is_missing_final_newline: bool,
+    line_ending: LineEnding,
+    /// When the document was last saved in this session.
+    last_saved: Option<Instant>,
+    history: History,
+    /// The number of changes to the text so far, which tells whether what's computed from the text
+    /// is outdated.
+    revision: u64,
+    /// The word count as of a revision, since counting takes a pass over the whole text.
+    word_count: Cell<Option<(u64, usize)>>,
+    /// The encoding the file is transcoded from on open and back to on save; `None` for UTF-8.
+    #[cfg(feature = "encoding")]
+    encoding: Option<&'static Encoding>,
+    /// Whether the file starts with a byte order mark, which is written back on save.
+    #[cfg(feature = "encoding")]
+    has_bom: bool,
 }
 
 impl Document {
+    /// Opens the file, which the document is named after as normalized by `set_filename`, so that
+    /// the same file is named the same however its path is spelled.
     /// # Errors
     /// Returns an error if the file can't be read.
-    pub fn open(filename: &str) -> Result<Self, Error> {
-        let content = fs::read_to_string(filename)?;
+    pub fn open(filename: &str) -> Result<Self, EditorError> {
+        let filename = normalize_path(filename);
+        let content = fs::read_to_string(&filename)?;
+        Ok(Self::from_content(&filename, &content))
+    }
+
+    /// Opens the file in the encoding named by the label, e.g., `latin1`, transcoding it to UTF-8.
+    /// A byte order mark takes precedence over the label; without either, the file is UTF-8. The
+    /// filename is normalized as by `open`.
+    /// # Errors
+    /// Returns an error if the file can't be read, the label names no known encoding, or the file
+    /// contains sequences that are invalid in the encoding.
+    #[cfg(feature = "encoding")]
+    pub fn open_with_encoding(filename: &str, label: Option<&str>) -> Result<Self, EditorError> {
+        let encoding = match label {
+            Some(label) => Encoding::for_label(label.as_bytes())
+                .ok_or_else(|| EditorError::InvalidInput(format!("unknown encoding: {label}")))?,
+            None => UTF_8,
+        };
+        let filename = normalize_path(filename);
+        let bytes = fs::read(&filename)?;
+        let (encoding, bom_len) = Encoding::for_bom(&bytes).unwrap_or((encoding, 0));
+        let (content, had_errors) =
+            encoding.decode_without_bom_handling(bytes.get(bom_len..).unwrap_or_default());
+        if had_errors {
+            return Err(EditorError::InvalidInput(format!(
+                "invalid {} sequence",
+                encoding.name()
+            )));
+        }
+        let mut doc = Self::from_content(&filename, &content);
+        doc.encoding = (encoding != UTF_8).then_some(encoding);
+        doc.has_bom = bom_len > 0;
+        Ok(doc)
+    }
+
+    fn from_content(filename: &str, content: &str) -> Self {
+        // An empty file has no last line to terminate.
+        let is_missing_final_newline = !content.is_empty() && !content.ends_with(['\n', '\r']);
         let file_type = FileType::from(filename);
         let mut rows = Vec::new();
-        for value in content.lines() {
+        for value in split_lines(content) {
             let row = Row::from(value);
             rows.push(row);
         }
-        Ok(Self {
+        Self {
             rows,
             filename: Some(filename.to_owned()),
-            is_dirty: false,
             file_type,
-        })
+            is_missing_final_newline,
+            line_ending: LineEnding::detect(content),
+            ..Self::default()
+        }
+    }
+
+    /// The indentation of the document: as detected from its rows, or else the default of its
+    /// file type. `None` if neither tells.
+    #[must_use]
+    pub fn indent_style(&self) -> Option<IndentStyle> {
+        self.detect_indent().or_else(|| self.file_type.indent())
+    }
+
+    /// Whether more rows are indented with tabs or with spaces; the width of the spaces is the
+    /// least among the rows. `None` if there's a tie, e.g., no indented rows at all.
+    fn detect_indent(&self) -> Option<IndentStyle> {
+        let mut tab_rows = 0usize;
+        let mut space_rows = 0usize;
+        let mut width = usize::MAX;
+        for row in self.rows.iter().filter(|row| !row.is_blank()) {
+            let text = row.as_str();
+            let spaces = text
+                .len()
+                .saturating_sub(text.trim_start_matches(' ').len());
+            if text.starts_with('\t') {
+                tab_rows = tab_rows.saturating_add(1);
+            } else if spaces >= 2 {
+                // A single space is rather an alignment, e.g., in a block comment.
+                space_rows = space_rows.saturating_add(1);
+                width = cmp::min(width, spaces);
+            }
+        }
+        match tab_rows.cmp(&space_rows) {
+            cmp::Ordering::Greater => Some(IndentStyle::Tabs),
+            cmp::Ordering::Less => Some(IndentStyle::Spaces(width)),
+            cmp::Ordering::Equal => None,
+        }
+    }
+
+    /// Whether the document is prose rather than code, e.g., plain text or Markdown.
+    #[must_use]
+    pub fn is_prose(&self) -> bool {
+        self.file_type.is_prose()
+    }
+
+    #[must_use]
+    pub fn filename(&self) -> Option<&str> {
+        self.filename.as_deref()
+    }
+
+    /// Names the document after the path, e.g., on save as, trimmed, with a leading `~` expanded
+    /// to the home directory, and with the `.` and `..` components resolved. The file type is
+    /// detected anew from it.
+    pub fn set_filename(&mut self, path: &str) {
+        let filename = normalize_path(path);
+        self.file_type = FileType::from(&filename);
+        self.filename = Some(filename);
+    }
+
+    /// Empties the document into an unnamed one without changes, as if no file was opened.
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    #[must_use]
+    pub fn line_ending(&self) -> LineEnding {
+        self.line_ending
+    }
+
+    /// Converts the line endings, which are written by the next save.
+    pub fn set_line_ending(&mut self, line_ending: LineEnding) {
+        if self.line_ending != line_ending {
+            self.line_ending = line_ending;
+            self.is_dirty = true;
+        }
     }
 
     #[must_use]
@@ -43,112 +320,880 @@ impl Document {
         self.rows.get(index)
     }
 
-    /// Whether the document is empty or no documents have been loaded.
+    /// The number of graphemes of the row; 0 if there's no such row, e.g., the row below the last
+    /// one, where the cursor can be.
+    #[must_use]
+    pub fn row_len(&self, y: usize) -> usize {
+        self.rows.get(y).map_or(0, Row::len)
+    }
+
+    /// The rows from `start` until `end` (exclusive), clamped to the document; empty if `start`
+    /// is past the end.
+    #[must_use]
+    pub fn rows_in_range(&self, start: usize, end: usize) -> &[Row] {
+        let end = cmp::min(end, self.len());
+        self.rows.get(start..end).unwrap_or_default()
+    }
+
+    /// The text from `start` until `end`, exclusive, with a `\n` between rows.
+    #[must_use]
+    pub fn text_in_range(&self, start: &Position, end: &Position) -> String {
+        let mut lines = Vec::new();
+        for (row, y) in self
+            .rows_in_range(start.y, end.y.saturating_add(1))
+            .iter()
+            .zip(start.y..)
+        {
+            let from = if y == start.y { start.x } else { 0 };
+            let to = if y == end.y { end.x } else { row.len() };
+            lines.push(row.slice(from, to));
+        }
+        lines.join("\n")
+    }
+
+    /// The numbers of graphemes, not counting line breaks, and of rows from `start` until `end`,
+    /// exclusive, in either order, clamped to the document.
+    #[must_use]
+    pub fn count_in_range(&self, start: &Position, end: &Position) -> (usize, usize) {
+        let (start, end) = if (end.y, end.x) < (start.y, start.x) {
+            (end, start)
+        } else {
+            (start, end)
+        };
+        let rows = self.rows_in_range(start.y, end.y.saturating_add(1));
+        let chars = (start.y..)
+            .zip(rows)
+            .map(|(y, row)| {
+                let from = if y == start.y { start.x } else { 0 };
+                let to = if y == end.y { end.x } else { row.len() };
+                cmp::min(to, row.len()).saturating_sub(from)
+            })
+            .sum();
+        (chars, rows.len())
+    }
+
+    /// The text of the rows, without line endings.
+    pub fn lines(&self) -> impl Iterator<Item = &str> {
+        self.rows.iter().map(Row::as_str)
+    }
+
+    /// The grapheme at the position, or `None` if there's no such row or the position is past the
+    /// end of the row.
+    #[must_use]
+    pub fn grapheme_at(&self, at: &Position) -> Option<&str> {
+        self.rows.get(at.y)?.grapheme_at(at.x)
+    }
+
+    /// The row at `y` with tabs expanded and control characters in caret notation, as it appears
+    /// with tab stops every `tab_width` columns.
+    #[must_use]
+    pub fn rendered_line(&self, y: usize, tab_width: usize) -> Option<String> {
+        self.rows.get(y).map(|row| row.expand_tabs(tab_width))
+    }
+
+    /// The visual width of the widest row, with tabs expanded and wide characters taking two columns.
+    #[must_use]
+    pub fn max_visual_width(&self, tab_width: usize) -> usize {
+        // NOTE: Recomputed on every call; cache it if this becomes a bottleneck.
+        self.rows
+            .iter()
+            .map(|row| row.column_of_grapheme(row.len(), tab_width))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Whether the document has no rows, or no documents have been loaded.
+    /// A document with a single empty row, e.g., after deleting everything, isn't empty; see
+    /// `has_content`.
     #[must_use]
     pub fn is_empty(&self) -> bool {
         self.rows.is_empty()
     }
 
+    /// Whether any row has text; `false` for no rows as well as only empty rows.
+    #[must_use]
+    pub fn has_content(&self) -> bool {
+        self.rows.iter().any(|row| !row.is_empty())
+    }
+
     #[must_use]
     pub fn len(&self) -> usize {
         self.rows.len()
     }
 
-    /// # Panics
-    /// Panics if trying to insert pass the end of the row.
+    /// The number of words, as Unicode defines them, e.g., `don't` is one word and `3.5` is another.
+    #[must_use]
+    pub fn word_count(&self) -> usize {
+        if let Some((revision, count)) = self.word_count.get() {
+            if revision == self.revision {
+                return count;
+            }
+        }
+        let count = self
+            .rows
+            .iter()
+            .map(|row| row.as_str().unicode_words().count())
+            .sum();
+        self.word_count.set(Some((self.revision, count)));
+        count
+    }
+
+    /// The number of digits of the line count, e.g., for the width of line numbers; 1 for an
+    /// empty document, as if it had a line.
+    #[must_use]
+    pub fn line_count_digits(&self) -> usize {
+        cmp::max(self.len(), 1).to_string().len()
+    }
+
+    /// Where the cursor goes after inserting the string at the position: after its last line,
+    /// which is on a row of its own if the string has a `\n`.
+    #[must_use]
+    pub fn position_after(at: &Position, s: &str) -> Position {
+        match s.rsplit_once('\n') {
+            Some((before_last_line, last_line)) => Position {
+                x: last_line.graphemes(true).count(),
+                y: at
+                    .y
+                    .saturating_add(before_last_line.matches('\n').count())
+                    .saturating_add(1),
+            },
+            None => Position {
+                x: at.x.saturating_add(s.graphemes(true).count()),
+                y: at.y,
+            },
+        }
+    }
+
     pub fn insert(&mut self, at: &Position, c: char) {
-        if at.y > self.len() {
-            return;
+        self.apply(Edit::InsertChar { at: at.clone(), c });
+    }
+
+    /// Inserts the string at the position as a whole, with each `\n` starting a new row.
+    pub fn insert_str(&mut self, at: &Position, s: &str) {
+        self.apply(Edit::InsertStr {
+            at: at.clone(),
+            text: s.to_owned(),
+        });
+    }
+
+    /// Deletes the grapheme at the position; at the end of a row, the next row is moved up.
+    pub fn delete(&mut self, at: &Position) {
+        self.apply(Edit::DeleteChar { at: at.clone() });
+    }
+
+    /// Deletes the text from `start` until `end`, exclusive, returning it; with each line break as
+    /// `\n`. Nothing is deleted out of the document.
+    pub fn delete_range(&mut self, start: Position, end: Position) -> String {
+        match self.apply(Edit::DeleteRange { start, end }).inverse {
+            Edit::InsertStr { text, .. } => text,
+            _ => String::new(),
+        }
+    }
+
+    /// Applies the edit, returning the edit that undoes it, which is also recorded for `undo`.
+    /// Edits out of the document change nothing, with an inverse that changes nothing either.
+    pub fn apply(&mut self, edit: Edit) -> EditOutcome {
+        let outcome = self.perform(edit);
+        if !outcome.inverse.is_none() {
+            self.history.record(outcome.inverse.clone());
+        }
+        outcome
+    }
+
+    /// Undoes the last undo step, returning where the cursor goes; `None` if there's nothing to
+    /// undo.
+    pub fn undo(&mut self) -> Option<Position> {
+        let step = self.history.pop_undo()?;
+        let (redo_step, end) = self.perform_step(step);
+        self.history.push_redo(redo_step);
+        Some(end)
+    }
+
+    /// Redoes the last undone step, returning where the cursor goes; `None` if there's nothing to
+    /// redo.
+    pub fn redo(&mut self) -> Option<Position> {
+        let step = self.history.pop_redo()?;
+        let (undo_step, end) = self.perform_step(step);
+        self.history.push_undo(undo_step);
+        Some(end)
+    }
+
+    /// Makes the next edit start a new undo step, instead of being undone along with the previous
+    /// edits.
+    pub fn end_undo_step(&mut self) {
+        self.history.end_step();
+    }
+
+    /// Performs the inverse edits of a step in reverse order, returning the step that reverts it
+    /// and where the cursor goes.
+    fn perform_step(&mut self, step: Vec<Edit>) -> (Vec<Edit>, Position) {
+        let mut inverses = Vec::new();
+        let mut end = Position::default();
+        for edit in step.into_iter().rev() {
+            let outcome = self.perform(edit);
+            inverses.push(outcome.inverse);
+            end = outcome.end;
+        }
+        (inverses, end)
+    }
+
+    fn perform(&mut self, edit: Edit) -> EditOutcome {
+        self.revision = self.revision.wrapping_add(1);
+        match edit {
+            Edit::InsertChar { at, c: '\n' } => self.perform(Edit::InsertNewline { at }),
+            Edit::InsertChar { at, c } => self.perform(Edit::InsertStr {
+                at,
+                text: c.to_string(),
+            }),
+            // NOTE: Navigating to one row below the last is allowed.
+            Edit::InsertNewline { at } if at.y == self.len() => self.perform(Edit::InsertRow {
+                y: at.y,
+                text: String::new(),
+            }),
+            Edit::InsertNewline { at } => self.perform(Edit::InsertStr {
+                at,
+                text: "\n".to_owned(),
+            }),
+            Edit::DeleteChar { at } => {
+                let row_len = self.row_len(at.y);
+                if at.x >= row_len {
+                    self.perform(Edit::JoinLines { y: at.y })
+                } else {
+                    let end = Position {
+                        x: at.x.saturating_add(1),
+                        y: at.y,
+                    };
+                    self.perform(Edit::DeleteRange { start: at, end })
+                }
+            }
+            Edit::JoinLines { y } if self.is_not_last_row(y) => {
+                let start = Position {
+                    x: self.row_len(y),
+                    y,
+                };
+                let end = Position {
+                    x: 0,
+                    y: y.saturating_add(1),
+                };
+                self.perform(Edit::DeleteRange { start, end })
+            }
+            Edit::InsertStr { at, text } => self.insert_text(at, &text),
+            Edit::DeleteRange { start, end } => self.remove_range(start, end),
+            Edit::InsertRow { y, text } if y <= self.len() => {
+                self.rows.insert(y, Row::from(text.as_str()));
+                self.is_dirty = true;
+                EditOutcome {
+                    inverse: Edit::RemoveRow { y },
+                    end: Position { x: 0, y },
+                }
+            }
+            Edit::RemoveRow { y } if y < self.len() => {
+                let row = self.rows.remove(y);
+                self.is_dirty = true;
+                EditOutcome {
+                    inverse: Edit::InsertRow {
+                        y,
+                        text: row.as_str().to_owned(),
+                    },
+                    end: Position { x: 0, y },
+                }
+            }
+            Edit::Batch(edits) => {
+                let mut inverses = Vec::new();
+                let mut end = Position::default();
+                for edit in edits {
+                    let outcome = self.perform(edit);
+                    inverses.push(outcome.inverse);
+                    end = outcome.end;
+                }
+                // Undone in the reverse order.
+                inverses.reverse();
+                EditOutcome {
+                    inverse: Edit::Batch(inverses),
+                    end,
+                }
+            }
+            Edit::JoinLines { y } | Edit::RemoveRow { y } | Edit::InsertRow { y, .. } => {
+                EditOutcome {
+                    inverse: Edit::none(),
+                    end: Position { x: 0, y },
+                }
+            }
+        }
+    }
+
+    /// Inserts the text at the position; past the last row, a new row is added for it.
+    #[allow(clippy::indexing_slicing, clippy::arithmetic_side_effects)]
+    fn insert_text(&mut self, at: Position, text: &str) -> EditOutcome {
+        if at.y > self.len() || text.is_empty() {
+            return EditOutcome {
+                inverse: Edit::none(),
+                end: at,
+            };
         }
         self.is_dirty = true;
-        if c == '\n' {
-            self.insert_newline(at);
-            return;
+        let is_new_row = at.y == self.len();
+        if is_new_row {
+            self.rows.push(Row::default());
+        }
+        // The part after the position goes to the end of the last inserted line.
+        let remainder = self.rows[at.y].split(at.x);
+        let mut lines = text.split('\n');
+        if let Some(first_line) = lines.next() {
+            self.rows[at.y].append(&Row::from(first_line));
         }
-        // If adding to the end of the file, push a new row with such
-        // character as the first character; otherwise, take that row
-        // and insert to the corresponding position.
-        if at.y == self.len() {
-            let mut row = Row::default();
-            row.insert(0, c);
-            self.rows.push(row);
+        let mut y = at.y;
+        for line in lines {
+            y += 1;
+            self.rows.insert(y, Row::from(line));
+        }
+        let end = Position {
+            x: self.rows[y].len(),
+            y,
+        };
+        self.rows[y].append(&remainder);
+        let delete = Edit::DeleteRange {
+            start: at.clone(),
+            end: end.clone(),
+        };
+        let inverse = if is_new_row {
+            // Deleting the text leaves the added row empty.
+            Edit::Batch(vec![delete, Edit::RemoveRow { y: at.y }])
         } else {
-            #[allow(clippy::indexing_slicing)]
-            let row = &mut self.rows[at.y];
-            row.insert(at.x, c);
+            delete
+        };
+        EditOutcome { inverse, end }
+    }
+
+    /// Deletes the text from `start` until `end`, which are clamped into the document.
+    #[allow(clippy::indexing_slicing)]
+    fn remove_range(&mut self, start: Position, end: Position) -> EditOutcome {
+        let Some(last_y) = self.len().checked_sub(1) else {
+            return EditOutcome {
+                inverse: Edit::none(),
+                end: start,
+            };
+        };
+        let clamp = |pos: Position, rows: &[Row]| {
+            if pos.y > last_y {
+                Position {
+                    x: rows[last_y].len(),
+                    y: last_y,
+                }
+            } else {
+                Position {
+                    x: cmp::min(pos.x, rows[pos.y].len()),
+                    y: pos.y,
+                }
+            }
+        };
+        let start = clamp(start, &self.rows);
+        let end = clamp(end, &self.rows);
+        if (end.y, end.x) <= (start.y, start.x) {
+            return EditOutcome {
+                inverse: Edit::none(),
+                end: start,
+            };
+        }
+        self.is_dirty = true;
+        // The rows are cut at both ends; what's between the cuts is the deleted text.
+        let remainder = self.rows[end.y].split(end.x);
+        let mut deleted = self.rows[start.y].split(start.x).as_str().to_owned();
+        for row in self.rows.drain(start.y.saturating_add(1)..=end.y) {
+            deleted.push('\n');
+            deleted.push_str(row.as_str());
+        }
+        self.rows[start.y].append(&remainder);
+        EditOutcome {
+            inverse: Edit::InsertStr {
+                at: start.clone(),
+                text: deleted,
+            },
+            end: start,
         }
     }
 
-    /// # Notes
-    /// The dirty flag is not touched.
-    fn insert_newline(&mut self, at: &Position) {
-        if at.y > self.len() {
-            return;
+    /// The text of the rows, each ending with the line ending of the document, as they'd be saved.
+    #[must_use]
+    pub fn selection_text(&self, rows: RangeInclusive<usize>) -> String {
+        let mut text = String::new();
+        for row in self.rows.get(rows).unwrap_or_default() {
+            text.push_str(row.as_str());
+            text.push_str(self.line_ending.as_str());
         }
-        // NOTE: Navigating to one row below the last is allowed.
-        if at.y == self.len() {
-            self.rows.push(Row::default());
-            return;
+        text
+    }
+
+    /// Inserts a copy of the rows right after them, e.g., rows `1..=2` are copied to `3..=4`.
+    /// Returns whether they were copied, which they aren't if any is past the end.
+    pub fn duplicate_rows(&mut self, rows: RangeInclusive<usize>) -> bool {
+        let Some(copies) = self.rows.get(rows.clone()) else {
+            return false;
+        };
+        // Inserted from the end of the last row, which works for the last row of the document too.
+        let mut text = String::new();
+        for row in copies {
+            text.push('\n');
+            text.push_str(row.as_str());
         }
-        // This works even at the end of a line, with `new_row` being empty.
-        #[allow(clippy::indexing_slicing)]
-        let curr_row = &mut self.rows[at.y];
-        let new_row = curr_row.split(at.x);
-        #[allow(clippy::arithmetic_side_effects)]
-        self.rows.insert(at.y + 1, new_row);
+        let at = Position {
+            x: copies.last().map_or(0, Row::len),
+            y: *rows.end(),
+        };
+        self.apply(Edit::InsertStr { at, text });
+        true
     }
 
-    /// # Panics
-    /// Panics if trying to delete pass the end of the row.
-    #[allow(clippy::indexing_slicing, clippy::arithmetic_side_effects)]
-    pub fn delete(&mut self, at: &Position) {
-        if at.y >= self.len() {
-            return;
+    /// Sorts the rows from `start` to `end`, inclusive, by their text; in descending order if
+    /// `reverse`. Returns whether any row moved.
+    pub fn sort_lines(&mut self, start: usize, end: usize, reverse: bool) -> bool {
+        let end = cmp::min(end, self.len().saturating_sub(1));
+        let Some(rows) = self.rows.get(start..=end) else {
+            return false;
+        };
+        let mut texts: Vec<String> = rows.iter().map(|row| row.as_str().to_owned()).collect();
+        if reverse {
+            texts.sort_by(|a, b| b.cmp(a));
+        } else {
+            texts.sort();
+        }
+        if rows
+            .iter()
+            .map(Row::as_str)
+            .eq(texts.iter().map(String::as_str))
+        {
+            return false;
+        }
+        self.replace_rows(start..=end, texts);
+        true
+    }
+
+    /// Replaces the rows, clamped to the document, with rows of the texts, which may be more or
+    /// fewer. Done row by row, so that undoing restores the rows as they were.
+    pub fn replace_rows(&mut self, rows: RangeInclusive<usize>, texts: Vec<String>) {
+        let (start, end) = rows.into_inner();
+        let end = cmp::min(end, self.len().saturating_sub(1));
+        let mut edits: Vec<Edit> = (start..=end)
+            .map(|_| Edit::RemoveRow { y: start })
+            .collect();
+        edits.extend(
+            (start..)
+                .zip(texts)
+                .map(|(y, text)| Edit::InsertRow { y, text }),
+        );
+        self.apply(Edit::Batch(edits));
+    }
+
+    /// Replaces the text from `start` until `end`, exclusive, with the text, undone as a single
+    /// edit.
+    pub fn replace_range(&mut self, start: Position, end: Position, text: String) -> EditOutcome {
+        if start.y == end.y && !text.contains('\n') {
+            if let Some(outcome) = self.replace_in_row(&start, end.x, &text) {
+                return outcome;
+            }
         }
+        self.apply(Edit::Batch(vec![
+            Edit::DeleteRange {
+                start: start.clone(),
+                end,
+            },
+            Edit::InsertStr { at: start, text },
+        ]))
+    }
+
+    /// Replaces the text of the row from `start` until column `end_x`, clamped to the row, in
+    /// place rather than by splitting and joining the row; `None` if there's no such row.
+    fn replace_in_row(
+        &mut self,
+        start: &Position,
+        end_x: usize,
+        text: &str,
+    ) -> Option<EditOutcome> {
+        self.revision = self.revision.wrapping_add(1);
+        let row = self.rows.get_mut(start.y)?;
+        let len = row.len();
+        let start_x = cmp::min(start.x, len);
+        let end_x = end_x.clamp(start_x, len);
+        let replaced = row.slice(start_x, end_x).to_owned();
+        if replaced.is_empty() && text.is_empty() {
+            return Some(EditOutcome {
+                inverse: Edit::none(),
+                end: start.clone(),
+            });
+        }
+        row.replace_range(start_x, end_x, text);
+        let start = Position {
+            x: start_x,
+            y: start.y,
+        };
+        // The rest of the row keeps its length, so the replacement ends where it begins.
+        let end = Position {
+            x: row.len().saturating_sub(len.saturating_sub(end_x)),
+            y: start.y,
+        };
         self.is_dirty = true;
-        // If deleting at the end of the row, the next row is moved up.
-        if at.x == self.rows[at.y].len()
-        // not last row
-        && at.y + 1 < self.len()
+        let inverse = Edit::Batch(vec![
+            Edit::DeleteRange {
+                start: start.clone(),
+                end: end.clone(),
+            },
+            Edit::InsertStr {
+                at: start,
+                text: replaced,
+            },
+        ]);
+        self.history.record(inverse.clone());
+        Some(EditOutcome { inverse, end })
+    }
+
+    /// Comments out the rows with the line comment token of the file type, placed after the
+    /// indentation; if all non-blank rows are already commented, uncomments them instead.
+    /// Blank rows are left as they are, and rows past the end ignored. Returns `false` if nothing
+    /// was toggled, i.e., if the file type has no line comments or none of the rows exist.
+    pub fn toggle_comment(&mut self, rows: RangeInclusive<usize>) -> bool {
+        let Some(prefix) = self.file_type.comment_prefix() else {
+            return false;
+        };
+        let rows = *rows.start()..=cmp::min(*rows.end(), self.len().saturating_sub(1));
+        let Some(texts) = self
+            .rows
+            .get(rows.clone())
+            .filter(|texts| !texts.is_empty())
+        else {
+            return false;
+        };
+        let is_commented = texts.iter().filter(|row| !row.is_blank()).all(|row| {
+            row.as_str()
+                .get(row.leading_whitespace().len()..)
+                .is_some_and(|content| content.starts_with(prefix))
+        });
+        for y in rows {
+            let Some(row) = self.rows.get(y).filter(|row| !row.is_blank()) else {
+                continue;
+            };
+            // The indentation is spaces and tabs, of a byte and a grapheme each.
+            let indent_len = row.leading_whitespace().len();
+            let content = row.as_str().get(indent_len..).unwrap_or_default();
+            let start = Position { x: indent_len, y };
+            if is_commented {
+                let uncommented = content.strip_prefix(prefix).unwrap_or(content);
+                // Also removes the space put after the token by commenting.
+                let removed = content
+                    .len()
+                    .saturating_sub(uncommented.strip_prefix(' ').unwrap_or(uncommented).len());
+                let end = Position {
+                    x: indent_len.saturating_add(removed),
+                    y,
+                };
+                self.apply(Edit::DeleteRange { start, end });
+            } else {
+                self.apply(Edit::InsertStr {
+                    at: start,
+                    text: format!("{prefix} "),
+                });
+            }
+        }
+        true
+    }
+
+    /// Prepends one unit of indentation, e.g., a tab or a tab width of spaces, to the row.
+    pub fn indent_line(&mut self, y: usize, indent: &str) {
+        if y < self.len() {
+            self.apply(Edit::InsertStr {
+                at: Position { x: 0, y },
+                text: indent.to_owned(),
+            });
+        }
+    }
+
+    /// Removes one unit of indentation from the row: a leading tab, or up to `tab_width` leading
+    /// spaces. Returns the number of graphemes removed, which is 0 if the row isn't indented.
+    pub fn dedent_line(&mut self, y: usize, tab_width: usize) -> usize {
+        let Some(text) = self.rows.get(y).map(Row::as_str) else {
+            return 0;
+        };
+        let removed = if text.starts_with('\t') {
+            1
+        } else {
+            text.chars()
+                .take(cmp::max(tab_width, 1))
+                .take_while(|c| *c == ' ')
+                .count()
+        };
+        if removed > 0 {
+            self.apply(Edit::DeleteRange {
+                start: Position { x: 0, y },
+                end: Position { x: removed, y },
+            });
+        }
+        removed
+    }
+
+    /// Whether there's a row after `y`; never underflows, even on an empty document.
+    fn is_not_last_row(&self, y: usize) -> bool {
+        y.saturating_add(1) < self.len()
+    }
+
+    /// Joins the row after `y` to the end of row `y`, returning where the text of the joined row
+    /// starts; `None` if `y` is the last row. If `smart`, the whitespace at the join is collapsed to
+    /// a single space, or none next to a `(` or `[` and its closing bracket, or an empty side.
+    pub fn join_rows(&mut self, y: usize, smart: bool) -> Option<Position> {
+        let row = self.rows.get(y)?;
+        let next = self.rows.get(y.saturating_add(1))?;
+        if !smart {
+            let end = Position { x: row.len(), y };
+            self.apply(Edit::JoinLines { y });
+            return Some(end);
+        }
+        let trimmed = row.as_str().trim_end_matches([' ', '\t']);
+        let leading = next.leading_whitespace();
+        let content = next.as_str().get(leading.len()..).unwrap_or_default();
+        let separator = if trimmed.is_empty()
+            || content.is_empty()
+            || trimmed.ends_with(['(', '['])
+            || content.starts_with([')', ']'])
         {
-            #[allow(clippy::arithmetic_side_effects)]
-            let next_row = self.rows.remove(at.y + 1);
-            let this_row = &mut self.rows[at.y];
-            this_row.append(&next_row);
+            ""
         } else {
-            let this_row = &mut self.rows[at.y];
-            this_row.delete(at.x);
+            " "
+        };
+        let at = Position {
+            x: trimmed.graphemes(true).count(),
+            y,
+        };
+        // The indentation is whitespace, of a grapheme per byte.
+        let end = Position {
+            x: leading.len(),
+            y: y.saturating_add(1),
+        };
+        let joined = Position {
+            x: at.x.saturating_add(separator.len()),
+            y,
+        };
+        self.apply(Edit::Batch(vec![
+            Edit::DeleteRange {
+                start: at.clone(),
+                end,
+            },
+            Edit::InsertStr {
+                at,
+                text: separator.to_owned(),
+            },
+        ]));
+        Some(joined)
+    }
+
+    /// Appends the whole lines read from the end of the file as it grew, each with its line
+    /// ending. The lines are part of the file already, so the document isn't made dirty by them,
+    /// nor can they be undone.
+    pub fn append_from_disk(&mut self, text: &str) {
+        self.revision = self.revision.wrapping_add(1);
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+        let mut lines = text.strip_suffix('\n').unwrap_or(&text).split('\n');
+        // The last line of the file was left unfinished, so the first appended line ends it.
+        if self.is_missing_final_newline {
+            if let (Some(row), Some(line)) = (self.rows.last_mut(), lines.next()) {
+                row.append(&Row::from(line));
+            }
+            self.is_missing_final_newline = false;
+        }
+        self.rows.extend(lines.map(Row::from));
+    }
+
+    /// Adds the row after the last one.
+    pub fn append_row(&mut self, row: Row) {
+        self.insert_row(self.len(), row);
+    }
+
+    /// Inserts the row before row `at`, or after the last one if `at` is the number of rows;
+    /// nothing is inserted further past the end.
+    pub fn insert_row(&mut self, at: usize, row: Row) {
+        self.apply(Edit::InsertRow {
+            y: at,
+            text: row.into_string(),
+        });
+    }
+
+    /// Removes the entire row, returning it; `None` if there's no such row.
+    pub fn remove_row(&mut self, at: usize) -> Option<Row> {
+        match self.apply(Edit::RemoveRow { y: at }).inverse {
+            Edit::InsertRow { text, .. } => Some(Row::from(text.as_str())),
+            _ => None,
         }
     }
 
+    /// Removes the blank rows at the end of the document beyond the first `max` of them, where a
+    /// row of only whitespace is blank. Returns whether any row was removed.
+    pub fn trim_trailing_blank_lines(&mut self, max: usize) -> bool {
+        let blank_count = self
+            .rows
+            .iter()
+            .rev()
+            .take_while(|row| row.is_blank())
+            .count();
+        if blank_count <= max {
+            return false;
+        }
+        for _ in max..blank_count {
+            self.apply(Edit::RemoveRow {
+                y: self.len().saturating_sub(1),
+            });
+        }
+        true
+    }
+
+    /// Saves the file, ending the last line with a newline.
     /// # Errors
     /// Returns an error if the file doesn't exist and can't be created, or can't
-    /// be written.
-    pub fn save(&mut self) -> Result<(), Error> {
+    /// be written; `ReadOnly` if that's for lack of permission.
+    pub fn save(&mut self) -> Result<(), EditorError> {
+        self.save_with(&SaveOptions::default())
+    }
+
+    /// Saves the file as the options say. Saving without a final newline is a one-off, which
+    /// doesn't make the document track the file as missing it.
+    /// The file is written to a temporary file next to it first, which then replaces it, so that a
+    /// failure midway leaves the file as it was.
+    /// # Errors
+    /// As `save`.
+    pub fn save_with(&mut self, options: &SaveOptions) -> Result<(), EditorError> {
+        let final_newline = options.final_newline;
         if let Some(filename) = &self.filename {
-            let mut file = fs::File::create(filename)?;
+            let content = self.joined_lines(final_newline);
+            // Encode before creating the file, so that a failure leaves the file untouched.
+            #[cfg(feature = "encoding")]
+            let bytes = self.encode(&content)?;
+            #[cfg(not(feature = "encoding"))]
+            let bytes = content.into_bytes();
+            let path = if options.through_symlinks {
+                resolve_symlinks(Path::new(filename))
+            } else {
+                PathBuf::from(filename)
+            };
+            write_atomically(&path, &bytes).map_err(|err| {
+                if err.kind() == ErrorKind::PermissionDenied {
+                    EditorError::ReadOnly
+                } else {
+                    EditorError::Io(err)
+                }
+            })?;
             self.file_type = FileType::from(filename);
-            for row in &mut self.rows {
-                file.write_all(row.as_bytes())?;
-                file.write_all(b"\n")?;
-            }
             self.is_dirty = false;
+            if final_newline {
+                self.is_missing_final_newline = false;
+            }
+            self.last_saved = Some(Instant::now());
         }
         Ok(())
     }
 
+    /// The rows joined by the line ending of the document, with a final one if `final_newline`.
+    fn joined_lines(&self, final_newline: bool) -> String {
+        let line_ending = self.line_ending.as_str();
+        let mut text = self.lines().collect::<Vec<_>>().join(line_ending);
+        if final_newline && !self.is_empty() {
+            text.push_str(line_ending);
+        }
+        text
+    }
+
+    /// Transcodes the content back to the encoding of the file, with the byte order mark if it had one.
+    /// # Errors
+    /// Returns an error if some characters can't be represented in the encoding.
+    #[cfg(feature = "encoding")]
+    #[allow(clippy::big_endian_bytes, clippy::little_endian_bytes)] // UTF-16 has an explicit byte order.
+    fn encode(&self, content: &str) -> Result<Vec<u8>, EditorError> {
+        let encoding = self.encoding.unwrap_or(UTF_8);
+        let content = if self.has_bom {
+            // The byte order mark is U+FEFF in whichever encoding.
+            format!("\u{feff}{content}")
+        } else {
+            content.to_owned()
+        };
+        // NOTE: encoding_rs only decodes UTF-16, so it's encoded by hand.
+        if encoding == UTF_16LE || encoding == UTF_16BE {
+            let is_big_endian = encoding == UTF_16BE;
+            return Ok(content
+                .encode_utf16()
+                .flat_map(|unit| {
+                    if is_big_endian {
+                        unit.to_be_bytes()
+                    } else {
+                        unit.to_le_bytes()
+                    }
+                })
+                .collect());
+        }
+        let (bytes, _, had_unmappable) = encoding.encode(&content);
+        if had_unmappable {
+            return Err(EditorError::InvalidInput(format!(
+                "some characters can't be encoded in {}",
+                encoding.name()
+            )));
+        }
+        Ok(bytes.into_owned())
+    }
+
+    /// The name of the encoding of the file if it's not UTF-8, e.g., `windows-1252`.
+    #[must_use]
+    #[cfg(feature = "encoding")]
+    pub fn encoding_name(&self) -> Option<&'static str> {
+        self.encoding.map(Encoding::name)
+    }
+
     #[must_use]
     pub fn is_dirty(&self) -> bool {
         self.is_dirty
     }
 
+    /// When the document was last saved, if it has been saved since open.
+    #[must_use]
+    pub fn last_saved(&self) -> Option<Instant> {
+        self.last_saved
+    }
+
+    /// Normalizes all rows to NFC, so that precomposed and decomposed characters are treated the same.
+    /// # Notes
+    /// The dirty flag is not touched.
+    #[cfg(feature = "nfc")]
+    pub fn normalize_nfc(&mut self) {
+        let mut is_changed = false;
+        for row in &mut self.rows {
+            // NOTE: Don't short-circuit; every row has to be normalized.
+            is_changed |= row.normalize_nfc();
+        }
+        if is_changed {
+            self.revision = self.revision.wrapping_add(1);
+            // The recorded positions may no longer fit the normalized text.
+            self.history.clear();
+            self.is_normalized = true;
+        }
+    }
+
+    /// Whether the file lacks a trailing newline after its last line, e.g., `[noeol]` in Vim.
+    #[must_use]
+    pub fn is_missing_final_newline(&self) -> bool {
+        self.is_missing_final_newline
+    }
+
+    /// Whether Unicode normalization has changed the text since open.
+    #[must_use]
+    pub fn is_normalized(&self) -> bool {
+        self.is_normalized
+    }
+
     /// Find the first occurrence of a query after a given position.
     #[must_use]
-    pub fn find_after(&self, query: &str, after: &Position) -> Option<Position> {
+    pub fn find_after(
+        &self,
+        query: &str,
+        after: &Position,
+        opts: &SearchOptions,
+    ) -> Option<Position> {
         // NOTE: The start row is skipped if `after` exceeds the row length.
         let mut x = after.x;
         for (y, row) in self.rows.iter().enumerate().skip(after.y) {
-            if let Some(x) = row.find_after(query, x) {
+            if let Some(x) = row.find(query, x, opts) {
                 return Some(Position { x, y });
             }
             // Only the start row is affected by the `after` position.
@@ -159,7 +1204,12 @@ impl Document {
 
     /// Find the last occurrence of a query before a given position.
     #[must_use]
-    pub fn rfind_before(&self, query: &str, before: &Position) -> Option<Position> {
+    pub fn rfind_before(
+        &self,
+        query: &str,
+        before: &Position,
+        opts: &SearchOptions,
+    ) -> Option<Position> {
         let mut x = before.x;
         #[allow(clippy::indexing_slicing)]
         for (y, row) in self
@@ -169,7 +1219,7 @@ impl Document {
             .take(before.y.saturating_add(1) /* first n, one-based */)
             .rev()
         {
-            if let Some(x) = row.rfind_before(query, x) {
+            if let Some(x) = row.rfind(query, x, opts) {
                 return Some(Position { x, y });
             }
             // Only the start row is affected by the `before` position.
@@ -178,8 +1228,27 @@ impl Document {
         None
     }
 
+    /// Marks the document as large if its text is longer than the bytes, which turns off the
+    /// passes over every grapheme, i.e., syntax, search, and spelling highlighting.
+    pub fn mark_large_over(&mut self, large_file_bytes: usize) {
+        let len = self
+            .rows
+            .iter()
+            .map(|row| row.as_bytes().len().saturating_add(1))
+            .fold(0usize, usize::saturating_add);
+        self.is_large = len > large_file_bytes;
+    }
+
+    #[must_use]
+    pub fn is_large(&self) -> bool {
+        self.is_large
+    }
+
     /// Highlight the document until a given row. Note that the highlight of a row is only affected by the previous rows.
     pub fn highlight_until(&mut self, until: usize) {
+        if self.is_large {
+            return;
+        }
         let mut highlight_ctx = row::HighlightContext::default();
         self.rows.iter_mut().take(until).for_each(|row| {
             highlight_ctx = row.highlight(self.file_type.highlight_options(), &highlight_ctx);
@@ -187,17 +1256,978 @@ impl Document {
     }
 
     /// Highlight the query in the entire document.
-    pub fn highlight_query(&mut self, query: &str) {
+    pub fn highlight_query(&mut self, query: &str, opts: &SearchOptions) {
+        if self.is_large {
+            return;
+        }
         self.rows
             .iter_mut()
-            .for_each(|row| row.highlight_query(query));
+            .for_each(|row| row.highlight_query(query, opts));
+    }
+
+    /// Marks the misspelled words in the rows if the document is prose, e.g., plain text or
+    /// Markdown. Only the given rows are checked, as checking is costly.
+    #[cfg(feature = "spell")]
+    pub fn highlight_misspelled(&mut self, rows: Range<usize>, dictionary: &Dictionary) {
+        if !self.file_type.is_prose() || self.is_large {
+            return;
+        }
+        for row in self.rows.iter_mut().take(rows.end).skip(rows.start) {
+            row.highlight_misspelled(dictionary);
+        }
     }
 
     /// Restore the original highlight of the document, particularly after highlighting a query.
     pub fn highlight_restore(&mut self) {
+        if self.is_large {
+            return;
+        }
         let mut highlight_ctx = row::HighlightContext::default();
         self.rows.iter_mut().for_each(|row| {
             highlight_ctx = row.highlight(self.file_type.highlight_options(), &highlight_ctx);
         });
     }
 }
+
+/// The text as it was read, the rows joined by the line ending of the document, with a final one
+/// unless the file was missing it.
+impl fmt::Display for Document {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.joined_lines(!self.is_missing_final_newline))
+    }
+}
+
+/// The file the path points to through any symbolic links, even if it doesn't exist yet; the path
+/// itself if it isn't a link. A relative link is relative to the directory of the link.
+fn resolve_symlinks(path: &Path) -> PathBuf {
+    // As many links as Linux follows before giving up on a loop.
+    const MAX_LINKS: usize = 40;
+    let mut path = path.to_path_buf();
+    for _ in 0..MAX_LINKS {
+        let is_symlink =
+            fs::symlink_metadata(&path).is_ok_and(|meta| meta.file_type().is_symlink());
+        let Some(target) = is_symlink.then(|| fs::read_link(&path).ok()).flatten() else {
+            break;
+        };
+        path = match path.parent() {
+            Some(parent) => parent.join(target),
+            None => target,
+        };
+    }
+    path
+}
+
+/// Writes the bytes to a temporary file in the directory of the path, then renames it over the
+/// path, which replaces the file at once; the temporary file is removed on failure.
+fn write_atomically(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let original = fs::metadata(path).ok();
+    let (temp, file) = create_temp_file(path, original.is_none())?;
+    let result = write_and_rename(file, &temp, path, original.as_ref(), bytes);
+    if result.is_err() {
+        drop(fs::remove_file(&temp));
+    }
+    result
+}
+
+/// How many names the temporary file is tried under before giving up, each taken by another file.
+const MAX_TEMP_FILE_ATTEMPTS: usize = 100;
+
+/// Creates a file in the directory of the path under the first of the temporary names that's not
+/// taken, so that nothing there, e.g., a link planted to have another file overwritten, is
+/// followed. Only the owner can read it until it takes the permissions of the file it's to replace,
+/// if any; a new file gets the default permissions, as the umask allows.
+fn create_temp_file(path: &Path, is_new: bool) -> io::Result<(PathBuf, fs::File)> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(if is_new { 0o666 } else { 0o600 });
+    for attempt in 0..MAX_TEMP_FILE_ATTEMPTS {
+        let temp = temp_file_name(path, attempt);
+        match options.open(&temp) {
+            Ok(file) => return Ok((temp, file)),
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => (),
+            Err(err) => return Err(err),
+        }
+    }
+    Err(io::Error::new(
+        ErrorKind::AlreadyExists,
+        "every temporary file name is taken",
+    ))
+}
+
+/// The temporary name tried at the attempt for the file of the path, hidden next to it.
+fn temp_file_name(path: &Path, attempt: usize) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{name}.{}-{attempt}.hecto~", process::id()))
+}
+
+/// The temporary file takes the ownership, where allowed, and the permissions of the original file
+/// it replaces, if any, before anything is written to it, so that the bytes are never readable by
+/// more than they're to be.
+fn write_and_rename(
+    mut file: fs::File,
+    temp: &Path,
+    path: &Path,
+    original: Option<&fs::Metadata>,
+    bytes: &[u8],
+) -> io::Result<()> {
+    if let Some(metadata) = original {
+        // Only the superuser may give a file away, so failing to is not an error. Done first, as
+        // changing the owner may clear the set-user-ID and set-group-ID bits.
+        #[cfg(unix)]
+        drop(fchown(&file, Some(metadata.uid()), Some(metadata.gid())));
+        file.set_permissions(metadata.permissions())?;
+    }
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    fs::rename(temp, path)
+}
+
+/// The path trimmed, with a leading `~` expanded to the home directory, and with the `.` and `..`
+/// components resolved without following symbolic links; e.g., `~/a/./b/../c` becomes
+/// `/home/user/a/c`.
+#[must_use]
+pub fn normalize_path(path: &str) -> String {
+    let path = path.trim();
+    let expanded = match (path.strip_prefix('~'), env::var("HOME")) {
+        (Some(rest), Ok(home)) if rest.is_empty() || rest.starts_with('/') => {
+            format!("{home}{rest}")
+        }
+        _ => path.to_owned(),
+    };
+    let mut normalized = PathBuf::new();
+    for component in Path::new(&expanded).components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir
+                if matches!(
+                    normalized.components().next_back(),
+                    Some(Component::Normal(_))
+                ) =>
+            {
+                normalized.pop();
+            }
+            _ => normalized.push(component),
+        }
+    }
+    if normalized.as_os_str().is_empty() {
+        expanded
+    } else {
+        normalized.to_string_lossy().into_owned()
+    }
+}
+
+/// Splits the content into lines on any of `\n`, `\r\n`, and `\r`, so that files with mixed or
+/// classic Mac line endings are split correctly. Like `str::lines`, the final line ending doesn't
+/// produce an empty line.
+fn split_lines(content: &str) -> Vec<&str> {
+    let mut lines = Vec::new();
+    let mut rest = content;
+    while let Some(index) = rest.find(['\n', '\r']) {
+        let (line, ending_and_rest) = rest.split_at(index);
+        lines.push(line);
+        rest = ending_and_rest
+            .strip_prefix("\r\n")
+            .or_else(|| ending_and_rest.get(1..))
+            .unwrap_or_default();
+    }
+    if !rest.is_empty() {
+        lines.push(rest);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TempPath;
+    #[cfg(unix)]
+    use std::os::unix::fs::{symlink, PermissionsExt};
+
+    /// A document of the content, as if opened from a plain text file.
+    fn document(content: &str) -> Document {
+        Document::from_content("test.txt", content)
+    }
+
+    #[test]
+    fn rendered_line_expands_leading_and_interior_tabs() {
+        let doc = document("\t\tfoo\na\tb\tc\n");
+        assert_eq!(doc.rendered_line(0, 4).as_deref(), Some("        foo"));
+        assert_eq!(doc.rendered_line(1, 4).as_deref(), Some("a   b   c"));
+        assert_eq!(
+            doc.rendered_line(1, 8).as_deref(),
+            Some("a       b       c")
+        );
+        assert_eq!(doc.rendered_line(2, 4), None);
+    }
+
+    #[test]
+    fn rendered_line_draws_control_characters_as_on_the_screen() {
+        let doc = document("\x01\tx\n");
+        assert_eq!(doc.rendered_line(0, 4).as_deref(), Some("^A  x"));
+    }
+
+    #[test]
+    #[cfg(feature = "nfc")]
+    fn normalize_nfc_composes_decomposed_text() {
+        let mut doc = document("cafe\u{301}\n");
+        assert_eq!(doc.row_len(0), 4);
+        doc.normalize_nfc();
+        assert!(doc.is_normalized());
+        assert_eq!(doc.row_len(0), 4);
+        assert_eq!(
+            doc.lines().next().map(str::as_bytes),
+            Some("caf\u{e9}".as_bytes())
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "nfc")]
+    fn normalize_nfc_leaves_composed_text_alone() {
+        let mut doc = document("caf\u{e9}\n");
+        doc.normalize_nfc();
+        assert!(!doc.is_normalized());
+    }
+
+    #[test]
+    fn missing_final_newline_is_tracked_until_saved() {
+        let path = TempPath::with_content("noeol.txt", b"foo\nbar");
+        let Ok(mut doc) = Document::open(&path) else {
+            panic!("failed to open {path}");
+        };
+        assert!(doc.is_missing_final_newline());
+        if let Err(err) = doc.save() {
+            panic!("failed to save {path}: {err}");
+        }
+        assert!(!doc.is_missing_final_newline());
+        assert_eq!(
+            fs::read_to_string(&path).ok().as_deref(),
+            Some("foo\nbar\n")
+        );
+    }
+
+    #[test]
+    fn final_newline_is_not_missing() {
+        assert!(!document("foo\n").is_missing_final_newline());
+        assert!(!document("").is_missing_final_newline());
+    }
+
+    #[test]
+    fn max_visual_width_is_of_the_widest_row_anywhere() {
+        let doc = document("short\nthe longest line\nmid\n");
+        assert_eq!(doc.max_visual_width(4), 16);
+    }
+
+    #[test]
+    fn max_visual_width_expands_tabs() {
+        let doc = document("\t\tab\nlonger than ab\n");
+        assert_eq!(doc.max_visual_width(8), 18);
+        assert_eq!(doc.max_visual_width(4), 14);
+        assert_eq!(Document::default().max_visual_width(4), 0);
+    }
+
+    #[test]
+    fn opens_carriage_return_delimited_lines() {
+        let path = TempPath::with_content("cr.txt", b"one\rtwo\rthree\r");
+        let Ok(doc) = Document::open(&path) else {
+            panic!("failed to open {path}");
+        };
+        assert_eq!(doc.len(), 3);
+        assert_eq!(doc.lines().collect::<Vec<_>>(), ["one", "two", "three"]);
+        assert!(doc.line_ending() == LineEnding::Cr);
+        assert!(!doc.is_missing_final_newline());
+    }
+
+    #[test]
+    fn detects_the_line_ending_by_the_first_terminator() {
+        assert!(LineEnding::detect("a\r\nb\n") == LineEnding::CrLf);
+        assert!(LineEnding::detect("a\rb\r\n") == LineEnding::Cr);
+        assert!(LineEnding::detect("a\nb\r") == LineEnding::Lf);
+        assert!(LineEnding::detect("a") == LineEnding::Lf);
+    }
+
+    #[test]
+    fn deleting_in_an_empty_document_does_nothing() {
+        let mut doc = Document::default();
+        assert!(!doc.is_not_last_row(0));
+        doc.delete(&Position::default());
+        doc.delete(&Position { x: 3, y: 2 });
+        assert!(doc.join_rows(0, true).is_none());
+        assert!(doc.is_empty());
+        assert!(!doc.is_dirty());
+    }
+
+    #[test]
+    fn deleting_at_the_end_of_the_last_row_does_nothing() {
+        let mut doc = document("foo\n");
+        assert!(!doc.is_not_last_row(0));
+        doc.delete(&Position { x: 3, y: 0 });
+        assert_eq!(doc.lines().collect::<Vec<_>>(), ["foo"]);
+    }
+
+    #[test]
+    #[cfg(feature = "encoding")]
+    fn latin1_round_trips_to_the_original_bytes() {
+        let path = TempPath::with_content("latin1.txt", b"caf\xe9\nna\xefve\n");
+        let Ok(mut doc) = Document::open_with_encoding(&path, Some("latin1")) else {
+            panic!("failed to open {path}");
+        };
+        assert_eq!(doc.lines().collect::<Vec<_>>(), ["caf\u{e9}", "na\u{ef}ve"]);
+        assert_eq!(doc.encoding_name(), Some("windows-1252"));
+        if let Err(err) = doc.save() {
+            panic!("failed to save {path}: {err}");
+        }
+        assert_eq!(fs::read(&path).ok(), Some(b"caf\xe9\nna\xefve\n".to_vec()));
+    }
+
+    #[test]
+    #[cfg(feature = "encoding")]
+    fn unknown_encoding_is_invalid_input() {
+        let path = TempPath::with_content("unknown-encoding.txt", b"foo\n");
+        let opened = Document::open_with_encoding(&path, Some("klingon"));
+        assert!(matches!(opened, Err(EditorError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn toggles_the_comments_of_a_three_line_selection() {
+        let mut doc = Document::from_content("test.rs", "fn f() {\n    let x = 1;\n\n}\n");
+        assert!(doc.toggle_comment(0..=2));
+        assert_eq!(
+            doc.lines().collect::<Vec<_>>(),
+            ["// fn f() {", "    // let x = 1;", "", "}"]
+        );
+        assert!(doc.toggle_comment(0..=2));
+        assert_eq!(
+            doc.lines().collect::<Vec<_>>(),
+            ["fn f() {", "    let x = 1;", "", "}"]
+        );
+        assert!(!document("plain\n").toggle_comment(0..=0));
+    }
+
+    #[test]
+    fn toggling_the_comments_of_rows_past_the_end_does_nothing() {
+        let mut doc = Document::from_content("test.rs", "a\nb\n");
+        assert!(!doc.toggle_comment(2..=3));
+        assert!(!doc.is_dirty());
+        assert!(doc.toggle_comment(1..=5));
+        assert_eq!(doc.lines().collect::<Vec<_>>(), ["a", "// b"]);
+    }
+
+    #[test]
+    fn duplicates_rows_right_after_them() {
+        let mut doc = document("a\nb\nc\n");
+        assert!(doc.duplicate_rows(0..=1));
+        assert_eq!(doc.lines().collect::<Vec<_>>(), ["a", "b", "a", "b", "c"]);
+        assert!(doc.duplicate_rows(4..=4));
+        assert_eq!(
+            doc.lines().collect::<Vec<_>>(),
+            ["a", "b", "a", "b", "c", "c"]
+        );
+        assert!(!doc.duplicate_rows(6..=6));
+        assert_eq!(doc.len(), 6);
+    }
+
+    #[test]
+    fn grapheme_at_positions_of_a_multibyte_row() {
+        let doc = document("\u{e9}te\u{301}\u{1f600}\n");
+        let at = |x: usize, y: usize| doc.grapheme_at(&Position { x, y });
+        assert_eq!(at(0, 0), Some("\u{e9}"));
+        assert_eq!(at(1, 0), Some("t"));
+        assert_eq!(at(2, 0), Some("e\u{301}"));
+        assert_eq!(at(3, 0), Some("\u{1f600}"));
+        assert_eq!(at(4, 0), None);
+        assert_eq!(at(0, 1), None);
+    }
+
+    #[test]
+    fn tells_no_rows_from_an_empty_row() {
+        let none = Document::default();
+        assert!(none.is_empty());
+        assert!(!none.has_content());
+        let empty = document("\n");
+        assert!(!empty.is_empty());
+        assert!(!empty.has_content());
+        let text = document("foo\n");
+        assert!(!text.is_empty());
+        assert!(text.has_content());
+    }
+
+    #[test]
+    fn saves_with_the_line_ending_switched_to() {
+        let path = TempPath::with_content("crlf.txt", b"one\ntwo\n");
+        let Ok(mut doc) = Document::open(&path) else {
+            panic!("failed to open {path}");
+        };
+        doc.set_line_ending(LineEnding::CrLf);
+        assert!(doc.is_dirty());
+        if let Err(err) = doc.save() {
+            panic!("failed to save {path}: {err}");
+        }
+        assert_eq!(fs::read(&path).ok(), Some(b"one\r\ntwo\r\n".to_vec()));
+    }
+
+    #[test]
+    fn parses_line_ending_names() {
+        assert!("CRLF".parse::<LineEnding>() == Ok(LineEnding::CrLf));
+        assert!("lf".parse::<LineEnding>() == Ok(LineEnding::Lf));
+        assert!("crlf\n".parse::<LineEnding>().is_err());
+    }
+
+    #[test]
+    fn trims_trailing_blank_lines_before_saving() {
+        let path = TempPath::with_content("blank-tail.txt", b"one\ntwo\n\n  \n\n\n");
+        let Ok(mut doc) = Document::open(&path) else {
+            panic!("failed to open {path}");
+        };
+        assert_eq!(doc.len(), 6);
+        assert!(doc.trim_trailing_blank_lines(1));
+        assert_eq!(doc.len(), 3);
+        assert!(!doc.trim_trailing_blank_lines(1));
+        if let Err(err) = doc.save() {
+            panic!("failed to save {path}: {err}");
+        }
+        assert_eq!(
+            fs::read_to_string(&path).ok().as_deref(),
+            Some("one\ntwo\n\n")
+        );
+        assert!(doc.trim_trailing_blank_lines(0));
+        assert_eq!(doc.lines().collect::<Vec<_>>(), ["one", "two"]);
+    }
+
+    #[test]
+    fn applying_the_inverse_restores_the_document() {
+        let at = |x: usize, y: usize| Position { x, y };
+        let edits = [
+            Edit::InsertChar {
+                at: at(1, 0),
+                c: 'x',
+            },
+            Edit::InsertChar {
+                at: at(1, 1),
+                c: '\n',
+            },
+            Edit::DeleteChar { at: at(0, 2) },
+            Edit::DeleteChar { at: at(3, 0) },
+            Edit::InsertNewline { at: at(0, 3) },
+            Edit::JoinLines { y: 1 },
+            Edit::InsertStr {
+                at: at(2, 1),
+                text: "a\nb\u{e9}\nc".to_owned(),
+            },
+            Edit::DeleteRange {
+                start: at(1, 0),
+                end: at(2, 2),
+            },
+            Edit::InsertRow {
+                y: 1,
+                text: "new".to_owned(),
+            },
+            Edit::RemoveRow { y: 2 },
+            Edit::Batch(vec![
+                Edit::InsertChar {
+                    at: at(0, 0),
+                    c: '>',
+                },
+                Edit::RemoveRow { y: 1 },
+            ]),
+        ];
+        for edit in edits {
+            let mut doc = document("one\ntwo\nthr\u{e9}e");
+            let inverse = doc.apply(edit).inverse;
+            assert!(!inverse.is_none());
+            assert!(doc.lines().ne(["one", "two", "thr\u{e9}e"]));
+            doc.apply(inverse);
+            assert_eq!(
+                doc.lines().collect::<Vec<_>>(),
+                ["one", "two", "thr\u{e9}e"]
+            );
+        }
+    }
+
+    #[test]
+    fn edits_out_of_the_document_change_nothing() {
+        let mut doc = document("one");
+        let outcome = doc.apply(Edit::RemoveRow { y: 5 });
+        assert!(outcome.inverse.is_none());
+        let outcome = doc.apply(Edit::JoinLines { y: 0 });
+        assert!(outcome.inverse.is_none());
+        assert_eq!(doc.lines().collect::<Vec<_>>(), ["one"]);
+    }
+
+    #[test]
+    fn counts_the_digits_of_the_line_count() {
+        assert_eq!(Document::default().line_count_digits(), 1);
+        for (count, digits) in [(1, 1), (9, 1), (10, 2), (100, 3), (1000, 4)] {
+            let doc = document(&"line\n".repeat(count));
+            assert_eq!(doc.len(), count);
+            assert_eq!(doc.line_count_digits(), digits);
+        }
+    }
+
+    #[test]
+    fn rows_in_range_are_clamped_to_the_document() {
+        let doc = document("a\nb\nc\nd");
+        let texts = |rows: &[Row]| rows.iter().map(Row::as_str).collect::<Vec<_>>().join(",");
+        assert_eq!(texts(doc.rows_in_range(1, 3)), "b,c");
+        assert_eq!(texts(doc.rows_in_range(2, 10)), "c,d");
+        assert!(doc.rows_in_range(4, 10).is_empty());
+        assert!(doc.rows_in_range(7, 10).is_empty());
+        assert!(doc.rows_in_range(3, 1).is_empty());
+    }
+
+    #[test]
+    fn replaces_within_a_row_as_one_undo_step() {
+        let mut doc = document("one two three");
+        let start = Position { x: 4, y: 0 };
+        let outcome = doc.replace_range(start, Position { x: 7, y: 0 }, "2".to_owned());
+        assert_eq!(doc.lines().collect::<Vec<_>>(), ["one 2 three"]);
+        assert!(outcome.end == Position { x: 5, y: 0 });
+        doc.end_undo_step();
+        doc.undo();
+        assert_eq!(doc.lines().collect::<Vec<_>>(), ["one two three"]);
+        doc.redo();
+        assert_eq!(doc.lines().collect::<Vec<_>>(), ["one 2 three"]);
+    }
+
+    #[test]
+    fn sorts_a_range_of_lines_either_way() {
+        let mut doc = document("z\ncherry\napple\nbanana\na");
+        assert!(doc.sort_lines(1, 3, false));
+        assert_eq!(
+            doc.lines().collect::<Vec<_>>(),
+            ["z", "apple", "banana", "cherry", "a"]
+        );
+        assert!(!doc.sort_lines(1, 3, false));
+        assert!(doc.sort_lines(1, 3, true));
+        assert_eq!(
+            doc.lines().collect::<Vec<_>>(),
+            ["z", "cherry", "banana", "apple", "a"]
+        );
+    }
+
+    #[test]
+    fn sorting_past_the_end_is_clamped() {
+        let mut doc = document("b\na");
+        assert!(doc.sort_lines(0, 10, false));
+        assert_eq!(doc.lines().collect::<Vec<_>>(), ["a", "b"]);
+        assert!(!doc.sort_lines(5, 10, false));
+    }
+
+    #[test]
+    fn indentation_defaults_to_the_file_type() {
+        let go = Document::from_content("main.go", "package main\n");
+        assert!(go.indent_style() == Some(IndentStyle::Tabs));
+        let python = Document::from_content("main.py", "import os\n");
+        assert!(python.indent_style() == Some(IndentStyle::Spaces(4)));
+        assert!(document("plain\n").indent_style().is_none());
+    }
+
+    #[test]
+    fn detected_indentation_wins_over_the_file_type() {
+        let go = Document::from_content("main.go", "func f() {\n  return\n}\n");
+        assert!(go.indent_style() == Some(IndentStyle::Spaces(2)));
+        let python = Document::from_content("main.py", "if x:\n\tpass\n");
+        assert!(python.indent_style() == Some(IndentStyle::Tabs));
+    }
+
+    #[test]
+    fn large_documents_are_not_highlighted() {
+        let mut doc = Document::from_content("main.rs", "let x = 42;\n");
+        doc.mark_large_over(100);
+        assert!(!doc.is_large());
+        doc.highlight_until(1);
+        let Some(row) = doc.row(0) else {
+            panic!("no first row");
+        };
+        assert!(row.render(0, 80, 4).contains('\x1b'));
+
+        let mut doc = Document::from_content("main.rs", "let x = 42;\n");
+        doc.mark_large_over(5);
+        assert!(doc.is_large());
+        doc.highlight_until(1);
+        let Some(row) = doc.row(0) else {
+            panic!("no first row");
+        };
+        assert!(row.render(0, 80, 4).starts_with("let x = 42;"));
+    }
+
+    #[test]
+    fn normalizes_the_path() {
+        assert_eq!(normalize_path("  a/./b/../c.txt \n"), "a/c.txt");
+        assert_eq!(normalize_path("/a/b/../../c"), "/c");
+        assert_eq!(normalize_path("../a/b/.."), "../a");
+        assert_eq!(normalize_path("."), ".");
+        if let Ok(home) = env::var("HOME") {
+            assert_eq!(normalize_path("~/notes.txt"), format!("{home}/notes.txt"));
+            assert_eq!(normalize_path("~other/notes.txt"), "~other/notes.txt");
+        }
+    }
+
+    #[test]
+    fn opening_the_same_file_spelled_differently_names_it_the_same() {
+        let file = TempPath::with_content("spelled.txt", "foo\n");
+        let path = Path::new(&*file);
+        let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+            panic!("no directory or name in {}", path.display());
+        };
+        let spelled = format!("{}/./{} ", dir.display(), name.to_string_lossy());
+        let (Ok(doc), Ok(other)) = (
+            Document::open(&path.to_string_lossy()),
+            Document::open(&spelled),
+        ) else {
+            panic!("failed to open {}", path.display());
+        };
+        assert_eq!(doc.filename(), other.filename());
+        assert_eq!(other.filename(), path.to_str());
+    }
+
+    #[test]
+    fn renaming_detects_the_file_type_anew() {
+        let mut doc = document("fn main() {}\n");
+        assert_eq!(doc.file_type(), "Plain Text");
+        doc.set_filename(" ./src/main.rs ");
+        assert_eq!(doc.filename(), Some("src/main.rs"));
+        assert_eq!(doc.file_type(), "Rust");
+    }
+
+    #[test]
+    fn clear_empties_the_document() {
+        let mut doc = document("one\ntwo");
+        doc.set_filename("notes.txt");
+        doc.insert(&Position::default(), 'x');
+        doc.clear();
+        assert!(doc.is_empty());
+        assert!(doc.filename().is_none());
+        assert!(!doc.is_dirty());
+        assert!(doc.undo().is_none());
+    }
+
+    #[test]
+    fn to_string_round_trips_an_opened_file() {
+        for (name, content) in [
+            ("round_trip.txt", "fn f() {\n\tx();\n\n}\n"),
+            ("round_trip_noeol.txt", "foo\nbar"),
+            ("round_trip_crlf.txt", "foo\r\n\r\nbar\r\n"),
+            ("round_trip_empty.txt", ""),
+        ] {
+            let path = TempPath::with_content(name, content.as_bytes());
+            let Ok(doc) = Document::open(&path) else {
+                panic!("failed to open {path}");
+            };
+            assert_eq!(doc.to_string(), content);
+        }
+    }
+
+    #[test]
+    fn lines_are_without_line_endings() {
+        let doc = document("foo\r\nbar\r\n");
+        assert_eq!(doc.lines().collect::<Vec<_>>(), ["foo", "bar"]);
+    }
+
+    #[test]
+    fn smart_join_collapses_the_indentation_to_a_space() {
+        let mut doc = document("foo {\n    bar\n");
+        let joined = doc.join_rows(0, true);
+        assert!(joined == Some(Position { x: 6, y: 0 }));
+        assert_eq!(doc.lines().collect::<Vec<_>>(), ["foo { bar"]);
+    }
+
+    #[test]
+    fn smart_join_adds_no_space_next_to_brackets() {
+        let mut doc = document("f(\n    x\n)\n");
+        doc.join_rows(0, true);
+        doc.join_rows(0, true);
+        assert_eq!(doc.lines().collect::<Vec<_>>(), ["f(x)"]);
+        let mut doc = document("[a,  \n\t]\n");
+        doc.join_rows(0, true);
+        assert_eq!(doc.lines().collect::<Vec<_>>(), ["[a,]"]);
+    }
+
+    #[test]
+    fn smart_join_with_a_blank_row_adds_no_space() {
+        let mut doc = document("foo\n   \n");
+        doc.join_rows(0, true);
+        assert_eq!(doc.lines().collect::<Vec<_>>(), ["foo"]);
+    }
+
+    #[test]
+    fn plain_join_keeps_the_whitespace() {
+        let mut doc = document("foo {\n    bar\n");
+        let joined = doc.join_rows(0, false);
+        assert!(joined == Some(Position { x: 5, y: 0 }));
+        assert_eq!(doc.lines().collect::<Vec<_>>(), ["foo {    bar"]);
+        assert!(doc.join_rows(0, false).is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn saving_through_a_symlink_writes_the_target_and_keeps_the_link() {
+        let target = TempPath::with_content("symlink-target.txt", b"old\n");
+        let link = TempPath::new("symlink-link.txt");
+        // Relative to the directory of the link, which is that of the target.
+        let relative = Path::new(&*target).file_name().unwrap_or_default();
+        if let Err(err) = symlink(relative, &link) {
+            panic!("failed to link {link}: {err}");
+        }
+        let Ok(mut doc) = Document::open(&link) else {
+            panic!("failed to open {link}");
+        };
+        doc.insert_str(&Position::default(), "new ");
+        if let Err(err) = doc.save() {
+            panic!("failed to save {link}: {err}");
+        }
+        let is_symlink =
+            fs::symlink_metadata(&link).is_ok_and(|meta| meta.file_type().is_symlink());
+        assert!(is_symlink);
+        assert_eq!(
+            fs::read_to_string(&target).ok().as_deref(),
+            Some("new old\n")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn saving_not_through_symlinks_replaces_the_link() {
+        let target = TempPath::with_content("unlinked-target.txt", b"old\n");
+        let link = TempPath::new("unlinked-link.txt");
+        if let Err(err) = symlink(&target, &link) {
+            panic!("failed to link {link}: {err}");
+        }
+        let Ok(mut doc) = Document::open(&link) else {
+            panic!("failed to open {link}");
+        };
+        doc.insert_str(&Position::default(), "new ");
+        let options = SaveOptions {
+            through_symlinks: false,
+            ..SaveOptions::default()
+        };
+        if let Err(err) = doc.save_with(&options) {
+            panic!("failed to save {link}: {err}");
+        }
+        let is_replaced =
+            fs::symlink_metadata(&link).is_ok_and(|meta| !meta.file_type().is_symlink());
+        assert!(is_replaced);
+        assert_eq!(fs::read_to_string(&target).ok().as_deref(), Some("old\n"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn saving_does_not_follow_a_link_planted_at_the_temporary_name() {
+        let path = TempPath::with_content("planted.txt", b"old\n");
+        let victim = TempPath::with_content("victim.txt", b"victim\n");
+        let planted = temp_file_name(Path::new(&*path), 0);
+        if let Err(err) = symlink(&victim, &planted) {
+            panic!("failed to plant a link at {}: {err}", planted.display());
+        }
+        let Ok(mut doc) = Document::open(&path) else {
+            panic!("failed to open {path}");
+        };
+        doc.insert_str(&Position::default(), "new ");
+        let result = doc.save();
+        drop(fs::remove_file(&planted));
+        if let Err(err) = result {
+            panic!("failed to save {path}: {err}");
+        }
+        assert_eq!(fs::read_to_string(&path).ok().as_deref(), Some("new old\n"));
+        assert_eq!(
+            fs::read_to_string(&victim).ok().as_deref(),
+            Some("victim\n")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn saving_keeps_the_mode_bits_of_the_file() {
+        let path = TempPath::with_content("script.sh", b"echo hi\n");
+        if let Err(err) = fs::set_permissions(&path, fs::Permissions::from_mode(0o751)) {
+            panic!("failed to make {path} executable: {err}");
+        }
+        let Ok(mut doc) = Document::open(&path) else {
+            panic!("failed to open {path}");
+        };
+        doc.insert_str(&Position::default(), "#!/bin/sh\n");
+        if let Err(err) = doc.save() {
+            panic!("failed to save {path}: {err}");
+        }
+        let mode = fs::metadata(&path).map(|meta| meta.permissions().mode() & 0o777);
+        assert_eq!(mode.ok(), Some(0o751));
+        assert_eq!(
+            fs::read_to_string(&path).ok().as_deref(),
+            Some("#!/bin/sh\necho hi\n")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn saving_keeps_a_read_only_file_read_only() {
+        let path = TempPath::with_content("read-only.txt", b"old\n");
+        if let Err(err) = fs::set_permissions(&path, fs::Permissions::from_mode(0o444)) {
+            panic!("failed to make {path} read-only: {err}");
+        }
+        let Ok(mut doc) = Document::open(&path) else {
+            panic!("failed to open {path}");
+        };
+        doc.insert_str(&Position::default(), "new ");
+        if let Err(err) = doc.save() {
+            panic!("failed to save {path}: {err}");
+        }
+        let mode = fs::metadata(&path).map(|meta| meta.permissions().mode() & 0o777);
+        assert_eq!(mode.ok(), Some(0o444));
+        assert_eq!(fs::read_to_string(&path).ok().as_deref(), Some("new old\n"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn saving_a_new_file_makes_it_readable_but_not_executable() {
+        let path = TempPath::new("new-file.txt");
+        let mut doc = Document::default();
+        doc.set_filename(&path);
+        doc.insert_str(&Position::default(), "foo");
+        if let Err(err) = doc.save() {
+            panic!("failed to save {path}: {err}");
+        }
+        let mode = fs::metadata(&path).map_or(0, |meta| meta.permissions().mode());
+        assert!(mode & 0o400 != 0);
+        assert!(mode & 0o111 == 0);
+    }
+
+    #[test]
+    fn converts_mixed_case_multibyte_text() {
+        let text = "stra\u{df}e \u{c9}t\u{c9} don't \u{3a3}O\u{3a3}";
+        assert_eq!(
+            Case::Upper.convert(text),
+            "STRASSE \u{c9}T\u{c9} DON'T \u{3a3}O\u{3a3}"
+        );
+        // A final sigma is lowercased as such.
+        assert_eq!(
+            Case::Lower.convert(text),
+            "stra\u{df}e \u{e9}t\u{e9} don't \u{3c3}o\u{3c2}"
+        );
+        assert_eq!(
+            Case::Title.convert(text),
+            "Stra\u{df}e \u{c9}t\u{e9} Don't \u{3a3}o\u{3c2}"
+        );
+    }
+
+    #[test]
+    fn position_after_text_without_a_newline_advances_by_graphemes() {
+        let at = Position { x: 3, y: 2 };
+        let after = Document::position_after(&at, "e\u{301}\u{4e2d}!");
+        assert!(after == Position { x: 6, y: 2 });
+        assert!(Document::position_after(&at, "") == at);
+    }
+
+    #[test]
+    fn position_after_a_newline_is_on_the_next_row() {
+        let at = Position { x: 3, y: 2 };
+        assert!(Document::position_after(&at, "foo\nba") == Position { x: 2, y: 3 });
+        assert!(Document::position_after(&at, "\n") == Position { x: 0, y: 3 });
+    }
+
+    #[test]
+    fn position_after_several_newlines_is_on_the_last_row() {
+        let at = Position { x: 3, y: 2 };
+        let after = Document::position_after(&at, "a\n\nb\n\u{e9}t\u{e9}");
+        assert!(after == Position { x: 3, y: 5 });
+        let mut doc = document("xyz\n");
+        let text = "1\n22\n333";
+        doc.insert_str(&Position { x: 1, y: 0 }, text);
+        let after = Document::position_after(&Position { x: 1, y: 0 }, text);
+        assert_eq!(doc.row(after.y).map(Row::as_str), Some("333yz"));
+        assert!(after == Position { x: 3, y: 2 });
+    }
+
+    #[test]
+    fn row_len_counts_graphemes_and_is_zero_past_the_end() {
+        let doc = document("abc\ne\u{301}\u{4e2d}\n");
+        assert_eq!(doc.row_len(0), 3);
+        assert_eq!(doc.row_len(1), 2);
+        assert_eq!(doc.row_len(2), 0);
+        assert_eq!(doc.row_len(usize::MAX), 0);
+        assert_eq!(Document::default().row_len(0), 0);
+    }
+
+    #[test]
+    fn appends_and_inserts_whole_rows() {
+        let mut doc = document("b\n");
+        doc.append_row(Row::from("d"));
+        doc.insert_row(0, Row::from("a"));
+        doc.insert_row(2, Row::from("c"));
+        assert_eq!(doc.lines().collect::<Vec<_>>(), ["a", "b", "c", "d"]);
+        assert!(doc.is_dirty());
+    }
+
+    #[test]
+    fn inserting_a_row_past_the_end_does_nothing() {
+        let mut doc = document("a\n");
+        doc.insert_row(2, Row::from("x"));
+        assert_eq!(doc.lines().collect::<Vec<_>>(), ["a"]);
+        assert!(!doc.is_dirty());
+    }
+
+    #[test]
+    fn removes_whole_rows() {
+        let mut doc = document("a\nb\nc\n");
+        let removed = doc.remove_row(1);
+        assert_eq!(removed.as_ref().map(Row::as_str), Some("b"));
+        assert_eq!(doc.lines().collect::<Vec<_>>(), ["a", "c"]);
+        assert!(doc.is_dirty());
+    }
+
+    #[test]
+    fn removing_a_row_past_the_end_is_none() {
+        let mut doc = document("a\n");
+        assert!(doc.remove_row(1).is_none());
+        assert!(doc.remove_row(usize::MAX).is_none());
+        assert_eq!(doc.lines().collect::<Vec<_>>(), ["a"]);
+        assert!(!doc.is_dirty());
+    }
+
+    #[test]
+    fn word_count_follows_the_edits() {
+        let mut doc = document("one two\n");
+        assert_eq!(doc.word_count(), 2);
+        assert_eq!(doc.word_count(), 2);
+        doc.insert_str(&Position { x: 7, y: 0 }, " three");
+        assert_eq!(doc.word_count(), 3);
+        doc.remove_row(0);
+        assert_eq!(doc.word_count(), 0);
+        doc.append_from_disk("four five\n");
+        assert_eq!(doc.word_count(), 2);
+    }
+
+    #[test]
+    fn counts_a_range_within_a_row() {
+        let doc = document("h\u{e9}llo w\u{f6}rld\n");
+        let counted = doc.count_in_range(&Position { x: 1, y: 0 }, &Position { x: 7, y: 0 });
+        assert_eq!(counted, (6, 1));
+        let reversed = doc.count_in_range(&Position { x: 7, y: 0 }, &Position { x: 1, y: 0 });
+        assert_eq!(reversed, (6, 1));
+    }
+
+    #[test]
+    fn counts_a_range_across_rows() {
+        let doc = document("abc\ndefg\nhi\n");
+        let counted = doc.count_in_range(&Position { x: 1, y: 0 }, &Position { x: 1, y: 2 });
+        assert_eq!(counted, (7, 3));
+        let whole = doc.count_in_range(&Position::default(), &Position { x: 2, y: 2 });
+        assert_eq!(whole, (9, 3));
+    }
+
+    #[test]
+    fn counts_a_range_clamped_to_the_document() {
+        let doc = document("abc\n");
+        let counted = doc.count_in_range(&Position { x: 1, y: 0 }, &Position { x: 9, y: 5 });
+        assert_eq!(counted, (2, 1));
+    }
+
+    #[test]
+    fn delete_range_returns_the_deleted_text() {
+        let mut doc = document("one\ntwo\nthree\n");
+        let deleted = doc.delete_range(Position { x: 1, y: 0 }, Position { x: 2, y: 2 });
+        assert_eq!(deleted, "ne\ntwo\nth");
+        assert_eq!(doc.lines().collect::<Vec<_>>(), ["oree"]);
+        let nothing = doc.delete_range(Position { x: 2, y: 0 }, Position { x: 1, y: 0 });
+        assert_eq!(nothing, "");
+    }
+}
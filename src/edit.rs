@@ -0,0 +1,47 @@
+use crate::Position;
+
+/// A change to the document, applied by `Document::apply`, which gives back the edit undoing it.
+/// The positions are in graphemes, as the cursor's.
+#[derive(Clone)]
+pub enum Edit {
+    /// Inserts the character at the position; `\n` splits the row there.
+    InsertChar { at: Position, c: char },
+    /// Deletes the grapheme at the position; at the end of a row, joins the next row instead.
+    DeleteChar { at: Position },
+    /// Splits the row at the position; past the last row, adds an empty row.
+    InsertNewline { at: Position },
+    /// Joins the row after `y` to the end of row `y`.
+    JoinLines { y: usize },
+    /// Inserts the text at the position, with each `\n` starting a new row.
+    InsertStr { at: Position, text: String },
+    /// Deletes the text from `start` until `end`, exclusive, joining the rows in between.
+    DeleteRange { start: Position, end: Position },
+    /// Inserts a row of the text before row `y`.
+    InsertRow { y: usize, text: String },
+    /// Removes row `y` entirely.
+    RemoveRow { y: usize },
+    /// Applies the edits in order.
+    Batch(Vec<Edit>),
+}
+
+impl Edit {
+    /// An edit that changes nothing, e.g., the inverse of an edit that couldn't be applied.
+    #[must_use]
+    pub fn none() -> Self {
+        Self::Batch(Vec::new())
+    }
+
+    /// Whether the edit changes nothing.
+    #[must_use]
+    pub fn is_none(&self) -> bool {
+        matches!(self, Self::Batch(edits) if edits.iter().all(Self::is_none))
+    }
+}
+
+/// What `Document::apply` did.
+pub struct EditOutcome {
+    /// The edit that restores the document to before the edit.
+    pub inverse: Edit,
+    /// Where the cursor goes after the edit, e.g., after the inserted text.
+    pub end: Position,
+}
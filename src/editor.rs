@@ -1,18 +1,62 @@
-use std::env;
+use core::cmp;
+#[cfg(feature = "datetime")]
+use core::fmt;
+use core::iter;
+use core::ops::{Range, RangeInclusive};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io::{self, Read};
+use std::mem;
+use std::path::Path;
 use std::time::{Duration, Instant};
 
+use crate::diff;
+use crate::filter_cancellable;
+use crate::normalize_path;
+use crate::Args;
+use crate::Case;
+use crate::Config;
+use crate::CursorShape;
+#[cfg(feature = "spell")]
+use crate::Dictionary;
 use crate::Document;
+use crate::Edit;
+use crate::EditorError;
+use crate::ErrorList;
+use crate::Folds;
+use crate::Input;
+use crate::JumpList;
+use crate::LineEnding;
+use crate::PathCompleter;
 use crate::Row;
+use crate::SaveOptions;
+use crate::SearchOptions;
+use crate::Tail;
 use crate::Terminal;
 use std::io::Error;
 use termion::color;
 use termion::event::Key;
+use termion::style;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 const STATUS_BG_COLOR: color::Rgb = color::Rgb(239, 239, 239);
 const STATUS_FG_COLOR: color::Rgb = color::Rgb(63, 63, 63);
-/// The number of times the user has to press `Ctrl-Q` to quit.
+const SELECTION_BG_COLOR: color::Rgb = color::Rgb(63, 63, 95);
+/// The number of times the user has to press the quit key, `Ctrl-Q` by default, to quit.
 const QUIT_TIMES: u8 = 3;
+/// The number of cells of the progress bar of the word goal.
+const WORD_GOAL_BAR_WIDTH: usize = 10;
+/// How often the file is checked for appended lines in tail mode.
+const TAIL_POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// How often the main loop wakes up while waiting for input, to check for the timed events, e.g.,
+/// the message expiring.
+const FRAME_INTERVAL: Duration = Duration::from_millis(100);
+/// The number of columns kept visible from the previous page when paging horizontally.
+const HORIZONTAL_PAGE_OVERLAP: usize = 8;
+/// The number of closed files remembered to reopen; the oldest ones are forgotten beyond it.
+const CLOSED_BUFFERS_CAPACITY: usize = 10;
 
 #[derive(Default, Clone, PartialEq)]
 pub struct Position {
@@ -20,6 +64,103 @@ pub struct Position {
     pub y: usize,
 }
 
+/// The modal editing mode, which is only switched when `Config::modal` is on.
+/// Without modal editing, the editor is always in Insert mode.
+#[derive(PartialEq, Clone, Copy)]
+enum Mode {
+    /// Keys are commands, e.g., `h/j/k/l` move the cursor.
+    Normal,
+    /// Keys are inserted as text.
+    Insert,
+}
+
+/// How the rows are numbered in the gutter, cycled through by the `line_numbers` command.
+#[derive(Default, PartialEq, Clone, Copy)]
+enum GutterMode {
+    /// No gutter.
+    #[default]
+    Off,
+    /// The 1-based number of each row.
+    Absolute,
+    /// The distance of each row from the row of the cursor, which shows its own number.
+    Relative,
+}
+
+impl GutterMode {
+    /// The mode after this one: off, absolute, relative, and off again.
+    fn next(self) -> Self {
+        match self {
+            Self::Off => Self::Absolute,
+            Self::Absolute => Self::Relative,
+            Self::Relative => Self::Off,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Absolute => "absolute",
+            Self::Relative => "relative",
+        }
+    }
+}
+
+/// An edit that can be replayed by the dot command in Normal mode.
+#[derive(Clone)]
+enum RepeatableEdit {
+    /// The text typed during an Insert mode session.
+    Insert(String),
+    /// The `x` command.
+    DeleteChar,
+    /// The `dd` command.
+    DeleteLine,
+}
+
+/// A macro being replayed.
+struct Replay {
+    register: char,
+    /// The inputs left to replay of the current repetition.
+    inputs: VecDeque<Input>,
+    /// The number of repetitions left after the current one, whose inputs are taken from the
+    /// register as each starts, rather than all queued up front.
+    remaining: usize,
+}
+
+/// How an edit at one cursor moves the text after it, so that the other cursors can follow it.
+enum Shift {
+    /// A grapheme was inserted at the position.
+    Inserted(Position),
+    /// A line break was inserted at the position.
+    Split(Position),
+    /// The grapheme at the position was deleted.
+    Deleted(Position),
+    /// The row after `y` was joined to the end of row `y`, which was `len` graphemes long.
+    Joined { y: usize, len: usize },
+}
+
+impl Shift {
+    /// Moves the position, which is after the edit, along with its text.
+    fn apply(&self, pos: &mut Position) {
+        match self {
+            Self::Inserted(at) if pos.y == at.y && pos.x >= at.x => pos.x = pos.x.saturating_add(1),
+            Self::Split(at) if pos.y == at.y && pos.x >= at.x => {
+                pos.x = pos.x.saturating_sub(at.x);
+                pos.y = pos.y.saturating_add(1);
+            }
+            Self::Split(at) if pos.y > at.y => pos.y = pos.y.saturating_add(1),
+            Self::Deleted(at) if pos.y == at.y && pos.x > at.x => pos.x = pos.x.saturating_sub(1),
+            Self::Joined { y, len } if pos.y == y.saturating_add(1) => {
+                pos.x = pos.x.saturating_add(*len);
+                pos.y = *y;
+            }
+            Self::Joined { y, .. } if pos.y > y.saturating_add(1) => {
+                pos.y = pos.y.saturating_sub(1);
+            }
+            _ => (),
+        }
+    }
+}
+
 struct StatusMessage {
     text: String,
     time: Instant,
@@ -33,6 +174,11 @@ impl StatusMessage {
         }
     }
 
+    /// Whether the message is still to be shown after `timeout_secs` seconds, where 0 means forever.
+    fn is_visible(&self, timeout_secs: u64) -> bool {
+        timeout_secs == 0 || self.time.elapsed() < Duration::from_secs(timeout_secs)
+    }
+
     /// A shorthand for `StatusMessage::from(String::new())`.
     fn clear(&mut self) {
         self.text.clear();
@@ -40,47 +186,211 @@ impl StatusMessage {
     }
 }
 
+/// A file open besides the current one, along with where the user was in it.
+#[derive(Default)]
+struct Buffer {
+    document: Document,
+    cursor_position: Position,
+    offset: Position,
+    folds: Folds,
+    jumps: JumpList,
+}
+
+/// The other pane of a split, which shows the same document, along with which of the two panes
+/// is focused.
+struct Split {
+    /// Where the other pane is in the document.
+    cursor_position: Position,
+    offset: Position,
+    /// Whether the focused pane is the top one.
+    is_top_focused: bool,
+}
+
+/// Called whenever a key is pressed in a prompt, along with the key and the current input.
+/// Returns the input to replace the current one with, if any.
+type PromptCallback<'callback> = &'callback mut dyn FnMut(&mut Editor, Key, &str) -> Option<String>;
+
+#[allow(clippy::struct_excessive_bools)]
 pub struct Editor {
     should_quit: bool,
     terminal: Terminal,
     document: Document,
     /// Where of the file the user is currently scrolled to.
+    /// `x` is a visual column rather than a grapheme index, since tabs and wide characters take
+    /// multiple columns.
     offset: Position,
     cursor_position: Position,
+    /// The cursors besides `cursor_position`, at which typing and deleting also take place.
+    secondary_cursors: Vec<Position>,
     status_message: StatusMessage,
     quit_times: u8,
+    config: Config,
+    mode: Mode,
+    /// The first key of a two-key Normal mode command, e.g., the first `d` of `dd`.
+    pending_operator: Option<char>,
+    /// The text typed since entering Insert mode, to be recorded as the last edit.
+    inserted_text: String,
+    /// The number of closing brackets and quotes auto-paired at the end of `inserted_text` that
+    /// the cursor is still before, so that the text typed next is recorded before them.
+    paired_closers: usize,
+    last_edit: Option<RepeatableEdit>,
+    /// The column of the caret in the message bar while prompting.
+    prompt_cursor_column: Option<usize>,
+    /// Hides the trailing whitespace of the rows without modifying them.
+    is_clean_view: bool,
+    /// Where the selection started; the rows from here to the cursor are selected.
+    selection_anchor: Option<Position>,
+    gutter_mode: GutterMode,
+    /// The rows collapsed into a line each.
+    folds: Folds,
+    /// The positions jumped from, gone back and forth through by Ctrl-O and Ctrl-I.
+    jumps: JumpList,
+    /// The text deleted last by `kill_line`, inserted back by `yank`.
+    clipboard: String,
+    /// Whether the last key killed text, so that killing again adds to the clipboard rather than
+    /// replacing it, as in Emacs.
+    is_after_kill: bool,
+    /// The other open files, in the order `next_buffer` goes through them.
+    buffers: Vec<Buffer>,
+    /// The names of the files closed, the latest last, along with where the cursor was in them.
+    closed_buffers: Vec<(String, Position)>,
+    /// Follows the file as it grows while on, appending the lines appended to it.
+    tail: Option<Tail>,
+    /// The locations of errors loaded from an error file, gone through by `next_error`.
+    errors: ErrorList,
+    /// The text area split into two panes, if it is.
+    split: Option<Split>,
+    /// The status bar and the message bar as last drawn, which are drawn again only once they
+    /// change.
+    drawn_status_bar: Option<String>,
+    drawn_message: Option<String>,
+    /// Draws the trailing whitespace of the rows on a warning background.
+    marks_trailing_whitespace: bool,
+    /// Draws the part of the rows past `max_line_length`, if set, on a warning background.
+    marks_long_lines: bool,
+    /// The number of characters typed into the current undo step, which is ended by anything but
+    /// typing a word.
+    typed_in_undo_step: usize,
+    /// The recorded macros by their registers. Pastes are kept whole, so that they're replayed as
+    /// pastes rather than typed key by key.
+    macros: HashMap<char, Vec<Input>>,
+    /// The register being recorded into, along with the inputs recorded so far.
+    recording: Option<(char, Vec<Input>)>,
+    /// The number of inputs recorded before the current key press, so that the keys that stop the
+    /// recording are left out.
+    recorded_before_keypress: usize,
+    /// The macros being replayed, the innermost last.
+    replaying: Vec<Replay>,
+    /// The wordlist of the spell checker; `None` if it can't be loaded.
+    #[cfg(feature = "spell")]
+    dictionary: Option<Dictionary>,
 }
 
-impl Default for Editor {
-    fn default() -> Self {
-        let args: Vec<String> = env::args().collect();
-        let mut initial_status =
-            String::from("HELP: Ctrl-F = find | Ctrl-S = save | Ctrl-Q = quit");
-        let document = if let Some(filename) = args.get(1) {
-            if let Ok(doc) = Document::open(filename) {
-                doc
-            } else {
-                initial_status = format!("ERR: Could not open file: {filename}");
+impl Editor {
+    /// Creates the editor with the file of the arguments opened, if any.
+    pub fn new(args: Args) -> Self {
+        let Args {
+            filename,
+            encoding,
+            errors,
+            line,
+            column,
+            ..
+        } = args;
+        // Read before the terminal is set up, as the keys are read from it instead of the standard
+        // input once that's piped errors.
+        let errors = errors.map(|filename| (read_errors(&filename), filename));
+        let config = Config::load();
+        let mut open_error = None;
+        let document = if let Some(filename) = filename {
+            open_document(&filename, encoding.as_deref(), &config).unwrap_or_else(|err| {
+                open_error = Some(format!("ERR: Could not open file: {filename} ({err})"));
                 Document::default()
-            }
+            })
         } else {
             Document::default()
         };
+        #[allow(clippy::expect_used)]
+        let terminal = Terminal::new().expect("Failed to initialize terminal");
+        let mut editor = Self::with(terminal, document, config);
+        match errors {
+            Some((Ok(text), filename)) => editor.set_errors(&text, &filename),
+            Some((Err(err), filename)) => {
+                editor.status_message =
+                    StatusMessage::from(format!("ERR: Could not read file: {filename} ({err})"));
+            }
+            None => (),
+        }
+        if let Some(open_error) = open_error {
+            editor.status_message = StatusMessage::from(open_error);
+        }
+        // The position of the arguments is 1-based, and clamped to the document.
+        editor.cursor_position = editor.clamp_to_document(&Position {
+            x: column.unwrap_or(1).saturating_sub(1),
+            y: line.unwrap_or(1).saturating_sub(1),
+        });
+        editor.scroll();
+        editor
+    }
+
+    /// Creates the editor with the document open in the terminal, with the cursor at the top-left
+    /// corner.
+    fn with(terminal: Terminal, document: Document, mut config: Config) -> Self {
+        let keymap = &config.keymap;
+        let initial_status = format!(
+            "HELP: {} = find | {} = save | {} = quit",
+            keymap.find, keymap.save, keymap.quit
+        );
+        config.seed_indent(document.indent_style());
+        #[cfg(feature = "spell")]
+        let dictionary = Dictionary::load(&config.spell_dictionary).ok();
+        let mode = if config.modal {
+            Mode::Normal
+        } else {
+            Mode::Insert
+        };
         Self {
             should_quit: false,
-            #[allow(clippy::expect_used)]
-            terminal: Terminal::new().expect("Failed to initialize terminal"),
+            terminal,
             document,
             offset: Position::default(),
-            // top-left corner
             cursor_position: Position::default(),
+            secondary_cursors: Vec::new(),
             status_message: StatusMessage::from(initial_status),
             quit_times: QUIT_TIMES,
+            config,
+            mode,
+            pending_operator: None,
+            inserted_text: String::new(),
+            paired_closers: 0,
+            last_edit: None,
+            prompt_cursor_column: None,
+            is_clean_view: false,
+            selection_anchor: None,
+            gutter_mode: GutterMode::default(),
+            folds: Folds::default(),
+            jumps: JumpList::default(),
+            clipboard: String::new(),
+            is_after_kill: false,
+            buffers: Vec::new(),
+            closed_buffers: Vec::new(),
+            tail: None,
+            errors: ErrorList::default(),
+            split: None,
+            drawn_status_bar: None,
+            drawn_message: None,
+            marks_trailing_whitespace: false,
+            marks_long_lines: true,
+            typed_in_undo_step: 0,
+            macros: HashMap::new(),
+            recording: None,
+            recorded_before_keypress: 0,
+            replaying: Vec::new(),
+            #[cfg(feature = "spell")]
+            dictionary,
         }
     }
-}
 
-impl Editor {
     pub fn run(&mut self) {
         loop {
             // NOTE: The screen is refreshed before quitting.
@@ -90,9 +400,13 @@ impl Editor {
             if self.should_quit {
                 break;
             }
+            if let Err(e) = &self.wait_for_input() {
+                die(e);
+            }
             if let Err(e) = &self.process_keypress() {
                 die(e);
             }
+            self.stop_tail_if_edited();
         }
     }
 
@@ -103,101 +417,373 @@ impl Editor {
             Terminal::clear_screen();
             println!("Goodbye.\r");
         } else {
-            self.document.highlight_until(
-                self.offset
-                    .y
-                    .saturating_add(self.terminal.size().height as usize),
-            );
-            self.draw_rows();
+            self.draw_panes();
             self.draw_status_bar();
             self.draw_message_bar();
+            self.draw_secondary_cursors();
             let cursor_pos_relative_to_offset = Position {
-                x: self.cursor_position.x.saturating_sub(self.offset.x),
-                y: self.cursor_position.y.saturating_sub(self.offset.y),
+                x: self
+                    .cursor_column()
+                    .saturating_sub(self.offset.x)
+                    .saturating_add(self.gutter_width()),
+                y: self
+                    .screen_row(self.cursor_position.y)
+                    .saturating_add(self.view().start),
             };
-            Terminal::cursor_position(&cursor_pos_relative_to_offset);
+            if let Some(x) = self.prompt_cursor_column {
+                // The cursor is at the caret of the prompt in the message bar.
+                let y = (self.terminal.text_area_size().height as usize).saturating_add(1);
+                Terminal::cursor_position(&Position { x, y });
+            } else {
+                Terminal::cursor_position(&cursor_pos_relative_to_offset);
+            }
         }
+        self.terminal.set_cursor_shape(match self.mode {
+            Mode::Normal => CursorShape::Block,
+            Mode::Insert => self.config.cursor_shape,
+        });
         Terminal::cursor_show();
         Terminal::flush()
     }
 
+    /// Draws the panes from the top down, with a line between them if split.
+    fn draw_panes(&mut self) {
+        let is_top_focused = self.split.as_ref().is_none_or(|split| split.is_top_focused);
+        if !is_top_focused {
+            self.draw_other_pane();
+            self.draw_split_line();
+        }
+        self.draw_pane();
+        if is_top_focused && self.split.is_some() {
+            self.draw_split_line();
+            self.draw_other_pane();
+        }
+    }
+
+    /// Draws the rows in view of the focused pane, highlighted.
+    fn draw_pane(&mut self) {
+        let visible_end = self.visible_end();
+        self.document.highlight_until(visible_end);
+        #[cfg(feature = "spell")]
+        if let Some(dictionary) = &self.dictionary {
+            self.document
+                .highlight_misspelled(self.offset.y..visible_end, dictionary);
+        }
+        self.draw_rows();
+    }
+
+    /// Draws the pane that isn't focused, without the selection, which is of the focused one.
+    fn draw_other_pane(&mut self) {
+        let selection_anchor = self.selection_anchor.take();
+        self.swap_panes();
+        // The document may have been changed through the focused pane, so the cursor may be past
+        // the end of it. It's clamped rather than scrolled to, which would unfold around it.
+        self.cursor_position = self.clamp_to_document(&self.cursor_position);
+        self.offset.y = cmp::min(self.offset.y, self.cursor_position.y);
+        self.draw_pane();
+        self.swap_panes();
+        self.selection_anchor = selection_anchor;
+    }
+
+    fn draw_split_line(&self) {
+        Terminal::clear_current_line();
+        println!(
+            "{}{}{}\r",
+            color::Fg(color::LightBlack),
+            "-".repeat(self.terminal.size().width as usize),
+            color::Fg(color::Reset)
+        );
+    }
+
+    /// The rows of the screen taken by the focused pane: the whole text area unless split.
+    fn view(&self) -> Range<usize> {
+        let height = self.terminal.text_area_size().height as usize;
+        match &self.split {
+            None => 0..height,
+            Some(split) => {
+                let (top, bottom) = split_layout(height);
+                if split.is_top_focused {
+                    top
+                } else {
+                    bottom
+                }
+            }
+        }
+    }
+
     /// If the row exists, draw it.
-    /// Otherwise, draw a tilde, meaning that row is not part of the document and
-    /// can't contain any text.
+    /// Otherwise, draw the empty row marker, a tilde by default, meaning that row is not part of
+    /// the document and can't contain any text.
     fn draw_rows(&self) {
-        let height = self.terminal.size().height;
+        let height = self.view().len();
+        // XXX: Should we draw the welcome message if we do open an empty file?
+        // Also after deleting everything, which leaves an empty row.
+        let welcome_lines = if self.document.has_content() {
+            Vec::new()
+        } else {
+            self.welcome_lines()
+        };
+        let rows = self
+            .document
+            .rows_in_range(self.offset.y, self.visible_end());
+        let first_line = self.folds.visual_line(self.offset.y);
         // The last line is kept empty for the status bar.
         for term_row in 0..height {
             Terminal::clear_current_line();
+            let y = self
+                .folds
+                .row_at_visual_line(first_line.saturating_add(term_row));
             // If such row exists, draw it.
             #[allow(clippy::integer_division)]
-            if let Some(row) = self
-                .document
-                .row(self.offset.y.saturating_add(term_row as usize))
+            if let Some(row) = rows.get(y.saturating_sub(self.offset.y)) {
+                if self.gutter_mode != GutterMode::Off {
+                    let width = self.gutter_width().saturating_sub(1);
+                    let number = self.line_number(y);
+                    print!(
+                        "{}{number:>width$} {}",
+                        color::Fg(color::LightBlack),
+                        color::Fg(color::Reset)
+                    );
+                }
+                let is_selected = self.selected_rows().is_some_and(|rows| rows.contains(&y));
+                if is_selected {
+                    Terminal::set_bg_color(SELECTION_BG_COLOR);
+                }
+                if let Some(fold) = self.folds.fold_at(y) {
+                    self.draw_fold_marker(fold);
+                } else {
+                    self.draw_row(row);
+                }
+                if is_selected {
+                    Terminal::reset_bg_color();
+                }
+            } else if let Some(line) = term_row
+                .checked_sub(height / 3)
+                .and_then(|index| welcome_lines.get(index))
             {
-                self.draw_row(row);
-            } else if self.document.is_empty() && term_row == height / 3 {
-                // XXX: Should we draw the welcome message if we do open an empty file?
-                self.draw_welcome_message();
+                self.draw_welcome_message(line);
             } else {
-                println!("~\r");
+                println!("{}\r", self.config.empty_row_marker);
             }
         }
     }
 
-    fn draw_welcome_message(&self) {
-        let mut welcome_msg = format!("Hecto editor -- version {VERSION}");
+    /// Draws the line of a fold in place of its rows, e.g., `+-- 12 lines --`.
+    fn draw_fold_marker(&self, fold: &RangeInclusive<usize>) {
+        let mut marker = format!(
+            "+-- {} lines --",
+            fold.end().saturating_sub(*fold.start()).saturating_add(1)
+        );
+        marker.truncate(self.text_width());
+        println!(
+            "{}{marker}{}\r",
+            color::Fg(color::LightBlack),
+            color::Fg(color::Reset)
+        );
+    }
+
+    /// The row after the last one on the screen.
+    fn visible_end(&self) -> usize {
+        let height = self.view().len();
+        self.folds
+            .row_at_visual_line(self.folds.visual_line(self.offset.y).saturating_add(height))
+    }
+
+    /// The line of the text area at which the row is shown, with the folds above it taken into
+    /// account.
+    fn screen_row(&self, y: usize) -> usize {
+        self.folds
+            .visual_line(y)
+            .saturating_sub(self.folds.visual_line(self.offset.y))
+    }
+
+    /// The version, followed by the keys of the common commands as currently bound.
+    fn welcome_lines(&self) -> Vec<String> {
+        let keymap = &self.config.keymap;
+        vec![
+            format!("Hecto editor -- version {VERSION}"),
+            String::new(),
+            format!("{} to save", keymap.save),
+            format!("{} to quit", keymap.quit),
+            format!("{} to find", keymap.find),
+        ]
+    }
+
+    /// Draws a line of the welcome message, centered.
+    fn draw_welcome_message(&self, welcome_msg: &str) {
+        println!("{}\r", self.welcome_line(welcome_msg));
+    }
+
+    /// The line of the welcome message centered after the empty-row marker, cut at the width of
+    /// the terminal.
+    fn welcome_line(&self, welcome_msg: &str) -> String {
         let term_width = self.terminal.size().width as usize;
         let msg_len = welcome_msg.len();
         // The padding is the number of spaces to add to the left of the message.
         #[allow(clippy::integer_division)]
         let padding = term_width.saturating_sub(msg_len) / 2;
-        let spaces = " ".repeat(padding.saturating_add(1 /* for ~ */));
-        welcome_msg = format!("~{spaces}{welcome_msg}\r");
-        welcome_msg.truncate(term_width);
-        println!("{welcome_msg}\r");
+        // The line starts with the marker, as the other empty rows do, which the padding makes
+        // room for.
+        let marker = &self.config.empty_row_marker;
+        let spaces = " ".repeat(padding.saturating_add(2).saturating_sub(marker.width()));
+        let mut welcome_msg = format!("{marker}{spaces}{welcome_msg}");
+        truncate_to_width(&mut welcome_msg, term_width);
+        welcome_msg
+    }
+
+    /// Draws the secondary cursors in view as inverted graphemes, since the terminal has only one
+    /// cursor, which is left to the primary one.
+    fn draw_secondary_cursors(&self) {
+        let view = self.view();
+        for cursor in &self.secondary_cursors {
+            if cursor.y < self.offset.y || self.folds.is_hidden(cursor.y) {
+                continue;
+            }
+            let screen_y = self.screen_row(cursor.y);
+            if screen_y >= view.len() {
+                continue;
+            }
+            let column = self.column_at(cursor);
+            let Some(screen_x) = column
+                .checked_sub(self.offset.x)
+                .filter(|x| *x < self.text_width())
+            else {
+                continue;
+            };
+            Terminal::cursor_position(&Position {
+                x: screen_x.saturating_add(self.gutter_width()),
+                y: screen_y.saturating_add(view.start),
+            });
+            // Tabs and the end of the line are drawn as a space.
+            let grapheme = self
+                .document
+                .grapheme_at(cursor)
+                .filter(|grapheme| *grapheme != "\t")
+                .unwrap_or(" ");
+            print!("{}{grapheme}{}", style::Invert, style::NoInvert);
+        }
     }
 
     pub fn draw_row(&self, row: &Row) {
-        let width = self.terminal.size().width as usize;
+        let width = self.text_width();
         let start = self.offset.x;
         let end = start.saturating_add(width);
-        let row = row.render(start, end);
+        let row = if self.is_clean_view {
+            row.render_trimmed(start, end, self.config.tab_width)
+        } else {
+            let max_line_length = self
+                .config
+                .max_line_length
+                .filter(|_| self.marks_long_lines);
+            row.render_marking(
+                start,
+                end,
+                self.config.tab_width,
+                self.marks_trailing_whitespace,
+                max_line_length,
+            )
+        };
         println!("{row}\r");
     }
 
     /// Where the handling logics go.
     fn process_keypress(&mut self) -> Result<(), Error> {
-        let pressed_key = Terminal::read_key()?;
+        self.recorded_before_keypress = self.recorded_len();
+        let pressed_key = match self.read_input()? {
+            Input::Key(key) => key,
+            Input::Paste(text) => {
+                self.end_undo_step_unless_typing(None);
+                self.paste(&text);
+                self.scroll_after_edit();
+                self.abort_quit();
+                return Ok(());
+            }
+        };
+        self.end_undo_step_unless_typing(Some(pressed_key));
+        self.is_after_kill &= self.is_kill_key(pressed_key);
+        // Any key other than the expected one cancels the pending operator.
+        let pending_operator = self.pending_operator.take();
+        if !self.keeps_secondary_cursors(pressed_key) {
+            self.secondary_cursors.clear();
+        }
+        // Edit what's folded in the open.
+        if self.is_edit_key(pressed_key) {
+            self.folds.unfold_at(self.cursor_position.y);
+        }
         match pressed_key {
             // NOTE: Getting a `quit` signal isn't an error.
-            Key::Ctrl('q') => {
-                #[allow(clippy::arithmetic_side_effects)]
-                if self.quit_times > 0 && self.document.is_dirty() {
-                    self.status_message = StatusMessage::from(format!(
-                        "WARN: File has unsaved changes! Press Ctrl-Q {} more times to quit.",
-                        self.quit_times
-                    ));
-                    self.quit_times -= 1;
+            key if key == self.config.keymap.quit.0 => {
+                if self.count_down_quit() {
+                    // Keep the sequence going.
                     return Ok(());
                 }
                 self.should_quit = true;
             }
-            Key::Ctrl('s') => self.save(),
-            Key::Ctrl('f') => self.search(),
-            Key::Char(c) => {
-                self.document.insert(&self.cursor_position, c);
-                // So that we don't insert backward.
-                self.move_cursor(Key::Right);
+            // The result is already reported in the message bar.
+            key if key == self.config.keymap.save.0 => drop(self.save()),
+            key if key == self.config.keymap.command_palette.0 => self.command_palette(),
+            key if key == self.config.keymap.find.0 => self.search(),
+            key if key == self.config.keymap.undo.0 => self.undo(),
+            key if key == self.config.keymap.redo.0 => self.redo(),
+            key if key == self.config.keymap.other_pane.0 => self.focus_other_pane(),
+            key if key == self.config.keymap.close_pane.0 => self.close_pane(),
+            key if key == self.config.keymap.kill_line.0 => self.kill_line(true),
+            key if key == self.config.keymap.kill_line_backward.0 => self.kill_line(false),
+            Key::Ctrl('r') if self.mode == Mode::Normal => self.redo(),
+            Key::Ctrl('o') => self.jump_back(),
+            // The unshifted `<` and `>` keys, since arrows with modifiers aren't recognized.
+            Key::Alt(',') => {
+                self.scroll_horizontal_page(false);
+                // The viewport is moved on purpose; don't snap it back to the cursor.
+                return Ok(());
             }
-            Key::Delete => self.document.delete(&self.cursor_position),
-            // Backspace is a combination of going left and deleting.
-            Key::Backspace => {
-                if self.cursor_position.x > 0 || self.cursor_position.y > 0 {
-                    self.move_cursor(Key::Left);
-                    self.document.delete(&self.cursor_position);
+            Key::Alt('.') => {
+                self.scroll_horizontal_page(true);
+                return Ok(());
+            }
+            Key::Char(c) if self.mode == Mode::Normal => {
+                self.process_normal_command(c, pending_operator);
+            }
+            Key::Esc if self.config.modal => {
+                if self.mode == Mode::Insert && !self.inserted_text.is_empty() {
+                    self.last_edit =
+                        Some(RepeatableEdit::Insert(mem::take(&mut self.inserted_text)));
+                }
+                self.paired_closers = 0;
+                self.mode = Mode::Normal;
+            }
+            Key::Ctrl('v') => self.insert_literal()?,
+            // Ctrl-Space, which sets the mark in Emacs.
+            Key::Null => self.toggle_selection(),
+            // At the start of a line, Tab indents rather than inserts, which is the same unless
+            // there's a selection.
+            Key::Char('\t')
+                if self.secondary_cursors.is_empty()
+                    && (self.selected_rows().is_some()
+                        || (self.cursor_position.x == 0
+                            && self.cursor_position.y < self.document.len())) =>
+            {
+                self.indent_selection();
+            }
+            Key::BackTab => self.dedent_selection(),
+            Key::Char('\t') if self.config.soft_tabs => {
+                // Fill up to the next tab stop, as a hard tab would.
+                let tab_width = cmp::max(self.config.tab_width, 1);
+                #[allow(clippy::arithmetic_side_effects)] // `tab_width` is non-zero.
+                let spaces = tab_width - self.cursor_column() % tab_width;
+                for _ in 0..spaces {
+                    self.insert_char(' ');
                 }
             }
+            Key::Char(c) => self.insert_char(c),
+            Key::Delete if !self.secondary_cursors.is_empty() => self.delete_at_cursors(false),
+            Key::Delete => self.document.delete(&self.cursor_position),
+            Key::Backspace if self.mode == Mode::Normal => self.move_cursor(Key::Left),
+            Key::Backspace if !self.secondary_cursors.is_empty() => {
+                self.unrecord_inserted_char();
+                self.delete_at_cursors(true);
+            }
+            Key::Backspace => self.delete_backward(),
             Key::Up
             | Key::Down
             | Key::Left
@@ -205,304 +791,4123 @@ impl Editor {
             | Key::PageUp
             | Key::PageDown
             | Key::End
-            | Key::Home => self.move_cursor(pressed_key),
+            | Key::Home => self.move_cursor_or_bell(pressed_key),
             _ => (),
         }
-        self.scroll();
-        // The user aborted the quit sequence.
+        self.scroll_with(self.is_edit_key(pressed_key));
+        // Moving around doesn't abort the quit sequence.
+        if !self.is_navigation(pressed_key) {
+            self.abort_quit();
+        }
+        Ok(())
+    }
+
+    /// Counts down the quit sequence if quitting has to be confirmed by pressing the quit key
+    /// repeatedly, returning whether it did; quitting goes ahead once it's done.
+    fn count_down_quit(&mut self) -> bool {
+        let has_unsaved_changes = self.has_unsaved_changes();
+        if self.quit_times == 0 || !(has_unsaved_changes || self.config.confirm_quit_always) {
+            return false;
+        }
+        let reason = if has_unsaved_changes {
+            "WARN: File has unsaved changes! "
+        } else {
+            ""
+        };
+        self.status_message = StatusMessage::from(format!(
+            "{reason}Press {} {} more times to quit.",
+            self.config.keymap.quit, self.quit_times
+        ));
+        self.quit_times = self.quit_times.saturating_sub(1);
+        true
+    }
+
+    /// Resets the quit sequence, if any, since the user is doing something else.
+    fn abort_quit(&mut self) {
         if self.quit_times < QUIT_TIMES {
             self.quit_times = QUIT_TIMES;
             self.status_message.clear();
         }
-        Ok(())
     }
 
-    fn scroll(&mut self) {
-        let Position { x, y } = self.cursor_position;
-        let width = self.terminal.size().width as usize;
-        let height = self.terminal.size().height as usize;
+    /// Inserts the pasted text as is, rather than handling it key by key.
+    fn paste(&mut self, text: &str) {
+        // Terminals usually send line breaks as carriage returns.
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+        // Killing after it starts the clipboard anew.
+        self.is_after_kill = false;
+        self.record_inserted(&text);
+        self.document.insert_str(&self.cursor_position, &text);
+        self.cursor_position = Document::position_after(&self.cursor_position, &text);
+    }
 
-        // Check if the cursor has moved outside of the visible window,
-        // and if so, adjust offset so that the cursor is just inside the visible window.
-        if y < self.offset.y {
-            self.offset.y = y;
-        } else if y >= self.offset.y.saturating_add(height) {
-            self.offset.y = y.saturating_sub(height).saturating_add(1);
+    /// Inserts the character at the cursor and moves the cursor after it.
+    fn insert_char(&mut self, c: char) {
+        if c == '\n'
+            && self.config.auto_indent
+            && self.secondary_cursors.is_empty()
+            && self.cursor_position.y < self.document.len()
+        {
+            self.insert_newline_indented();
+            return;
         }
-        if x < self.offset.x {
-            self.offset.x = x;
-        } else if x >= self.offset.x.saturating_add(width) {
-            self.offset.x = x.saturating_sub(width).saturating_add(1);
+        if self.config.auto_pair && self.secondary_cursors.is_empty() && self.insert_paired(c) {
+            return;
         }
+        self.record_inserted(&c.to_string());
+        if !self.secondary_cursors.is_empty() {
+            self.insert_at_cursors(c);
+            return;
+        }
+        self.document.insert(&self.cursor_position, c);
+        // So that we don't insert backward.
+        self.move_cursor(Key::Right);
     }
 
-    fn move_cursor(&mut self, key: Key) {
-        let Position { mut x, mut y } = self.cursor_position;
-        let term_height = self.terminal.size().height as usize;
-        // The cursor is allowed to move to the last row of the document.
-        let doc_height = self.document.len();
-        let mut row_width = if let Some(row) = self.document.row(y) {
-            row.len()
+    /// Breaks the line at the cursor, indenting the new line as the broken one. In code, a line
+    /// broken after an opening bracket is indented by one more level, and a closing bracket right
+    /// after the cursor goes on a line of its own, back at the indentation of the opening one.
+    fn insert_newline_indented(&mut self) {
+        let at = self.cursor_position.clone();
+        let Some(row) = self.document.row(at.y) else {
+            return;
+        };
+        let mut graphemes = row.as_str().graphemes(true);
+        let before: String = graphemes.by_ref().take(at.x).collect();
+        let after = graphemes.as_str();
+        // Only the indentation before the cursor is kept, e.g., none if breaking at the start.
+        // The indentation is spaces and tabs, of a byte and a grapheme each.
+        let leading = row.leading_whitespace();
+        let indent = if row.is_blank() && self.config.auto_indent_from_nearest {
+            self.new_line_indent(at.y)
         } else {
-            0
+            leading
+                .get(..cmp::min(leading.len(), at.x))
+                .unwrap_or_default()
         };
-        match key {
-            Key::Up => y = y.saturating_sub(1),
-            Key::Down => {
-                // Prevent the cursor from keep going down after the last row.
-                if y < doc_height {
-                    y = y.saturating_add(1);
-                }
-            }
-            #[allow(clippy::arithmetic_side_effects)]
-            Key::Left => {
-                if x > 0 {
-                    x -= 1;
-                } else if y > 0 {
-                    // Left at the beginning of the line moves to the end of the previous line.
-                    y -= 1;
-                    if let Some(row) = self.document.row(y) {
-                        x = row.len();
-                    } else {
-                        x = 0;
-                    }
-                }
-            }
-            #[allow(clippy::arithmetic_side_effects)]
-            Key::Right => {
-                // Right at the end of the line moves to the beginning of the next line.
-                if x < row_width {
-                    x += 1;
-                } else if y < doc_height {
-                    y += 1;
-                    x = 0;
-                }
+        let closing = before
+            .trim_end()
+            .chars()
+            .last()
+            .and_then(closing_bracket)
+            .filter(|_| !self.document.is_prose());
+        let mut inserted = format!("\n{indent}");
+        if closing.is_some() {
+            inserted.push_str(&self.indent_unit_like(indent));
+        }
+        let cursor = Position {
+            // The indentation is whitespace, of a grapheme per character.
+            x: inserted.chars().count().saturating_sub(1),
+            y: at.y.saturating_add(1),
+        };
+        if closing.is_some() && after.trim_start().chars().next() == closing {
+            inserted.push('\n');
+            inserted.push_str(indent);
+        }
+        self.record_inserted(&inserted);
+        self.document.apply(Edit::InsertStr { at, text: inserted });
+        self.cursor_position = cursor;
+    }
+
+    /// The indentation of a line opened next to row `y` with `auto_indent`: that of the row, or,
+    /// with `auto_indent_from_nearest`, that of the nearest non-blank row from it upward.
+    fn new_line_indent(&self, y: usize) -> &str {
+        if !self.config.auto_indent {
+            return "";
+        }
+        let nearest = if self.config.auto_indent_from_nearest {
+            (0..=y)
+                .rev()
+                .filter_map(|y| self.document.row(y))
+                .find(|row| !row.is_blank())
+        } else {
+            None
+        };
+        nearest
+            .or_else(|| self.document.row(y))
+            .map_or("", Row::leading_whitespace)
+    }
+
+    /// Opens a line below or above the current one, indented as it with `auto_indent`, and enters
+    /// Insert mode on it.
+    fn open_line(&mut self, below: bool) {
+        let y = self.cursor_position.y;
+        let indent = self.new_line_indent(y).to_owned();
+        let (edit, cursor_y) = if y >= self.document.len() {
+            // The line below the last row, where the cursor can be, is opened by being added
+            // rather than by breaking it, which would add another line.
+            (
+                Edit::InsertRow {
+                    y,
+                    text: indent.clone(),
+                },
+                y,
+            )
+        } else if below {
+            let at = Position {
+                x: self.document.row_len(y),
+                y,
+            };
+            let text = format!("\n{indent}");
+            (Edit::InsertStr { at, text }, y.saturating_add(1))
+        } else {
+            let text = format!("{indent}\n");
+            let at = Position { x: 0, y };
+            (Edit::InsertStr { at, text }, y)
+        };
+        self.document.apply(edit);
+        self.cursor_position = Position {
+            // The indentation is whitespace, of a grapheme per character.
+            x: indent.chars().count(),
+            y: cursor_y,
+        };
+        self.inserted_text.clear();
+        self.paired_closers = 0;
+        self.mode = Mode::Insert;
+        self.scroll();
+    }
+
+    /// Inserts the opening bracket or quote along with its closing one, with the cursor in between,
+    /// or types over the closing one right at the cursor. Returns `false` if the character isn't
+    /// paired, e.g., a quote after a word character with `auto_pair_skip_after_word`.
+    fn insert_paired(&mut self, c: char) -> bool {
+        let at = self.cursor_position.clone();
+        let row = self.document.row(at.y);
+        let next = row.and_then(|row| row.grapheme_at(at.x));
+        let before = at.x.checked_sub(1);
+        if matches!(c, ')' | ']' | '}' | '"' | '\'') && next.and_then(single_char) == Some(c) {
+            // Typing over an auto-paired closing one, which is already recorded.
+            if self.paired_closers > 0 {
+                self.paired_closers = self.paired_closers.saturating_sub(1);
+            } else {
+                self.record_inserted(&c.to_string());
             }
-            Key::PageUp => {
-                y = {
-                    if y > term_height {
-                        y.saturating_sub(term_height)
-                    } else {
-                        0
-                    }
+            self.move_cursor(Key::Right);
+            return true;
+        }
+        let closing = match c {
+            '"' | '\'' => {
+                let is_after_word =
+                    before
+                        .and_then(|x| row?.grapheme_at(x))
+                        .is_some_and(|grapheme| {
+                            grapheme.chars().all(|c| c.is_alphanumeric() || c == '_')
+                        });
+                let is_in_string =
+                    before.is_some_and(|x| row.is_some_and(|row| row.is_in_string(x)));
+                if (is_after_word && self.config.auto_pair_skip_after_word)
+                    || (is_in_string && self.config.auto_pair_skip_in_string)
+                {
+                    return false;
                 }
+                c
             }
-            Key::PageDown => {
-                y = {
-                    if y.saturating_add(term_height) < doc_height {
-                        y.saturating_add(term_height)
-                    } else {
-                        doc_height
-                    }
-                }
+            _ => {
+                let Some(closing) = closing_bracket(c) else {
+                    return false;
+                };
+                closing
             }
-            Key::Home => x = 0,
-            Key::End => x = row_width,
-            _ => (),
-        }
-        // Users may move the cursor from a long line to a short line.
-        // We have to prevent the cursor from going beyond the end of the line.
-        row_width = if let Some(row) = self.document.row(y) {
-            row.len()
-        } else {
-            0
         };
-        if x > row_width {
-            x = row_width;
+        let pair = format!("{c}{closing}");
+        self.record_inserted(&pair);
+        if self.config.modal {
+            self.paired_closers = self.paired_closers.saturating_add(1);
         }
+        self.document.apply(Edit::InsertStr { at, text: pair });
+        self.move_cursor(Key::Right);
+        true
+    }
 
-        self.cursor_position = Position { x, y };
+    /// Records the text typed in Insert mode for the dot command, before the auto-paired closing
+    /// characters that the cursor is before.
+    fn record_inserted(&mut self, text: &str) {
+        if !self.config.modal {
+            return;
+        }
+        // The closing characters are ASCII, of a byte each.
+        let at = self.inserted_text.len().saturating_sub(self.paired_closers);
+        self.inserted_text.insert_str(at, text);
     }
 
-    fn draw_status_bar(&self) {
-        let modified_indicator = if self.document.is_dirty() {
-            " (modified)"
+    /// Forgets the last character recorded by `record_inserted`, as deleted by Backspace.
+    fn unrecord_inserted_char(&mut self) {
+        let at = self.inserted_text.len().saturating_sub(self.paired_closers);
+        if let Some((index, _)) = self
+            .inserted_text
+            .get(..at)
+            .and_then(|before| before.char_indices().next_back())
+        {
+            self.inserted_text.remove(index);
+        }
+    }
+
+    /// One level of indentation: a tab, or a tab width of spaces with `soft_tabs`.
+    fn indent_unit(&self) -> String {
+        if self.config.soft_tabs {
+            " ".repeat(cmp::max(self.config.tab_width, 1))
         } else {
-            ""
-        };
-        let filename = if let Some(name) = &self.document.filename {
-            let mut name = name.clone();
-            name.truncate(20);
-            name
+            "\t".to_owned()
+        }
+    }
+
+    /// One level of indentation of the same kind as the indentation, so that a line indented
+    /// with tabs is indented further with a tab regardless of `soft_tabs`, and one indented with
+    /// spaces with spaces; `indent_unit` if there's no indentation.
+    fn indent_unit_like(&self, indent: &str) -> String {
+        if indent.starts_with('\t') {
+            "\t".to_owned()
+        } else if indent.starts_with(' ') {
+            " ".repeat(cmp::max(self.config.tab_width, 1))
         } else {
-            "[No Name]".to_owned()
-        };
-        let mut status = format!(
-            "{filename} - {} lines{modified_indicator}",
-            self.document.len()
-        );
-        let line_indicator = format!(
-            "{} | {}/{}",
-            self.document.file_type(),
-            self.cursor_position.y.saturating_add(1), /* 1-based */
-            self.document.len()
-        );
-        #[allow(clippy::arithmetic_side_effects)]
-        let len = status.len() + line_indicator.len();
-        let term_width = self.terminal.size().width as usize;
-        status.push_str(&" ".repeat(term_width.saturating_sub(len)));
-        // XXX: Isn't status always less than or equal to term_width?
-        status.truncate(term_width);
-        // The current line number is aligned to the right edge.
-        status = format!("{status}{line_indicator}");
-        Terminal::set_bg_color(STATUS_BG_COLOR);
-        Terminal::set_fg_color(STATUS_FG_COLOR);
-        println!("{status}\r");
-        Terminal::reset_bg_color();
-        Terminal::reset_fg_color();
+            self.indent_unit()
+        }
     }
 
-    fn draw_message_bar(&self) {
-        Terminal::clear_current_line();
-        let message = &self.status_message;
-        if message.time.elapsed() < Duration::from_secs(5) {
-            let mut text = message.text.clone();
-            text.truncate(self.terminal.size().width as usize);
-            print!("{text}");
+    /// Whether the key types or deletes at every cursor, or opens a prompt that may add a cursor,
+    /// rather than going back to a single cursor.
+    fn keeps_secondary_cursors(&self, key: Key) -> bool {
+        match key {
+            _ if key == self.config.keymap.command_palette.0 => true,
+            Key::Char(':') if self.mode == Mode::Normal => true,
+            _ => self.is_edit_key(key),
         }
     }
 
-    /// Prompt the user for input. `None` is returned if the user cancels the prompt.
-    /// The callback is called whenever a key is pressed, along with the key and the current input.
-    /// # Errors
-    /// Returns an error if the user input can't be read.
-    /// XXX: Optional callback?
-    fn prompt<C>(&mut self, prompt: &str, mut callback: C) -> Result<Option<String>, Error>
-    where
-        C: FnMut(&mut Self, Key, &String),
-    {
-        let mut result = String::new();
-        loop {
-            self.status_message = StatusMessage::from(format!("{prompt}{result}"));
-            self.refresh_screen()?;
-            let key = Terminal::read_key()?;
-            match key {
-                Key::Backspace => {
-                    if !result.is_empty() {
-                        result.pop();
-                    }
-                }
-                // Enter is pressed; prompt is done.
-                Key::Char('\n') => break,
-                Key::Char(c) => {
-                    if !c.is_control() {
-                        result.push(c);
-                    }
+    fn is_kill_key(&self, key: Key) -> bool {
+        key == self.config.keymap.kill_line.0 || key == self.config.keymap.kill_line_backward.0
+    }
+
+    /// Whether the key types or deletes text.
+    fn is_edit_key(&self, key: Key) -> bool {
+        matches!(
+            key,
+            Key::Char(_) | Key::Delete | Key::Backspace | Key::Ctrl('v')
+        ) && self.mode == Mode::Insert
+    }
+
+    /// Inserts the character at every cursor, moving each after it.
+    fn insert_at_cursors(&mut self, c: char) {
+        self.edit_at_cursors(|document, at| {
+            document.insert(at, c);
+            if c == '\n' {
+                let after = Position {
+                    x: 0,
+                    y: at.y.saturating_add(1),
+                };
+                (after, Shift::Split(at.clone()))
+            } else {
+                let after = Position {
+                    x: at.x.saturating_add(1),
+                    y: at.y,
+                };
+                (after, Shift::Inserted(at.clone()))
+            }
+        });
+    }
+
+    /// Deletes the grapheme at every cursor, or before it if `backward`, joining the lines at the
+    /// line boundaries as Delete and Backspace do.
+    fn delete_at_cursors(&mut self, backward: bool) {
+        self.edit_at_cursors(|document, at| {
+            let at = if !backward {
+                at.clone()
+            } else if at.x > 0 {
+                Position {
+                    x: at.x.saturating_sub(1),
+                    y: at.y,
                 }
-                Key::Esc => {
-                    result.clear();
-                    break;
+            } else if let Some(y) = at.y.checked_sub(1) {
+                let x = document.row_len(y);
+                Position { x, y }
+            } else {
+                // Nothing before the start of the document.
+                return (at.clone(), Shift::Deleted(at.clone()));
+            };
+            let row_len = document.row_len(at.y);
+            let shift = if at.x == row_len && at.y.saturating_add(1) < document.len() {
+                Shift::Joined {
+                    y: at.y,
+                    len: row_len,
                 }
-                _ => (),
+            } else {
+                Shift::Deleted(at.clone())
+            };
+            document.delete(&at);
+            (at, shift)
+        });
+    }
+
+    /// Applies the edit at every cursor from the bottom up, so that an edit never moves the cursors
+    /// yet to be edited; the ones already edited are shifted along with their text instead.
+    /// The edit returns where the cursor goes and how the text after it moves.
+    fn edit_at_cursors<F>(&mut self, mut edit: F)
+    where
+        F: FnMut(&mut Document, &Position) -> (Position, Shift),
+    {
+        let mut cursors = mem::take(&mut self.secondary_cursors);
+        cursors.push(self.cursor_position.clone());
+        cursors.sort_unstable_by_key(|cursor| cmp::Reverse((cursor.y, cursor.x)));
+        cursors.dedup();
+        let primary = cursors
+            .iter()
+            .position(|cursor| *cursor == self.cursor_position)
+            .unwrap_or_default();
+        for index in 0..cursors.len() {
+            let Some(at) = cursors.get(index) else {
+                break;
+            };
+            let (after, shift) = edit(&mut self.document, at);
+            for edited in cursors.iter_mut().take(index) {
+                shift.apply(edited);
+            }
+            if let Some(cursor) = cursors.get_mut(index) {
+                *cursor = after;
             }
-            callback(self, key, &result);
         }
+        self.cursor_position = cursors.get(primary).cloned().unwrap_or_default();
+        // Cursors that end up together, e.g., by deleting the text between them, become one.
+        cursors.dedup();
+        self.secondary_cursors = cursors
+            .into_iter()
+            .filter(|cursor| *cursor != self.cursor_position)
+            .collect();
+    }
+
+    /// Adds a cursor right below the lowest one, at the column of the primary cursor.
+    fn add_cursor_below(&mut self) {
+        let lowest = self
+            .secondary_cursors
+            .iter()
+            .chain([&self.cursor_position])
+            .map(|cursor| cursor.y)
+            .max()
+            .unwrap_or_default();
+        let y = lowest.saturating_add(1);
+        let Some(row) = self.document.row(y) else {
+            self.status_message = StatusMessage::from("No line below to add a cursor.".to_owned());
+            return;
+        };
+        let x = row.grapheme_index_at_column(self.cursor_column(), self.config.tab_width);
+        self.secondary_cursors.push(Position { x, y });
+    }
+
+    /// Reads the next key and inserts its character verbatim, e.g., a hard tab even with soft tabs.
+    fn insert_literal(&mut self) -> Result<(), Error> {
+        self.status_message = StatusMessage::from("Insert literal: press a key.".to_owned());
+        self.refresh_screen()?;
+        let key = self.read_key()?;
         self.status_message.clear();
-        if result.is_empty() {
-            Ok(None)
+        if let Some(c) = literal_char(key) {
+            self.insert_char(c);
         } else {
-            Ok(Some(result))
+            self.status_message =
+                StatusMessage::from("Cannot insert the key literally.".to_owned());
         }
+        Ok(())
     }
 
-    fn save(&mut self) {
-        // If the file has no name, prompt the user for one.
-        if self.document.filename.is_none() {
-            let new_name = self.prompt("Save as: ", |_, _, _| {}).unwrap_or(None);
-            if new_name.is_none() {
-                self.status_message = StatusMessage::from("Save aborted.".to_owned());
-                return;
+    /// Whether the key only moves the cursor, without editing the document.
+    fn is_navigation(&self, key: Key) -> bool {
+        match key {
+            Key::Up
+            | Key::Down
+            | Key::Left
+            | Key::Right
+            | Key::PageUp
+            | Key::PageDown
+            | Key::End
+            | Key::Home => true,
+            Key::Char('h' | 'j' | 'k' | 'l') | Key::Backspace => self.mode == Mode::Normal,
+            _ => false,
+        }
+    }
+
+    /// Handles a character key pressed in Normal mode.
+    /// `pending_operator` is the first key of a two-key command, if any.
+    fn process_normal_command(&mut self, c: char, pending_operator: Option<char>) {
+        match (pending_operator, c) {
+            (Some('d'), 'd') => self.perform_edit(RepeatableEdit::DeleteLine),
+            (Some('q'), register) => self.start_recording(register),
+            (Some('@'), register) => self.replay_macro(register, 1),
+            (_, 'q') if self.recording.is_some() => self.stop_recording(),
+            (_, 'q') => self.pending_operator = Some('q'),
+            (_, '@') => self.pending_operator = Some('@'),
+            // Ctrl-I, which terminals send as Tab.
+            (_, '\t') => self.jump_forward(),
+            (_, 'h') => self.move_cursor_or_bell(Key::Left),
+            (_, 'j') => self.move_cursor_or_bell(Key::Down),
+            (_, 'k') => self.move_cursor_or_bell(Key::Up),
+            (_, 'l') => self.move_cursor_or_bell(Key::Right),
+            (_, 'i') => {
+                self.inserted_text.clear();
+                self.paired_closers = 0;
+                self.mode = Mode::Insert;
+            }
+            (_, 'o') => self.open_line(true),
+            (_, 'O') => self.open_line(false),
+            (_, 'x') => self.perform_edit(RepeatableEdit::DeleteChar),
+            (_, 'J') => self.join_lines(),
+            (_, 'u') => self.undo(),
+            (_, '.') => {
+                // Nothing to repeat if no edit has been done yet.
+                if let Some(edit) = self.last_edit.clone() {
+                    self.apply_edit(&edit);
+                }
             }
-            self.document.filename = new_name;
+            (_, 'd') => self.pending_operator = Some('d'),
+            (_, ':') => self.command_line(),
+            _ => (),
         }
-        let msg = if self.document.save().is_ok() {
-            "File saved sucessfully."
-        } else {
-            "Error writing file!"
-        };
-        self.status_message = StatusMessage::from(msg.to_owned());
     }
 
-    /// Searches for a query in the document with incremental backward and forward search.
-    fn search(&mut self) {
-        let old_position = self.cursor_position.clone();
-        // We start by searching forward.
-        let mut forward = true;
-        // NOTE: Every time the query is updated, either by typing or deleting,
-        // the cursor is moved back to the old position to start a new forward search.
-        let incremental_search = |editor: &mut Self, key: Key, partial_query: &String| {
-            let mut moved = false;
-            match key {
-                Key::Char(_) | Key::Backspace => {
-                    editor.cursor_position = old_position.clone();
-                    editor.scroll();
-                    forward = true;
+    /// Applies the edit and records it for the dot command.
+    fn perform_edit(&mut self, edit: RepeatableEdit) {
+        self.apply_edit(&edit);
+        self.last_edit = Some(edit);
+    }
+
+    /// Applies the edit at the cursor position.
+    fn apply_edit(&mut self, edit: &RepeatableEdit) {
+        match edit {
+            RepeatableEdit::Insert(text) => {
+                for c in text.chars() {
+                    self.document.insert(&self.cursor_position, c);
+                    self.move_cursor(Key::Right);
                 }
-                Key::Right | Key::Down => {
-                    // NOTE: For our incremental search to not return the current position.
-                    // Will move back if no next match is found.
-                    editor.move_cursor(Key::Right);
-                    forward = true;
-                    moved = true;
+            }
+            RepeatableEdit::DeleteChar => {
+                // Unlike Delete, never joins the next line.
+                if self.cursor_position.x < self.document.row_len(self.cursor_position.y) {
+                    self.document.delete(&self.cursor_position);
                 }
-                Key::Left | Key::Up => {
-                    // The current position is excluded from the search, so will not return the current position.
-                    forward = false;
+            }
+            RepeatableEdit::DeleteLine => {
+                drop(self.document.remove_row(self.cursor_position.y));
+                // Stay on the same line, which is now the next row, if any.
+                self.move_cursor(Key::Home);
+            }
+        }
+    }
+
+    /// Ends the undo step before the key is handled, unless the key types the next character of a
+    /// word and fewer than `undo_coalesce_max` characters have been typed into the step, so that
+    /// a word is undone as a whole. `None` is for input other than a key, e.g., a paste.
+    fn end_undo_step_unless_typing(&mut self, key: Option<Key>) {
+        let is_typing =
+            self.mode == Mode::Insert && matches!(key, Some(Key::Char(c)) if !c.is_whitespace());
+        if is_typing
+            && self.typed_in_undo_step > 0
+            && self.typed_in_undo_step < self.config.undo_coalesce_max
+        {
+            self.typed_in_undo_step = self.typed_in_undo_step.saturating_add(1);
+            return;
+        }
+        self.document.end_undo_step();
+        self.typed_in_undo_step = usize::from(is_typing);
+    }
+
+    fn undo(&mut self) {
+        if let Some(position) = self.document.undo() {
+            self.cursor_position = position;
+            self.after_layout_change();
+        } else {
+            self.status_message = StatusMessage::from("Already at the oldest change.".to_owned());
+        }
+    }
+
+    fn redo(&mut self) {
+        if let Some(position) = self.document.redo() {
+            self.cursor_position = position;
+            self.after_layout_change();
+        } else {
+            self.status_message = StatusMessage::from("Already at the newest change.".to_owned());
+        }
+    }
+
+    /// Reads the next input, from the innermost macro being replayed if any. The input from the
+    /// terminal is recorded into the macro being recorded, if any.
+    fn read_input(&mut self) -> Result<Input, Error> {
+        if let Some(input) = self.next_replayed_input() {
+            return Ok(input);
+        }
+        let input = self.terminal.read_input()?;
+        if let Some((_, inputs)) = &mut self.recording {
+            inputs.push(input.clone());
+        }
+        Ok(input)
+    }
+
+    /// Like `read_input`, but with pasted text given as if typed key by key.
+    fn read_key(&mut self) -> Result<Key, Error> {
+        while let Some(input) = self.next_replayed_input() {
+            match input {
+                Input::Key(key) => return Ok(key),
+                Input::Paste(text) => {
+                    if let Some(replay) = self.replaying.last_mut() {
+                        for c in text.chars().rev() {
+                            replay.inputs.push_front(Input::Key(Key::Char(c)));
+                        }
+                    }
                 }
-                _ => (),
             }
-            let find_func = if forward {
-                Document::find_after
+        }
+        let key = self.terminal.read_key()?;
+        if let Some((_, inputs)) = &mut self.recording {
+            inputs.push(Input::Key(key));
+        }
+        Ok(key)
+    }
+
+    fn next_replayed_input(&mut self) -> Option<Input> {
+        if !self.has_replayed_inputs() {
+            return None;
+        }
+        self.replaying.last_mut()?.inputs.pop_front()
+    }
+
+    /// Whether the innermost macro being replayed has inputs left, starting its next repetition if
+    /// the current one is done.
+    fn has_replayed_inputs(&mut self) -> bool {
+        let Some(replay) = self.replaying.last_mut() else {
+            return false;
+        };
+        if replay.inputs.is_empty() && replay.remaining > 0 {
+            replay.inputs = self
+                .macros
+                .get(&replay.register)
+                .map(|inputs| inputs.iter().cloned().collect())
+                .unwrap_or_default();
+            // An empty macro would never be done repeating otherwise.
+            replay.remaining = if replay.inputs.is_empty() {
+                0
             } else {
-                Document::rfind_before
+                replay.remaining.saturating_sub(1)
             };
+        }
+        !replay.inputs.is_empty()
+    }
 
-            editor.document.highlight_restore();
+    /// The number of inputs recorded so far; 0 if not recording.
+    fn recorded_len(&self) -> usize {
+        self.recording
+            .as_ref()
+            .map_or(0, |(_, inputs)| inputs.len())
+    }
 
-            if let Some(position) =
-                find_func(&editor.document, partial_query, &editor.cursor_position)
-            {
-                editor.cursor_position = position;
-                editor.scroll();
-            } else if moved {
-                // Not found, move the offset back.
-                editor.move_cursor(Key::Left);
-            }
-            editor.document.highlight_query(partial_query);
+    /// Starts recording the keys into the register.
+    fn start_recording(&mut self, register: char) {
+        if let Some((recording, _)) = &self.recording {
+            self.status_message =
+                StatusMessage::from(format!("ERR: Already recording @{recording}."));
+            return;
+        }
+        self.recording = Some((register, Vec::new()));
+    }
+
+    /// Stops recording, leaving out the keys of the key press that stops it, e.g., the command.
+    fn stop_recording(&mut self) {
+        let Some((register, mut inputs)) = self.recording.take() else {
+            self.status_message = StatusMessage::from("ERR: Not recording.".to_owned());
+            return;
         };
+        inputs.truncate(self.recorded_before_keypress);
+        self.macros.insert(register, inputs);
+        self.status_message = StatusMessage::from(format!("Recorded @{register}."));
+    }
 
-        // Perform the search.
-        if let Some(query) = self
-            .prompt(
-                "Search (ESC to cancel, Arrows to navigate): ",
-                incremental_search,
-            )
-            .unwrap_or(None)
+    /// Replays the macro of the register `count` times by feeding its inputs back as if input.
+    /// A macro can replay other macros, but not one that is already being replayed, which would
+    /// never end.
+    fn replay_macro(&mut self, register: char, count: usize) {
+        if self
+            .replaying
+            .iter()
+            .any(|replay| replay.register == register)
         {
-            // The find is done along with the incremental search.
-            // If the find succeeds, the cursor is already at the right position.
-            // Otherwise, the cursor is moved back to the old position.
-            // However, there's a chance that we're at the old position if it's the first match.
-            // So we perform an additional forward search.
-            if self.cursor_position == old_position
-                && self
-                    .document
-                    .find_after(&query, &self.cursor_position)
-                    .is_none()
-            {
-                self.status_message = StatusMessage::from(format!("Not found: {query}"));
+            self.status_message =
+                StatusMessage::from(format!("ERR: @{register} can't replay itself."));
+            return;
+        }
+        if !self.macros.contains_key(&register) {
+            self.status_message =
+                StatusMessage::from(format!("ERR: Nothing recorded in @{register}."));
+            return;
+        }
+        self.replaying.push(Replay {
+            register,
+            inputs: VecDeque::new(),
+            remaining: count,
+        });
+        let depth = self.replaying.len();
+        // The macros replayed by this one are done before it continues, so its inputs stay on top.
+        while !self.should_quit && self.has_replayed_inputs() {
+            if let Err(e) = &self.process_keypress() {
+                die(e);
             }
-        } else {
-            self.status_message = StatusMessage::from("Search canceled.".to_owned());
-            // The user canceled the search; restore the old position.
-            self.cursor_position = old_position;
+        }
+        self.replaying.truncate(depth.saturating_sub(1));
+    }
+
+    /// Inserts the time, formatted by `date_format`, at the cursor.
+    #[cfg(feature = "datetime")]
+    fn insert_date(&mut self, now: &chrono::DateTime<chrono::Local>) {
+        let date = now.format(&self.config.date_format);
+        // An invalid format fails to display.
+        let mut text = String::new();
+        if fmt::write(&mut text, format_args!("{date}")).is_ok() {
+            self.paste(&text);
             self.scroll();
+        } else {
+            self.status_message = StatusMessage::from("ERR: Invalid date_format.".to_owned());
         }
-        self.document.highlight_restore();
     }
-}
 
-fn die(e: &Error) {
-    Terminal::clear_screen();
-    panic!("{}", e);
+    /// Prompts for and runs an Ex-like command, e.g., `:wq`.
+    fn command_line(&mut self) {
+        if let Ok(command) = self.prompt(":", None) {
+            self.run_command(&command);
+        }
+    }
+
+    /// Prompts for and runs a command by its name, e.g., `save_quit`.
+    fn command_palette(&mut self) {
+        if let Ok(command) = self.prompt("Command: ", None) {
+            self.run_command(&command);
+        }
+    }
+
+    /// Runs a command of the command line or the command palette.
+    /// The Ex-like commands are aliases of the named ones.
+    fn run_command(&mut self, command: &str) {
+        let command = command.trim();
+        let (name, argument) = command.split_once(' ').unwrap_or((command, ""));
+        // The other commands act at the primary cursor only.
+        if name != "add_cursor_below" {
+            self.secondary_cursors.clear();
+        }
+        match name {
+            "w" | "save" => drop(self.save()),
+            "save_without_final_newline" => drop(self.save_with(false)),
+            "q" | "quit" => {
+                if self.has_unsaved_changes() {
+                    self.status_message = StatusMessage::from(
+                        "ERR: No write since last change (add ! to override).".to_owned(),
+                    );
+                } else {
+                    self.should_quit = true;
+                }
+            }
+            // Discards the unsaved changes without confirmation.
+            "q!" | "force_quit" => self.should_quit = true,
+            "wq" | "x" | "save_quit" => {
+                if self.save().is_ok() {
+                    self.should_quit = true;
+                }
+            }
+            #[cfg(feature = "datetime")]
+            "insert_date" => self.insert_date(&chrono::Local::now()),
+            "add_cursor_below" => self.add_cursor_below(),
+            "record" => {
+                if let Some(register) = single_char(argument) {
+                    self.start_recording(register);
+                } else {
+                    self.status_message =
+                        StatusMessage::from("ERR: Expected a register, e.g., record a.".to_owned());
+                }
+            }
+            "stop_recording" => self.stop_recording(),
+            "replay" => {
+                let mut arguments = argument.split_whitespace();
+                let register = arguments.next().and_then(single_char);
+                let count = arguments.next().map_or(Some(1), |count| count.parse().ok());
+                if let (Some(register), Some(count)) = (register, count) {
+                    self.replay_macro(register, count);
+                } else {
+                    self.status_message = StatusMessage::from(
+                        "ERR: Expected a register and an optional count, e.g., replay a 3."
+                            .to_owned(),
+                    );
+                }
+            }
+            "new" => self.new_buffer(),
+            "diff" => self.diff_against_disk(),
+            "codepoint" => self.show_codepoints(),
+            "goto" => self.goto_line(argument.trim()),
+            "jump_back" => self.jump_back(),
+            "jump_forward" => self.jump_forward(),
+            line if line.parse::<usize>().is_ok() => self.goto_line(line),
+            "r" | "read" => self.insert_file(argument.trim()),
+            "write_selection" => self.write_selection(argument.trim()),
+            "undo" => self.undo(),
+            "redo" => self.redo(),
+            "select_word" => self.select_word(),
+            "select_line" => self.select_line(),
+            "sort" => self.sort_selection(argument.trim()),
+            "filter" => self.filter_selection(argument.trim()),
+            "duplicate" => self.duplicate_selection(),
+            "join" => self.join_lines(),
+            "kill_line" => self.kill_line(true),
+            "kill_line_backward" => self.kill_line(false),
+            "yank" => self.yank(),
+            "upper" => self.convert_case(Case::Upper),
+            "lower" => self.convert_case(Case::Lower),
+            "title" => self.convert_case(Case::Title),
+            "toggle_comment" => self.toggle_comment_selection(),
+            "line_ending" => {
+                self.status_message = StatusMessage::from(
+                    if let Ok(line_ending) = argument.trim().parse::<LineEnding>() {
+                        self.document.set_line_ending(line_ending);
+                        format!("Line endings set to {}.", line_ending.name())
+                    } else {
+                        "ERR: Expected a line ending of lf, crlf, or cr.".to_owned()
+                    },
+                );
+            }
+            _ => {
+                if !self.run_view_command(name) && !self.run_buffer_command(name, argument) {
+                    self.status_message =
+                        StatusMessage::from(format!("ERR: Not an editor command: {command}"));
+                }
+            }
+        }
+    }
+
+    /// Runs a command that changes only how the document is shown; returns `false` if there's no
+    /// such command.
+    fn run_view_command(&mut self, name: &str) -> bool {
+        match name {
+            "clean_view" => {
+                self.is_clean_view = !self.is_clean_view;
+                self.after_layout_change();
+            }
+            "trailing_whitespace" => {
+                self.marks_trailing_whitespace = !self.marks_trailing_whitespace;
+            }
+            "long_lines" => {
+                self.marks_long_lines = !self.marks_long_lines;
+            }
+            "line_numbers" => {
+                self.gutter_mode = self.gutter_mode.next();
+                self.after_layout_change();
+                self.status_message =
+                    StatusMessage::from(format!("Line numbers: {}.", self.gutter_mode.name()));
+            }
+            "fold" => self.fold(),
+            "unfold" => {
+                if !self.folds.unfold_at(self.cursor_position.y) {
+                    self.status_message = StatusMessage::from("No fold at the cursor.".to_owned());
+                }
+            }
+            "unfold_all" => self.folds.clear(),
+            "split" => self.split(),
+            "other_pane" => self.focus_other_pane(),
+            "close_pane" => self.close_pane(),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Runs a command that goes through the open files; returns `false` if there's no such
+    /// command.
+    fn run_buffer_command(&mut self, name: &str, argument: &str) -> bool {
+        match name {
+            "e" | "edit" => self.edit_file(argument.trim()),
+            "bn" | "next_buffer" => self.switch_to_buffer(0),
+            "bp" | "previous_buffer" => {
+                self.switch_to_buffer(self.buffers.len().saturating_sub(1));
+            }
+            "bd" | "close_buffer" => self.close_buffer(),
+            "reopen_buffer" => self.reopen_buffer(),
+            "ls" | "buffers" => self.list_buffers(),
+            "wa" | "save_all" => self.save_all(),
+            "tail" => self.toggle_tail(),
+            "errors" => self.load_errors(argument.trim()),
+            "cn" | "next_error" => self.go_to_error(true),
+            "cp" | "previous_error" => self.go_to_error(false),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Loads the locations of errors from the file, e.g., the saved output of a build, prompting
+    /// for it if not given. The standard input can only be read from at startup, with `--errors -`,
+    /// as the keys are read from it afterwards.
+    fn load_errors(&mut self, filename: &str) {
+        let filename = if filename.is_empty() {
+            match self.prompt_path("Error file") {
+                Ok(filename) => filename,
+                Err(_) => return,
+            }
+        } else {
+            filename.to_owned()
+        };
+        if filename == "-" {
+            self.status_message = StatusMessage::from(
+                "ERR: Errors can only be piped in at startup, with --errors -.".to_owned(),
+            );
+            return;
+        }
+        match fs::read_to_string(&filename) {
+            Ok(text) => self.set_errors(&text, &filename),
+            Err(err) => {
+                self.status_message =
+                    StatusMessage::from(format!("ERR: Could not read file: {filename} ({err})"));
+            }
+        }
+    }
+
+    /// Replaces the errors with those parsed from the text, read from the file.
+    fn set_errors(&mut self, text: &str, filename: &str) {
+        self.errors = ErrorList::parse(text);
+        self.status_message = StatusMessage::from(if self.errors.is_empty() {
+            format!("No errors in {filename}.")
+        } else {
+            format!("{} errors loaded.", self.errors.len())
+        });
+    }
+
+    /// Opens the file of the next or previous error and moves the cursor to it, showing its
+    /// message.
+    fn go_to_error(&mut self, next: bool) {
+        let location = if next {
+            self.errors.forward()
+        } else {
+            self.errors.back()
+        };
+        let Some(location) = location.cloned() else {
+            self.status_message = StatusMessage::from(if self.errors.is_empty() {
+                "No errors loaded.".to_owned()
+            } else if next {
+                "No more errors.".to_owned()
+            } else {
+                "No previous errors.".to_owned()
+            });
+            return;
+        };
+        // An error in a file that's gone is reported rather than created as an empty one.
+        if !self.open_buffer(&location.filename, false) {
+            return;
+        }
+        // The location is 1-based.
+        let at = self.clamp_to_document(&Position {
+            x: location.column.saturating_sub(1),
+            y: location.line.saturating_sub(1),
+        });
+        self.folds.unfold_at(at.y);
+        self.jump_to(at);
+        self.status_message = StatusMessage::from(format!(
+            "({}/{}) {}",
+            self.errors.current().unwrap_or_default(),
+            self.errors.len(),
+            location.message
+        ));
+    }
+
+    /// Splits the text area into two panes showing the document, focusing the top one; both start
+    /// where the cursor is. Each pane needs a row, besides the one of the line between them.
+    fn split(&mut self) {
+        if self.split.is_some() {
+            self.status_message = StatusMessage::from("ERR: Already split.".to_owned());
+            return;
+        }
+        let (top, _) = split_layout(self.terminal.text_area_size().height as usize);
+        if top.is_empty() {
+            self.status_message = StatusMessage::from("ERR: Too few rows to split.".to_owned());
+            return;
+        }
+        self.split = Some(Split {
+            cursor_position: self.cursor_position.clone(),
+            offset: self.offset.clone(),
+            is_top_focused: true,
+        });
+        self.scroll();
+    }
+
+    /// Switches the state of the focused pane with that of the other one, which then has the
+    /// focus; returns `false` if not split.
+    fn swap_panes(&mut self) -> bool {
+        let Some(split) = &mut self.split else {
+            return false;
+        };
+        mem::swap(&mut self.cursor_position, &mut split.cursor_position);
+        mem::swap(&mut self.offset, &mut split.offset);
+        split.is_top_focused = !split.is_top_focused;
+        true
+    }
+
+    fn focus_other_pane(&mut self) {
+        if self.swap_panes() {
+            self.selection_anchor = None;
+            self.after_layout_change();
+        } else {
+            self.status_message = StatusMessage::from("Not split.".to_owned());
+        }
+    }
+
+    /// Closes the focused pane, leaving the other one to take the whole text area.
+    fn close_pane(&mut self) {
+        if self.swap_panes() {
+            self.split = None;
+            self.selection_anchor = None;
+            self.after_layout_change();
+        } else {
+            self.status_message = StatusMessage::from("Not split.".to_owned());
+        }
+    }
+
+    /// Folds the selected rows, or the section around the cursor, which is delimited by blank
+    /// rows.
+    fn fold(&mut self) {
+        let rows = self
+            .selected_rows()
+            .unwrap_or_else(|| self.section_rows(self.cursor_position.y));
+        if rows.start() == rows.end() {
+            self.status_message = StatusMessage::from("Nothing to fold.".to_owned());
+            return;
+        }
+        self.folds.fold(rows, self.document.len());
+        self.selection_anchor = None;
+        if let Some(fold) = self.folds.fold_at(self.cursor_position.y) {
+            self.cursor_position = Position {
+                x: 0,
+                y: *fold.start(),
+            };
+        }
+        self.scroll();
+    }
+
+    /// The rows around `y` up to the blank rows before and after them; only `y` if it's blank.
+    fn section_rows(&self, y: usize) -> RangeInclusive<usize> {
+        let is_blank = |y: usize| self.document.row(y).is_none_or(Row::is_blank);
+        if is_blank(y) {
+            return y..=y;
+        }
+        let start = (0..y)
+            .rev()
+            .find(|y| is_blank(*y))
+            .map_or(0, |y| y.saturating_add(1));
+        let end = (y..self.document.len())
+            .find(|y| is_blank(*y))
+            .unwrap_or(self.document.len())
+            .saturating_sub(1);
+        start..=end
+    }
+
+    /// Starts selecting from the cursor, or clears the selection if there's one.
+    fn toggle_selection(&mut self) {
+        self.selection_anchor = if self.selection_anchor.is_some() {
+            None
+        } else {
+            Some(self.cursor_position.clone())
+        };
+    }
+
+    /// Selects the word under the cursor, with the cursor at its end.
+    fn select_word(&mut self) {
+        let Position { x, y } = self.cursor_position;
+        let Some(word) = self.document.row(y).and_then(|row| row.word_at(x)) else {
+            self.status_message = StatusMessage::from("No word under the cursor.".to_owned());
+            return;
+        };
+        self.selection_anchor = Some(Position { x: word.start, y });
+        self.cursor_position = Position { x: word.end, y };
+    }
+
+    /// Selects the line of the cursor, with the cursor at its end.
+    fn select_line(&mut self) {
+        let y = self.cursor_position.y;
+        let Some(row) = self.document.row(y) else {
+            self.status_message = StatusMessage::from("No line to select.".to_owned());
+            return;
+        };
+        self.selection_anchor = Some(Position { x: 0, y });
+        self.cursor_position = Position { x: row.len(), y };
+    }
+
+    /// The rows spanned by the selection, or `None` if nothing is selected.
+    /// Rows removed since the selection started are excluded.
+    fn selected_rows(&self) -> Option<RangeInclusive<usize>> {
+        let anchor = self.selection_anchor.as_ref()?;
+        let last_row = self.document.len().checked_sub(1)?;
+        let start = cmp::min(anchor.y, self.cursor_position.y);
+        let end = cmp::max(anchor.y, self.cursor_position.y);
+        (start <= last_row).then(|| start..=cmp::min(end, last_row))
+    }
+
+    /// The selected rows, or the row of the cursor if nothing is selected.
+    fn target_rows(&self) -> RangeInclusive<usize> {
+        self.selected_rows()
+            .unwrap_or(self.cursor_position.y..=self.cursor_position.y)
+    }
+
+    /// Indents the selected rows by one unit, or the row of the cursor if nothing is selected.
+    /// Empty rows in a selection are left as they are.
+    fn indent_selection(&mut self) {
+        let indent = self.indent_unit();
+        let is_selecting = self.selected_rows().is_some();
+        for y in self.target_rows() {
+            if is_selecting && self.document.row(y).is_some_and(Row::is_empty) {
+                continue;
+            }
+            self.document.indent_line(y, &indent);
+            if y == self.cursor_position.y {
+                self.cursor_position.x = self.cursor_position.x.saturating_add(indent.len());
+            }
+        }
+        if !is_selecting {
+            self.record_inserted(&indent);
+        }
+        self.scroll();
+    }
+
+    /// Dedents the selected rows by up to one unit, or the row of the cursor if nothing is selected.
+    fn dedent_selection(&mut self) {
+        for y in self.target_rows() {
+            let removed = self.document.dedent_line(y, self.config.tab_width);
+            if y == self.cursor_position.y {
+                self.cursor_position.x = self.cursor_position.x.saturating_sub(removed);
+            }
+        }
+        self.scroll();
+    }
+
+    /// Duplicates the selected rows below them, moving the selection onto the copy. Nothing is
+    /// duplicated on the line past the end of the document.
+    fn duplicate_selection(&mut self) {
+        let rows = self.target_rows();
+        #[allow(clippy::arithmetic_side_effects)] // The range isn't empty.
+        let count = rows.end() - rows.start() + 1;
+        if !self.document.duplicate_rows(rows) {
+            self.bell();
+            return;
+        }
+        if let Some(anchor) = &mut self.selection_anchor {
+            anchor.y = anchor.y.saturating_add(count);
+        }
+        self.cursor_position.y = self.cursor_position.y.saturating_add(count);
+        self.scroll();
+    }
+
+    /// Converts the case of the selected text, from the anchor to the cursor, or of the word at the
+    /// cursor.
+    fn convert_case(&mut self, case: Case) {
+        let (start, end) = if let Some(anchor) = &self.selection_anchor {
+            let anchor = self.clamp_to_document(anchor);
+            let cursor = self.clamp_to_document(&self.cursor_position);
+            if (anchor.y, anchor.x) <= (cursor.y, cursor.x) {
+                (anchor, cursor)
+            } else {
+                (cursor, anchor)
+            }
+        } else {
+            let y = self.cursor_position.y;
+            let Some(word) = self
+                .document
+                .row(y)
+                .and_then(|row| row.word_at(self.cursor_position.x))
+            else {
+                self.status_message = StatusMessage::from("No word at the cursor.".to_owned());
+                return;
+            };
+            (Position { x: word.start, y }, Position { x: word.end, y })
+        };
+        let text = self.document.text_in_range(&start, &end);
+        let converted = case.convert(&text);
+        if converted != text {
+            self.document.replace_range(start, end, converted);
+        }
+        // The case of some characters takes more or fewer graphemes, e.g., `ß` is `SS`.
+        let row_len = self.document.row_len(self.cursor_position.y);
+        self.cursor_position.x = cmp::min(self.cursor_position.x, row_len);
+    }
+
+    /// Sorts the selected rows, which stay selected, in descending order if the order is
+    /// `reverse`; without a selection, sorts the whole document once confirmed.
+    fn sort_selection(&mut self, order: &str) {
+        let reverse = match order {
+            "" => false,
+            "reverse" => true,
+            _ => {
+                self.status_message = StatusMessage::from(
+                    "ERR: Expected nothing or reverse, e.g., sort reverse.".to_owned(),
+                );
+                return;
+            }
+        };
+        let rows = if let Some(rows) = self.selected_rows() {
+            rows
+        } else {
+            let answer = self.prompt("Sort all lines? (y/n): ", None);
+            if !answer.is_ok_and(|answer| answer.eq_ignore_ascii_case("y")) {
+                self.status_message = StatusMessage::from("Sort aborted.".to_owned());
+                return;
+            }
+            0..=self.document.len().saturating_sub(1)
+        };
+        if !self
+            .document
+            .sort_lines(*rows.start(), *rows.end(), reverse)
+        {
+            self.status_message = StatusMessage::from("Already sorted.".to_owned());
+            return;
+        }
+        // The row of the cursor has changed, so the cursor may be past the end of it.
+        let row_len = self.document.row_len(self.cursor_position.y);
+        self.cursor_position.x = cmp::min(self.cursor_position.x, row_len);
+        self.scroll();
+    }
+
+    /// Pipes the selected rows, or the whole document, through the shell command and replaces them
+    /// with its output, prompting for the command if not given. If the command fails or is cancelled
+    /// with Esc or Ctrl-C, its error is shown and the document is left as it is.
+    fn filter_selection(&mut self, command: &str) {
+        let command = if command.is_empty() {
+            match self.prompt("Filter through: ", None) {
+                Ok(command) => command,
+                Err(_) => return,
+            }
+        } else {
+            command.to_owned()
+        };
+        let rows = self
+            .selected_rows()
+            .unwrap_or(0..=self.document.len().saturating_sub(1));
+        let mut input = String::new();
+        for row in self
+            .document
+            .rows_in_range(*rows.start(), rows.end().saturating_add(1))
+        {
+            input.push_str(row.as_str());
+            input.push('\n');
+        }
+        self.status_message = StatusMessage::from(format!("Running {command}... (Esc to cancel)"));
+        // The message is only for while the command runs, so failing to draw it is no reason to not
+        // run it.
+        drop(self.refresh_screen());
+        self.status_message.clear();
+        let terminal = &mut self.terminal;
+        // The keys typed ahead that aren't to cancel are kept for after the command is done.
+        match filter_cancellable(&command, &input, || terminal.is_cancel_pressed()) {
+            Ok(output) => {
+                let texts = output.lines().map(str::to_owned).collect();
+                self.document.replace_rows(rows, texts);
+                self.selection_anchor = None;
+                self.after_layout_change();
+            }
+            Err(err) => {
+                self.status_message = StatusMessage::from(format!("ERR: {command} failed: {err}"));
+            }
+        }
+    }
+
+    /// Deletes from the cursor to the end of the row, or from the start of the row to the cursor,
+    /// into the clipboard. At the end of the row, deleting forward joins the next row instead.
+    /// Killing right after a kill adds to the clipboard: after the text forward, before it backward.
+    fn kill_line(&mut self, forward: bool) {
+        let Position { x, y } = self.cursor_position;
+        let row_len = self.document.row_len(y);
+        let (start, end) = if !forward {
+            (Position { x: 0, y }, Position { x, y })
+        } else if x < row_len {
+            (Position { x, y }, Position { x: row_len, y })
+        } else {
+            let next = Position {
+                x: 0,
+                y: y.saturating_add(1),
+            };
+            (Position { x, y }, next)
+        };
+        if start == end || end.y >= self.document.len() {
+            self.bell();
+            return;
+        }
+        let killed = self.document.delete_range(start.clone(), end);
+        if !self.is_after_kill {
+            self.clipboard.clear();
+        }
+        if forward {
+            self.clipboard.push_str(&killed);
+        } else {
+            self.clipboard.insert_str(0, &killed);
+        }
+        self.is_after_kill = true;
+        self.cursor_position = start;
+    }
+
+    /// Inserts the text deleted last by `kill_line` at the cursor, moving the cursor after it.
+    fn yank(&mut self) {
+        if self.clipboard.is_empty() {
+            self.status_message = StatusMessage::from("Nothing to yank.".to_owned());
+            return;
+        }
+        let text = self.clipboard.clone();
+        self.paste(&text);
+    }
+
+    /// Joins the selected rows into one, or the next row to the row of the cursor if nothing is
+    /// selected, leaving the cursor at the last join.
+    fn join_lines(&mut self) {
+        let rows = self.target_rows();
+        let joins = cmp::max(rows.end().saturating_sub(*rows.start()), 1);
+        let mut joined = None;
+        for _ in 0..joins {
+            match self
+                .document
+                .join_rows(*rows.start(), self.config.smart_join)
+            {
+                Some(at) => joined = Some(at),
+                None => break,
+            }
+        }
+        let Some(at) = joined else {
+            self.status_message = StatusMessage::from("No line to join.".to_owned());
+            return;
+        };
+        self.cursor_position = at;
+        self.selection_anchor = None;
+        self.scroll();
+    }
+
+    /// Toggles the line comment of the selected rows, which stay selected.
+    fn toggle_comment_selection(&mut self) {
+        let rows = self.target_rows();
+        // Nothing to comment on the line past the end.
+        if *rows.start() >= self.document.len() {
+            self.bell();
+            return;
+        }
+        if !self.document.toggle_comment(rows) {
+            self.status_message =
+                StatusMessage::from("ERR: No line comments for this file type.".to_owned());
+            return;
+        }
+        // The comment token shifts the text, so the cursor may be past the end of its row.
+        let row_len = self.document.row_len(self.cursor_position.y);
+        self.cursor_position.x = cmp::min(self.cursor_position.x, row_len);
+    }
+
+    /// The visual column of the cursor, which differs from its grapheme index if there are tabs or
+    /// wide characters before it.
+    fn cursor_column(&self) -> usize {
+        self.column_at(&self.cursor_position)
+    }
+
+    /// The visual column of the position.
+    fn column_at(&self, pos: &Position) -> usize {
+        self.document.row(pos.y).map_or(pos.x, |row| {
+            row.column_of_grapheme(pos.x, self.config.tab_width)
+        })
+    }
+
+    /// The number of the row in the gutter: 1-based, or in relative mode, how many lines it's
+    /// away from the cursor on the screen, where a fold takes a single line.
+    fn line_number(&self, y: usize) -> usize {
+        if self.gutter_mode == GutterMode::Relative && y != self.cursor_position.y {
+            self.folds
+                .visual_line(y)
+                .abs_diff(self.folds.visual_line(self.cursor_position.y))
+        } else {
+            y.saturating_add(1)
+        }
+    }
+
+    /// The number of columns taken by the line numbers, including the space after them; 0 if
+    /// they're hidden.
+    fn gutter_width(&self) -> usize {
+        if self.gutter_mode == GutterMode::Off {
+            0
+        } else {
+            self.document.line_count_digits().saturating_add(1)
+        }
+    }
+
+    /// The number of columns left for the text, besides the gutter.
+    fn text_width(&self) -> usize {
+        (self.terminal.text_area_size().width as usize).saturating_sub(self.gutter_width())
+    }
+
+    /// Keeps the view consistent after a view option changes how the text is laid out, e.g., the
+    /// gutter narrowing the text area. The cursor is clamped into the document and scrolled into
+    /// view; the offset is only moved if needed, so the cursor stays on the same screen row.
+    fn after_layout_change(&mut self) {
+        self.cursor_position = self.clamp_to_document(&self.cursor_position);
+        self.scroll();
+    }
+
+    /// The position moved into the document, which is at most the line below the last row, and at
+    /// most the end of its row.
+    fn clamp_to_document(&self, at: &Position) -> Position {
+        let y = cmp::min(at.y, self.document.len());
+        let x = cmp::min(at.x, self.document.row_len(y));
+        Position { x, y }
+    }
+
+    fn scroll(&mut self) {
+        self.scroll_with(false);
+    }
+
+    /// Scrolls as `scroll` does, but past the end of the document as well, so that the line being
+    /// typed at the end stays the `scroll_off` lines above the bottom rather than at the edge.
+    fn scroll_after_edit(&mut self) {
+        self.scroll_with(true);
+    }
+
+    /// Scrolls as `scroll` or, after an edit, as `scroll_after_edit` does.
+    fn scroll_with(&mut self, after_edit: bool) {
+        let Position { y, .. } = self.cursor_position;
+        let x = self.cursor_column();
+        let width = self.text_width();
+        let height = self.view().len();
+        self.folds.forget_if_changed(self.document.len());
+        // The cursor may be moved into a fold, e.g., by searching.
+        if self.folds.is_hidden(y) {
+            self.folds.unfold_at(y);
+        }
+        // The view starts at the first row of a fold, rather than in the middle of it.
+        self.offset.y = self
+            .folds
+            .row_at_visual_line(self.folds.visual_line(self.offset.y));
+
+        // Check if the cursor has moved outside of the visible window, or into the `scroll_off`
+        // lines at its edges, and if so, adjust offset so that the cursor is just inside them.
+        // A fold counts as a single line.
+        #[allow(clippy::integer_division)] // Half of the lines, rounded down.
+        let margin = cmp::min(self.config.scroll_off, height.saturating_sub(1) / 2);
+        let line = self.folds.visual_line(y);
+        let offset_line = self.folds.visual_line(self.offset.y);
+        if line < offset_line.saturating_add(margin) {
+            self.offset.y = self.folds.row_at_visual_line(line.saturating_sub(margin));
+        } else if line.saturating_add(margin) >= offset_line.saturating_add(height) {
+            // Doesn't scroll past the line below the last row, where the cursor can be, unless
+            // typing there.
+            let mut last_line = self.folds.visual_line(self.document.len());
+            if after_edit {
+                last_line = last_line.saturating_add(margin);
+            }
+            let new_offset_line = cmp::min(line.saturating_add(margin), last_line)
+                .saturating_add(1)
+                .saturating_sub(height);
+            self.offset.y = self.folds.row_at_visual_line(new_offset_line);
+        }
+        if x < self.offset.x {
+            self.offset.x = x;
+        } else if x >= self.offset.x.saturating_add(width) {
+            self.offset.x = x.saturating_sub(width).saturating_add(1);
+        }
+    }
+
+    /// Scrolls the viewport left or right by most of the terminal width, without scrolling past
+    /// the end of the longest row. The cursor is then moved into view, as far as its row allows.
+    fn scroll_horizontal_page(&mut self, right: bool) {
+        let width = self.text_width();
+        let step = cmp::max(width.saturating_sub(HORIZONTAL_PAGE_OVERLAP), 1);
+        if right {
+            let longest = self.document.max_visual_width(self.config.tab_width);
+            // The cursor can be one past the end of the row.
+            let max_offset = longest.saturating_add(1).saturating_sub(width);
+            self.offset.x = cmp::max(
+                self.offset.x,
+                cmp::min(self.offset.x.saturating_add(step), max_offset),
+            );
+        } else {
+            self.offset.x = self.offset.x.saturating_sub(step);
+        }
+        if let Some(row) = self.document.row(self.cursor_position.y) {
+            let last_visible_column = self.offset.x.saturating_add(width).saturating_sub(1);
+            let column = cmp::min(
+                cmp::max(self.cursor_column(), self.offset.x),
+                last_visible_column,
+            );
+            // A column past the end of the row clamps the cursor to the end.
+            self.cursor_position.x = row.grapheme_index_at_column(column, self.config.tab_width);
+        }
+    }
+
+    /// Moves the cursor as `move_cursor`, ringing the bell if it's already at the edge of the
+    /// document in the direction of the key.
+    fn move_cursor_or_bell(&mut self, key: Key) {
+        let old_position = self.cursor_position.clone();
+        self.move_cursor(key);
+        if self.cursor_position == old_position && !matches!(key, Key::Home | Key::End) {
+            self.bell();
+        }
+    }
+
+    /// Deletes the grapheme before the cursor, as Backspace does; at the very start of the
+    /// document, there's none, and the bell is rung if configured to: flashed with `visual_bell`,
+    /// or else audible.
+    fn delete_backward(&mut self) {
+        if !self.can_delete_backward() {
+            if !self.config.bell_on_backspace_at_start {
+                return;
+            }
+            if self.config.visual_bell {
+                self.bell();
+            } else {
+                self.terminal.ring_bell();
+            }
+            return;
+        }
+        self.unrecord_inserted_char();
+        // Backspace is a combination of going left and deleting.
+        self.move_cursor(Key::Left);
+        self.document.delete(&self.cursor_position);
+    }
+
+    /// Whether there's anything before the cursor for Backspace to delete, which there isn't at
+    /// the very start of the document.
+    fn can_delete_backward(&self) -> bool {
+        self.cursor_position.x > 0 || self.cursor_position.y > 0
+    }
+
+    /// Signals an invalid action by flashing the screen with `visual_bell`; otherwise, the action
+    /// is silently ignored.
+    fn bell(&mut self) {
+        if self.config.visual_bell {
+            // NOTE: Failing to flash is nothing worth reporting.
+            drop(self.terminal.flash());
+        }
+    }
+
+    fn move_cursor(&mut self, key: Key) {
+        let Position { mut x, mut y } = self.cursor_position;
+        let term_height = self.view().len();
+        // The cursor is allowed to move to the last row of the document.
+        let doc_height = self.document.len();
+        let mut row_width = self.document.row_len(y);
+        match key {
+            Key::Up => y = y.saturating_sub(1),
+            // Prevent the cursor from keep going down after the last row.
+            Key::Down if y < doc_height => y = y.saturating_add(1),
+            #[allow(clippy::arithmetic_side_effects)]
+            Key::Left => {
+                if x > 0 {
+                    x -= 1;
+                } else if y > 0 {
+                    // Left at the beginning of the line moves to the end of the previous line.
+                    y -= 1;
+                    x = self.document.row_len(y);
+                }
+            }
+            #[allow(clippy::arithmetic_side_effects)]
+            Key::Right => {
+                // Right at the end of the line moves to the beginning of the next line.
+                if x < row_width {
+                    x += 1;
+                } else if y < doc_height {
+                    y += 1;
+                    x = 0;
+                }
+            }
+            Key::PageUp => {
+                y = {
+                    if y > term_height {
+                        y.saturating_sub(term_height)
+                    } else {
+                        0
+                    }
+                }
+            }
+            Key::PageDown => {
+                y = {
+                    if y.saturating_add(term_height) < doc_height {
+                        y.saturating_add(term_height)
+                    } else {
+                        doc_height
+                    }
+                }
+            }
+            Key::Home => x = 0,
+            Key::End => x = row_width,
+            _ => (),
+        }
+        // A fold is moved over as a line; going down skips past it, and going up lands on its first
+        // row.
+        if let Some(fold) = self.folds.fold_at(y).filter(|fold| *fold.start() != y) {
+            y = if y > self.cursor_position.y {
+                fold.end().saturating_add(1)
+            } else {
+                *fold.start()
+            };
+        }
+        // Users may move the cursor from a long line to a short line.
+        // We have to prevent the cursor from going beyond the end of the line.
+        row_width = self.document.row_len(y);
+        if x > row_width {
+            x = row_width;
+        }
+
+        self.cursor_position = Position { x, y };
+    }
+
+    /// Draws the status bar unless it's already on the screen as it would be drawn.
+    fn draw_status_bar(&mut self) {
+        let status = self.status_bar(Instant::now());
+        if self.drawn_status_bar.as_ref() == Some(&status) {
+            // Goes on to the message bar, below it, as drawing it would.
+            let y = (self.terminal.text_area_size().height as usize).saturating_add(1);
+            Terminal::cursor_position(&Position { x: 0, y });
+            return;
+        }
+        Terminal::set_bg_color(STATUS_BG_COLOR);
+        Terminal::set_fg_color(STATUS_FG_COLOR);
+        println!("{status}\r");
+        Terminal::reset_bg_color();
+        Terminal::reset_fg_color();
+        self.drawn_status_bar = Some(status);
+    }
+
+    /// The text of the status bar as of `now`, padded to the width of the terminal.
+    fn status_bar(&self, now: Instant) -> String {
+        let noeol_indicator = if self.document.is_missing_final_newline() {
+            " [noeol]"
+        } else {
+            ""
+        };
+        let large_indicator = if self.document.is_large() {
+            " [big]"
+        } else {
+            ""
+        };
+        let tail_indicator = if self.tail.is_some() { " [tail]" } else { "" };
+        let normalized_indicator = if self.document.is_normalized() {
+            " [NFC]"
+        } else {
+            ""
+        };
+        #[cfg(feature = "encoding")]
+        let encoding_indicator = self
+            .document
+            .encoding_name()
+            .map_or_else(String::new, |name| format!(" [{name}]"));
+        #[cfg(not(feature = "encoding"))]
+        let encoding_indicator = "";
+        let modified_indicator = if self.document.is_dirty() {
+            " (modified)".to_owned()
+        } else if let Some(last_saved) = self
+            .document
+            .last_saved()
+            .filter(|_| self.config.show_last_saved)
+        {
+            let elapsed = now.saturating_duration_since(last_saved);
+            format!(" (saved {})", format_elapsed(elapsed))
+        } else {
+            String::new()
+        };
+        let filename = if let Some(name) = self.document.filename() {
+            let mut name = name.to_owned();
+            truncate_to_width(&mut name, 20);
+            name
+        } else {
+            "[No Name]".to_owned()
+        };
+        let mode_indicator = match self.mode {
+            _ if !self.config.modal => "",
+            Mode::Normal => "NORMAL | ",
+            Mode::Insert => "INSERT | ",
+        };
+        let word_goal_indicator = if self.config.word_goal == 0 {
+            String::new()
+        } else {
+            let words = self.document.word_count();
+            let goal = self.config.word_goal;
+            let mark = if words >= goal { " \u{2713}" } else { "" };
+            format!(
+                " {words}/{goal} words {}{mark}",
+                progress_bar(words, goal, WORD_GOAL_BAR_WIDTH)
+            )
+        };
+        let recording_indicator = self
+            .recording
+            .as_ref()
+            .map_or_else(String::new, |(register, _)| {
+                format!("recording @{register} | ")
+            });
+        let selection_indicator = self.selected_rows().map_or_else(String::new, |rows| {
+            let (start, end) = rows.into_inner();
+            let (chars, lines) = self.document.count_in_range(
+                &Position { x: 0, y: start },
+                &Position {
+                    x: self.document.row_len(end),
+                    y: end,
+                },
+            );
+            format!(" ({chars} chars, {lines} lines selected)")
+        });
+        let mut status = format!(
+            "{mode_indicator}{recording_indicator}{filename} - {} lines{selection_indicator}{modified_indicator}{noeol_indicator}{large_indicator}{tail_indicator}{normalized_indicator}{encoding_indicator}{word_goal_indicator}",
+            self.document.len()
+        );
+        let line_indicator = format!(
+            "{} | {}/{}",
+            self.document.file_type(),
+            self.cursor_position.y.saturating_add(1), /* 1-based */
+            self.document.len()
+        );
+        // The widths rather than the lengths, since the indicators aren't all ASCII, e.g., the
+        // check mark of the word goal.
+        #[allow(clippy::arithmetic_side_effects)]
+        let len = status.width() + line_indicator.width();
+        let term_width = self.terminal.size().width as usize;
+        status.push_str(&" ".repeat(term_width.saturating_sub(len)));
+        // XXX: Isn't status always less than or equal to term_width?
+        truncate_to_width(&mut status, term_width);
+        // The current line number is aligned to the right edge.
+        format!("{status}{line_indicator}")
+    }
+
+    /// Draws the message bar unless it's already on the screen as it would be drawn.
+    fn draw_message_bar(&mut self) {
+        let text = self.message_bar();
+        if self.drawn_message.as_ref() == Some(&text) {
+            return;
+        }
+        Terminal::clear_current_line();
+        print!("{text}");
+        self.drawn_message = Some(text);
+    }
+
+    /// The text of the message bar: the message until it expires, cut at the width of the
+    /// terminal.
+    fn message_bar(&self) -> String {
+        let message = &self.status_message;
+        let mut text = if message.is_visible(self.config.message_timeout_secs) {
+            message.text.clone()
+        } else {
+            String::new()
+        };
+        truncate_to_width(&mut text, self.terminal.size().width as usize);
+        text
+    }
+
+    /// Prompt the user for input. Of pasted text, only the first line is taken.
+    /// The callback, if any, is called whenever a key is pressed, along with the key and the current
+    /// input, e.g., for live feedback such as incremental search. It may replace the input, in which
+    /// case the caret is moved to the end.
+    /// # Errors
+    /// Returns `Cancelled` if the user cancels the prompt or enters nothing, or an error if the user
+    /// input can't be read.
+    fn prompt(
+        &mut self,
+        prompt: &str,
+        callback: Option<PromptCallback>,
+    ) -> Result<String, EditorError> {
+        self.prompt_with_arrows(prompt, callback, true)
+    }
+
+    /// Like `prompt`, but Left and Right only move the caret if `arrows_move_caret`; otherwise,
+    /// they're left to the callback, e.g., to go through the matches of a search.
+    /// # Errors
+    /// As `prompt`.
+    fn prompt_with_arrows(
+        &mut self,
+        prompt: &str,
+        mut callback: Option<PromptCallback>,
+        arrows_move_caret: bool,
+    ) -> Result<String, EditorError> {
+        let mut result = String::new();
+        // The character index in `result` where the next character is inserted.
+        let mut caret = 0usize;
+        loop {
+            self.status_message = StatusMessage::from(format!("{prompt}{result}"));
+            let caret_byte_index = byte_index(&result, caret);
+            self.prompt_cursor_column = Some(
+                prompt
+                    .width()
+                    .saturating_add(result.get(..caret_byte_index).map_or(0, str::width)),
+            );
+            self.refresh_screen()?;
+            let (key, is_pasted) = match self.read_input()? {
+                Input::Key(key) => (key, false),
+                Input::Paste(text) => {
+                    // Only the first line, since a line break would end the prompt.
+                    let line: String = text
+                        .split(['\r', '\n'])
+                        .next()
+                        .unwrap_or_default()
+                        .chars()
+                        .filter(|c| !c.is_control())
+                        .collect();
+                    let Some(last) = line.chars().last() else {
+                        continue;
+                    };
+                    result.insert_str(caret_byte_index, &line);
+                    caret = caret.saturating_add(line.chars().count());
+                    // The callback sees the paste as if its last character were typed.
+                    (Key::Char(last), true)
+                }
+            };
+            match key {
+                // The pasted text is already inserted.
+                _ if is_pasted => (),
+                Key::Backspace if caret > 0 => {
+                    caret = caret.saturating_sub(1);
+                    result.remove(byte_index(&result, caret));
+                }
+                Key::Delete if caret < result.chars().count() => {
+                    result.remove(caret_byte_index);
+                }
+                Key::Left if arrows_move_caret => caret = caret.saturating_sub(1),
+                Key::Right if arrows_move_caret => {
+                    caret = cmp::min(caret.saturating_add(1), result.chars().count());
+                }
+                Key::Home => caret = 0,
+                Key::End => caret = result.chars().count(),
+                // Enter is pressed; prompt is done.
+                Key::Char('\n') => break,
+                Key::Char(c) if !c.is_control() => {
+                    result.insert(caret_byte_index, c);
+                    caret = caret.saturating_add(1);
+                }
+                Key::Esc => {
+                    result.clear();
+                    break;
+                }
+                _ => (),
+            }
+            if let Some(callback) = callback.as_mut() {
+                if let Some(replacement) = callback(self, key, &result) {
+                    result = replacement;
+                    caret = result.chars().count();
+                }
+            }
+        }
+        self.prompt_cursor_column = None;
+        self.status_message.clear();
+        if result.is_empty() {
+            Err(EditorError::Cancelled)
+        } else {
+            Ok(result)
+        }
+    }
+
+    /// Prompts for a path, which Tab completes.
+    fn prompt_path(&mut self, prompt: &str) -> Result<String, EditorError> {
+        let mut completer = PathCompleter::default();
+        let mut complete_path = |_: &mut Self, key: Key, input: &str| {
+            if key == Key::Char('\t') {
+                completer.complete(input)
+            } else {
+                None
+            }
+        };
+        self.prompt(
+            &format!("{prompt} (Tab to complete): "),
+            Some(&mut complete_path),
+        )
+    }
+
+    /// Starts an empty, unnamed document, keeping the current one open as another buffer, as
+    /// `edit_file` does.
+    fn new_buffer(&mut self) {
+        if self.is_untouched() {
+            // There's nothing to keep.
+            self.document.clear();
+            return;
+        }
+        let previous = self.swap_buffer(Buffer::default());
+        self.buffers.push(previous);
+    }
+
+    /// Whether the document is an empty, unnamed one left untouched, which is replaced rather than
+    /// kept when another one is opened.
+    fn is_untouched(&self) -> bool {
+        self.document.filename().is_none() && !self.document.is_dirty() && self.document.is_empty()
+    }
+
+    /// Whether the unsaved changes of the document, if any, may be discarded, asking if there are.
+    fn confirm_discard(&mut self) -> bool {
+        !self.document.is_dirty()
+            || self
+                .prompt("Discard unsaved changes? (y/n): ", None)
+                .is_ok_and(|answer| answer.eq_ignore_ascii_case("y"))
+    }
+
+    /// Whether any open file, the current one or another, has unsaved changes.
+    fn has_unsaved_changes(&self) -> bool {
+        self.document.is_dirty() || self.buffers.iter().any(|buffer| buffer.document.is_dirty())
+    }
+
+    /// Makes the buffer the current one, returning the one it replaces.
+    fn swap_buffer(&mut self, buffer: Buffer) -> Buffer {
+        let previous = Buffer {
+            document: mem::replace(&mut self.document, buffer.document),
+            cursor_position: mem::replace(&mut self.cursor_position, buffer.cursor_position),
+            offset: mem::replace(&mut self.offset, buffer.offset),
+            folds: mem::replace(&mut self.folds, buffer.folds),
+            jumps: mem::replace(&mut self.jumps, buffer.jumps),
+        };
+        self.selection_anchor = None;
+        // The lines appended to the file, and the other pane, are of the document being left.
+        self.tail = None;
+        self.split = None;
+        // Each file is indented as it does itself.
+        self.config.seed_indent(self.document.indent_style());
+        // The document may have been trimmed by `save_all` meanwhile.
+        self.after_layout_change();
+        previous
+    }
+
+    /// Makes the buffer at the index of `buffers` the current one, keeping the order in which
+    /// `next_buffer` goes through them.
+    fn switch_to_buffer(&mut self, index: usize) {
+        if index >= self.buffers.len() {
+            self.status_message = StatusMessage::from("No other buffers.".to_owned());
+            return;
+        }
+        self.buffers.rotate_left(index);
+        let buffer = self.buffers.remove(0);
+        let previous = self.swap_buffer(buffer);
+        let at = self.buffers.len().saturating_sub(index);
+        self.buffers.insert(at, previous);
+    }
+
+    /// Opens the file in a buffer of its own, which becomes the current one, or goes to its buffer
+    /// if it's already open, prompting for it if not given. A file that doesn't exist yet is
+    /// created on the first save.
+    fn edit_file(&mut self, filename: &str) {
+        let filename = if filename.is_empty() {
+            match self.prompt_path("Edit file") {
+                Ok(filename) => filename,
+                Err(_) => return,
+            }
+        } else {
+            filename.to_owned()
+        };
+        self.open_buffer(&filename, true);
+    }
+
+    /// Makes the buffer of the file the current one, opening it if it isn't open yet. A file that
+    /// doesn't exist is only opened if `create`, as an empty document that creates it on save.
+    /// Returns whether the file is open in the current buffer; the error is shown if not.
+    fn open_buffer(&mut self, filename: &str, create: bool) -> bool {
+        // The documents are named after the normalized paths, e.g., with `~` expanded, and it's
+        // the normalized path that's checked for a file, so that an existing one is never taken
+        // for a new one and then overwritten.
+        let filename = normalize_path(filename);
+        if self.document.filename() == Some(filename.as_str()) {
+            return true;
+        }
+        if let Some(index) = self
+            .buffers
+            .iter()
+            .position(|buffer| buffer.document.filename() == Some(filename.as_str()))
+        {
+            self.switch_to_buffer(index);
+            return true;
+        }
+        let document = match open_document(&filename, None, &self.config) {
+            Ok(document) => document,
+            Err(EditorError::Io(err)) if create && err.kind() == io::ErrorKind::NotFound => {
+                let mut document = Document::default();
+                document.set_filename(&filename);
+                document
+            }
+            Err(err) => {
+                self.status_message =
+                    StatusMessage::from(format!("ERR: Could not open file: {filename} ({err})"));
+                return false;
+            }
+        };
+        let is_untouched = self.is_untouched();
+        let previous = self.swap_buffer(Buffer {
+            document,
+            ..Buffer::default()
+        });
+        if !is_untouched {
+            self.buffers.push(previous);
+        }
+        true
+    }
+
+    /// Closes the current buffer, asking first if there are unsaved changes, and goes to the next
+    /// one. Closing the last buffer leaves an empty, unnamed one.
+    fn close_buffer(&mut self) {
+        if !self.confirm_discard() {
+            self.status_message = StatusMessage::from("Close aborted.".to_owned());
+            return;
+        }
+        let next = if self.buffers.is_empty() {
+            Buffer::default()
+        } else {
+            self.buffers.remove(0)
+        };
+        let closed = self.swap_buffer(next);
+        // An unnamed file has nowhere to be reopened from.
+        if let Some(filename) = closed.document.filename() {
+            self.closed_buffers
+                .push((filename.to_owned(), closed.cursor_position));
+            if self.closed_buffers.len() > CLOSED_BUFFERS_CAPACITY {
+                self.closed_buffers.remove(0);
+            }
+        }
+    }
+
+    /// Opens the file closed last again, with the cursor where it was.
+    fn reopen_buffer(&mut self) {
+        let Some((filename, cursor_position)) = self.closed_buffers.pop() else {
+            self.status_message = StatusMessage::from("No closed files to reopen.".to_owned());
+            return;
+        };
+        // A file that's gone since is reported rather than created as an empty one.
+        if !self.open_buffer(&filename, false) {
+            return;
+        }
+        // The file may have changed on disk since.
+        self.cursor_position = self.clamp_to_document(&cursor_position);
+        self.scroll();
+    }
+
+    /// Shows the names of the open files, the current one first and in brackets, with a `+` after
+    /// those with unsaved changes, e.g., `[main.rs+] | notes.txt`.
+    fn list_buffers(&mut self) {
+        let names: Vec<String> = iter::once(&self.document)
+            .chain(self.buffers.iter().map(|buffer| &buffer.document))
+            .enumerate()
+            .map(|(index, document)| {
+                let name = document.filename().unwrap_or("[No Name]");
+                let modified = if document.is_dirty() { "+" } else { "" };
+                if index == 0 {
+                    format!("[{name}{modified}]")
+                } else {
+                    format!("{name}{modified}")
+                }
+            })
+            .collect();
+        self.status_message = StatusMessage::from(names.join(" | "));
+    }
+
+    /// Saves every open file with unsaved changes, tidied up as a single save does, and reports
+    /// how it went, e.g., `3 saved, 1 failed: notes.txt`. The unnamed ones are skipped, since
+    /// there's no file to save them to; saving each on its own prompts for a name.
+    fn save_all(&mut self) {
+        let mut saved = 0usize;
+        let mut failed = Vec::new();
+        let mut unnamed = 0usize;
+        let documents = iter::once(&mut self.document)
+            .chain(self.buffers.iter_mut().map(|buffer| &mut buffer.document));
+        for document in documents.filter(|document| document.is_dirty()) {
+            let Some(filename) = document.filename().map(str::to_owned) else {
+                unnamed = unnamed.saturating_add(1);
+                continue;
+            };
+            tidy_for_save(document, &self.config);
+            let options = SaveOptions {
+                through_symlinks: self.config.save_through_symlinks,
+                ..SaveOptions::default()
+            };
+            if document.save_with(&options).is_ok() {
+                saved = saved.saturating_add(1);
+            } else {
+                failed.push(filename);
+            }
+        }
+        // The cursor may be on a removed row.
+        self.after_layout_change();
+        if saved == 0 && failed.is_empty() && unnamed == 0 {
+            self.status_message = StatusMessage::from("No unsaved changes.".to_owned());
+            return;
+        }
+        let mut parts = vec![format!("{saved} saved")];
+        if !failed.is_empty() {
+            parts.push(format!("{} failed: {}", failed.len(), failed.join(", ")));
+        }
+        if unnamed > 0 {
+            parts.push(format!("{unnamed} unnamed skipped"));
+        }
+        self.status_message = StatusMessage::from(parts.join(", "));
+    }
+
+    /// Starts following the file as it grows, e.g., a log, or stops if already following.
+    fn toggle_tail(&mut self) {
+        if self.tail.take().is_some() {
+            self.status_message = StatusMessage::from("Tail mode off.".to_owned());
+            return;
+        }
+        let Some(filename) = self.document.filename().map(str::to_owned) else {
+            self.status_message = StatusMessage::from("ERR: No file to follow.".to_owned());
+            return;
+        };
+        // The lines are appended after the text as saved.
+        if self.document.is_dirty() {
+            self.status_message =
+                StatusMessage::from("ERR: Save the changes before following the file.".to_owned());
+            return;
+        }
+        match Tail::new(&filename) {
+            Ok(tail) => {
+                self.tail = Some(tail);
+                self.status_message =
+                    StatusMessage::from(format!("Following {filename}; editing stops it."));
+            }
+            Err(err) => {
+                self.status_message =
+                    StatusMessage::from(format!("ERR: Could not follow {filename}: {err}"));
+            }
+        }
+    }
+
+    /// Waits for the next input, waking up every `FRAME_INTERVAL` meanwhile for the timed events:
+    /// appending the lines appended to the file in tail mode, and the bars changing with time,
+    /// e.g., the message expiring. The screen is only redrawn if anything changed, and only what
+    /// changes with time is checked, so that waiting takes next to no CPU.
+    fn wait_for_input(&mut self) -> Result<(), Error> {
+        let mut tail_polled = Instant::now();
+        let mut timed_state = self.timed_state(Instant::now());
+        while self.replaying.is_empty() && !self.terminal.poll_input(FRAME_INTERVAL) {
+            let mut has_changed = false;
+            if tail_polled.elapsed() >= TAIL_POLL_INTERVAL {
+                tail_polled = Instant::now();
+                has_changed = self.follow_tail();
+            }
+            let state = self.timed_state(Instant::now());
+            if has_changed || state != timed_state {
+                timed_state = state;
+                self.refresh_screen()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// What of the bars changes with time alone as of `now`: whether the message is visible, and
+    /// how long ago the document was saved if shown. The rest only changes with input.
+    fn timed_state(&self, now: Instant) -> (bool, Option<String>) {
+        let is_message_visible = self
+            .status_message
+            .is_visible(self.config.message_timeout_secs);
+        let saved = self
+            .document
+            .last_saved()
+            .filter(|_| self.config.show_last_saved && !self.document.is_dirty())
+            .map(|last_saved| format_elapsed(now.saturating_duration_since(last_saved)));
+        (is_message_visible, saved)
+    }
+
+    /// Appends the lines appended to the file, with the cursor following them if it's on the last
+    /// line; returns whether anything changed. Tail mode stops if the file can't be followed.
+    fn follow_tail(&mut self) -> bool {
+        let Some(tail) = &mut self.tail else {
+            return false;
+        };
+        match tail.read_appended() {
+            Ok(None) => false,
+            Ok(Some(text)) => {
+                let is_at_end = self.cursor_position.y.saturating_add(1) >= self.document.len();
+                self.document.append_from_disk(&text);
+                if is_at_end {
+                    self.cursor_position = Position {
+                        x: 0,
+                        y: self.document.len().saturating_sub(1),
+                    };
+                }
+                self.scroll();
+                true
+            }
+            Err(err) => {
+                self.tail = None;
+                self.status_message = StatusMessage::from(format!("Tail mode stopped: {err}"));
+                true
+            }
+        }
+    }
+
+    /// Stops tail mode once the document is edited, since the lines of the file would no longer
+    /// be appended after the text they follow.
+    fn stop_tail_if_edited(&mut self) {
+        if self.tail.is_some() && self.document.is_dirty() {
+            self.tail = None;
+            self.status_message = StatusMessage::from("Tail mode stopped by editing.".to_owned());
+        }
+    }
+
+    /// Shows the Unicode scalar values of the character at the cursor.
+    fn show_codepoints(&mut self) {
+        self.status_message = StatusMessage::from(
+            self.document
+                .grapheme_at(&self.cursor_position)
+                .map_or_else(
+                    || "No character at the cursor.".to_owned(),
+                    format_codepoints,
+                ),
+        );
+    }
+
+    /// Shows the numbers of lines added and removed since the file was saved, e.g., `+3 -1`.
+    fn diff_against_disk(&mut self) {
+        let Some(filename) = self.document.filename().map(str::to_owned) else {
+            self.status_message =
+                StatusMessage::from("ERR: No file to compare against.".to_owned());
+            return;
+        };
+        // Read as the document was, e.g., in its encoding.
+        #[cfg(feature = "encoding")]
+        let encoding = self.document.encoding_name();
+        #[cfg(not(feature = "encoding"))]
+        let encoding = None;
+        let saved = match open_document(&filename, encoding, &self.config) {
+            Ok(saved) => saved,
+            Err(err) => {
+                self.status_message =
+                    StatusMessage::from(format!("ERR: Could not read {filename}: {err}"));
+                return;
+            }
+        };
+        let summary = diff(
+            saved.rows_in_range(0, saved.len()),
+            self.document.rows_in_range(0, self.document.len()),
+        );
+        self.status_message = StatusMessage::from(if summary.is_empty() {
+            format!("No changes against {filename}.")
+        } else {
+            format!("{summary} against {filename}")
+        });
+    }
+
+    /// Moves the cursor to the start of the line, counted from 1, or the last line if past it.
+    fn goto_line(&mut self, line: &str) {
+        let Some(y) = line
+            .parse::<usize>()
+            .ok()
+            .and_then(|line| line.checked_sub(1))
+        else {
+            self.status_message =
+                StatusMessage::from("ERR: Expected a line number, e.g., goto 42.".to_owned());
+            return;
+        };
+        let y = cmp::min(y, self.document.len().saturating_sub(1));
+        // Going to a line in a fold, even its first one, is to see it.
+        self.folds.unfold_at(y);
+        self.jump_to(Position { x: 0, y });
+    }
+
+    /// Moves the cursor to the position, recording where it was in the jump list.
+    fn jump_to(&mut self, to: Position) {
+        self.jumps.push(self.cursor_position.clone());
+        self.cursor_position = to;
+        self.scroll();
+    }
+
+    fn jump_back(&mut self) {
+        if let Some(position) = self.jumps.back(self.cursor_position.clone()) {
+            self.move_to_jump(position);
+        } else {
+            self.bell();
+        }
+    }
+
+    fn jump_forward(&mut self) {
+        if let Some(position) = self.jumps.forward() {
+            self.move_to_jump(position);
+        } else {
+            self.bell();
+        }
+    }
+
+    /// Moves the cursor to a position of the jump list, which may be past the text since edited.
+    fn move_to_jump(&mut self, position: Position) {
+        self.cursor_position = position;
+        self.after_layout_change();
+    }
+
+    /// Inserts the contents of the file at the cursor, prompting for the file if not given, and
+    /// moves the cursor after them.
+    fn insert_file(&mut self, filename: &str) {
+        let filename = if filename.is_empty() {
+            match self.prompt_path("Insert file") {
+                Ok(filename) => filename,
+                Err(_) => return,
+            }
+        } else {
+            filename.to_owned()
+        };
+        match fs::read_to_string(&filename) {
+            Ok(content) => {
+                let text = content.replace("\r\n", "\n").replace('\r', "\n");
+                let outcome = self.document.apply(Edit::InsertStr {
+                    at: self.cursor_position.clone(),
+                    text,
+                });
+                self.cursor_position = outcome.end;
+                self.scroll();
+            }
+            Err(err) => {
+                self.status_message =
+                    StatusMessage::from(format!("ERR: Could not read {filename}: {err}"));
+            }
+        }
+    }
+
+    /// Writes the selected rows, or the current row, to a file, prompting for it if not given.
+    /// The document itself is left as it is.
+    fn write_selection(&mut self, filename: &str) {
+        let filename = if filename.is_empty() {
+            match self.prompt_path("Write selection to") {
+                Ok(filename) => filename,
+                Err(_) => return,
+            }
+        } else {
+            filename.to_owned()
+        };
+        if Path::new(&filename).exists() {
+            let answer = self.prompt(&format!("{filename} exists. Overwrite? (y/n): "), None);
+            if !answer.is_ok_and(|answer| answer.eq_ignore_ascii_case("y")) {
+                self.status_message = StatusMessage::from("Write aborted.".to_owned());
+                return;
+            }
+        }
+        let text = self.document.selection_text(self.target_rows());
+        self.status_message = StatusMessage::from(match fs::write(&filename, text) {
+            Ok(()) => format!("Selection written to {filename}."),
+            Err(err) => format!("Error writing {filename}: {err}!"),
+        });
+    }
+
+    /// Saves the document, prompting for a filename if it has none, and reports the result in the
+    /// message bar.
+    /// # Errors
+    /// Returns `Cancelled` if the filename prompt is cancelled, or the error of saving.
+    fn save(&mut self) -> Result<(), EditorError> {
+        self.save_with(true)
+    }
+
+    /// Saves the document, with the last line ending with a newline only if `final_newline`.
+    fn save_with(&mut self, final_newline: bool) -> Result<(), EditorError> {
+        // If the file has no name, prompt the user for one.
+        if self.document.filename().is_none() {
+            match self.prompt_path("Save as") {
+                Ok(new_name) => self.document.set_filename(&new_name),
+                Err(err) => {
+                    self.status_message = StatusMessage::from("Save aborted.".to_owned());
+                    return Err(err);
+                }
+            }
+        }
+        if tidy_for_save(&mut self.document, &self.config) {
+            // The cursor may be on a removed row.
+            self.after_layout_change();
+        }
+        let result = self.document.save_with(&SaveOptions {
+            final_newline,
+            through_symlinks: self.config.save_through_symlinks,
+        });
+        self.status_message = StatusMessage::from(match &result {
+            Ok(()) => "File saved sucessfully.".to_owned(),
+            Err(EditorError::ReadOnly) => "Error writing file: the file is read-only!".to_owned(),
+            Err(err) => format!("Error writing file: {err}!"),
+        });
+        result
+    }
+
+    /// Searches for a query in the document with incremental backward and forward search.
+    fn search(&mut self) {
+        let old_position = self.cursor_position.clone();
+        let opts = SearchOptions {
+            ignore_case: self.config.search_ignore_case,
+            smart_case: self.config.search_smart_case,
+        };
+        // We start by searching forward.
+        let mut forward = true;
+        // NOTE: Every time the query is updated, either by typing or deleting,
+        // the cursor is moved back to the old position to start a new forward search.
+        let mut incremental_search = |editor: &mut Self, key: Key, partial_query: &str| {
+            let mut moved = false;
+            match key {
+                Key::Char(_) | Key::Backspace | Key::Delete => {
+                    editor.cursor_position = old_position.clone();
+                    editor.scroll();
+                    forward = true;
+                }
+                Key::Right | Key::Down => {
+                    // NOTE: For our incremental search to not return the current position.
+                    // Will move back if no next match is found.
+                    editor.move_cursor(Key::Right);
+                    forward = true;
+                    moved = true;
+                }
+                Key::Left | Key::Up => {
+                    // The current position is excluded from the search, so will not return the current position.
+                    forward = false;
+                }
+                // Only the caret is moved; the query is the same.
+                Key::Home | Key::End => return None,
+                _ => (),
+            }
+            let find_func = if forward {
+                Document::find_after
+            } else {
+                Document::rfind_before
+            };
+
+            editor.document.highlight_restore();
+
+            if let Some(position) = find_func(
+                &editor.document,
+                partial_query,
+                &editor.cursor_position,
+                &opts,
+            ) {
+                editor.cursor_position = position;
+                editor.scroll();
+            } else if moved {
+                // Not found, move the offset back.
+                editor.move_cursor(Key::Left);
+            }
+            editor.document.highlight_query(partial_query, &opts);
+            None
+        };
+
+        // Perform the search.
+        if let Ok(query) = self.prompt_with_arrows(
+            "Search (ESC to cancel, Arrows to navigate): ",
+            Some(&mut incremental_search),
+            false,
+        ) {
+            // The find is done along with the incremental search.
+            // If the find succeeds, the cursor is already at the right position.
+            // Otherwise, the cursor is moved back to the old position.
+            // However, there's a chance that we're at the old position if it's the first match.
+            // So we perform an additional forward search.
+            if self.cursor_position == old_position
+                && self
+                    .document
+                    .find_after(&query, &self.cursor_position, &opts)
+                    .is_none()
+            {
+                self.status_message = StatusMessage::from(format!("Not found: {query}"));
+                self.bell();
+            } else if self.cursor_position != old_position {
+                self.jumps.push(old_position);
+            }
+        } else {
+            self.status_message = StatusMessage::from("Search canceled.".to_owned());
+            // The user canceled the search; restore the old position.
+            self.cursor_position = old_position;
+            self.scroll();
+        }
+        self.document.highlight_restore();
+    }
+}
+
+/// Reads the text of the errors from the file, or to the end of the standard input if it's `-`.
+fn read_errors(filename: &str) -> io::Result<String> {
+    if filename != "-" {
+        return fs::read_to_string(filename);
+    }
+    let mut text = String::new();
+    io::stdin().read_to_string(&mut text)?;
+    Ok(text)
+}
+
+/// Opens the file, in the encoding named by the label if any, marked and normalized as configured.
+fn open_document(
+    filename: &str,
+    encoding: Option<&str>,
+    config: &Config,
+) -> Result<Document, EditorError> {
+    #[cfg(feature = "encoding")]
+    let mut document = Document::open_with_encoding(filename, encoding)?;
+    #[cfg(not(feature = "encoding"))]
+    if encoding.is_some() {
+        return Err(EditorError::InvalidInput(
+            "built without the encoding feature".to_owned(),
+        ));
+    }
+    #[cfg(not(feature = "encoding"))]
+    let mut document = Document::open(filename)?;
+    document.mark_large_over(config.large_file_bytes);
+    #[cfg(feature = "nfc")]
+    if config.normalize_on_open {
+        document.normalize_nfc();
+    }
+    Ok(document)
+}
+
+/// Normalizes and trims the document as configured for saving it; returns whether rows were
+/// removed.
+fn tidy_for_save(document: &mut Document, config: &Config) -> bool {
+    #[cfg(feature = "nfc")]
+    if config.normalize_on_save {
+        document.normalize_nfc();
+    }
+    config
+        .max_trailing_blank_lines
+        .is_some_and(|max| document.trim_trailing_blank_lines(max))
+}
+
+/// The screen rows of the top and the bottom pane of a text area of the height split in two,
+/// with the row between them for the line separating them. The top pane is the shorter one.
+#[allow(clippy::integer_division)] // Half of the rows, rounded down.
+fn split_layout(height: usize) -> (Range<usize>, Range<usize>) {
+    let top = height.saturating_sub(1) / 2;
+    (0..top, top.saturating_add(1)..height)
+}
+
+/// The bracket closing the opening one, if it's one.
+fn closing_bracket(c: char) -> Option<char> {
+    match c {
+        '{' => Some('}'),
+        '(' => Some(')'),
+        '[' => Some(']'),
+        _ => None,
+    }
+}
+
+/// The byte index of the character at `char_index`; the length of the string if past the end.
+fn byte_index(s: &str, char_index: usize) -> usize {
+    s.char_indices()
+        .nth(char_index)
+        .map_or(s.len(), |(index, _)| index)
+}
+
+/// The raw character of a key for inserting literally. `None` if the key has no such character,
+/// or the character would break the document, e.g., a carriage return is a line ending on its own.
+fn literal_char(key: Key) -> Option<char> {
+    let c = match key {
+        Key::Char(c) => c,
+        // Ctrl-A to Ctrl-Z are 0x01 to 0x1A, and Ctrl-4 to Ctrl-7 are 0x1C to 0x1F.
+        Key::Ctrl(c @ 'a'..='z') => char::from((c as u8) & 0x1f),
+        Key::Ctrl(c @ '4'..='7') => char::from((c as u8).saturating_sub(b'4').saturating_add(0x1c)),
+        Key::Backspace => '\x7f',
+        _ => return None,
+    };
+    // An escape would be interpreted by the terminal when drawn.
+    if c == '\r' || c == '\x1b' {
+        return None;
+    }
+    Some(c)
+}
+
+/// A bar of `width` cells, filled in proportion to `done` out of `total`, e.g., `[###-------]`.
+/// Full once `done` reaches `total`; rounds down, so it's never full before that.
+fn progress_bar(done: usize, total: usize, width: usize) -> String {
+    #[allow(clippy::integer_division)]
+    let filled = done
+        .saturating_mul(width)
+        .checked_div(total)
+        .map_or(width, |filled| cmp::min(filled, width));
+    format!(
+        "[{}{}]",
+        "#".repeat(filled),
+        "-".repeat(width.saturating_sub(filled))
+    )
+}
+
+/// Cuts the string to the characters that fit in `width` columns.
+fn truncate_to_width(s: &mut String, width: usize) {
+    let mut columns = 0usize;
+    for (index, c) in s.char_indices() {
+        columns = columns.saturating_add(c.width().unwrap_or(0));
+        if columns > width {
+            s.truncate(index);
+            return;
+        }
+    }
+}
+
+/// The character if the string is exactly one character.
+fn single_char(s: &str) -> Option<char> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
+
+/// The Unicode scalar values of the grapheme, e.g., `U+0065 U+0301` for an `e` with an acute
+/// accent.
+fn format_codepoints(grapheme: &str) -> String {
+    grapheme
+        .chars()
+        .map(|c| format!("U+{:04X}", u32::from(c)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Formats the elapsed time coarsely, e.g., `just now`, `42s ago`, `2m ago`, or `3h ago`.
+#[allow(clippy::integer_division)] // Truncating to whole minutes and hours is intended.
+fn format_elapsed(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    match secs {
+        0..=4 => "just now".to_owned(),
+        5..=59 => format!("{secs}s ago"),
+        60..=3599 => format!("{}m ago", secs / 60),
+        _ => format!("{}h ago", secs / 3600),
+    }
+}
+
+fn die(e: &Error) {
+    Terminal::clear_screen();
+    panic!("{}", e);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KeyBinding;
+    use crate::TempPath;
+
+    /// An editor on a mock terminal with the text typed into an unnamed document, with the cursor
+    /// back at the top-left corner. Typing the text is an undo step of its own.
+    fn editor_with(text: &str, config: Config) -> Editor {
+        let mut document = Document::default();
+        document.insert_str(&Position::default(), text);
+        document.end_undo_step();
+        Editor::with(Terminal::mock(80, 24), document, config)
+    }
+
+    fn editor(text: &str) -> Editor {
+        editor_with(text, Config::default())
+    }
+
+    fn modal_editor(text: &str) -> Editor {
+        let mut config = Config::default();
+        config.modal = true;
+        editor_with(text, config)
+    }
+
+    /// Presses the keys, given as the bytes a terminal sends for them. A lone Esc has to be
+    /// pressed on its own, since it would otherwise start an escape sequence.
+    fn press(editor: &mut Editor, keys: &str) {
+        editor.terminal.push_input(keys.as_bytes());
+        while editor.terminal.has_input() {
+            if let Err(err) = editor.process_keypress() {
+                panic!("failed to process the keys {keys:?}: {err}");
+            }
+        }
+    }
+
+    fn lines(editor: &Editor) -> Vec<&str> {
+        editor.document.lines().collect()
+    }
+
+    #[test]
+    fn modal_session_inserts_and_deletes_lines() {
+        let mut editor = modal_editor("");
+        assert!(editor.mode == Mode::Normal);
+        press(&mut editor, "ifirst\rsecond\rthird");
+        assert!(editor.mode == Mode::Insert);
+        press(&mut editor, "\x1b");
+        assert!(editor.mode == Mode::Normal);
+        assert_eq!(lines(&editor), ["first", "second", "third"]);
+        press(&mut editor, "kdd");
+        assert_eq!(lines(&editor), ["first", "third"]);
+        press(&mut editor, "dd");
+        assert_eq!(lines(&editor), ["first"]);
+    }
+
+    #[test]
+    fn normal_mode_keys_move_rather_than_insert() {
+        let mut editor = modal_editor("abc\ndef");
+        press(&mut editor, "jl");
+        assert!(editor.cursor_position == Position { x: 1, y: 1 });
+        assert_eq!(lines(&editor), ["abc", "def"]);
+        press(&mut editor, "x");
+        assert_eq!(lines(&editor), ["abc", "df"]);
+    }
+
+    #[test]
+    fn dot_repeats_the_last_insert_at_the_cursor() {
+        let mut editor = modal_editor("one\ntwo");
+        press(&mut editor, "iab");
+        press(&mut editor, "\x1b");
+        assert_eq!(lines(&editor), ["abone", "two"]);
+        press(&mut editor, "j.");
+        assert!(editor.cursor_position == Position { x: 4, y: 1 });
+        assert_eq!(lines(&editor), ["abone", "twabo"]);
+    }
+
+    #[test]
+    fn dot_repeats_the_last_deletion() {
+        let mut editor = modal_editor("abc\ndef\nghi");
+        press(&mut editor, "x");
+        press(&mut editor, "j.");
+        assert_eq!(lines(&editor), ["bc", "ef", "ghi"]);
+        press(&mut editor, "dd.");
+        assert_eq!(lines(&editor), ["bc"]);
+    }
+
+    #[test]
+    fn navigation_keeps_the_quit_sequence_going() {
+        let mut editor = editor("unsaved");
+        press(&mut editor, "\x11");
+        assert_eq!(editor.quit_times, QUIT_TIMES.saturating_sub(1));
+        press(&mut editor, "\x1b[B\x1b[C");
+        assert_eq!(editor.quit_times, QUIT_TIMES.saturating_sub(1));
+        press(&mut editor, "\x11\x11\x11");
+        assert!(editor.should_quit);
+    }
+
+    #[test]
+    fn editing_aborts_the_quit_sequence() {
+        let mut editor = editor("unsaved");
+        press(&mut editor, "\x11\x11");
+        press(&mut editor, "a");
+        assert_eq!(editor.quit_times, QUIT_TIMES);
+        assert!(!editor.should_quit);
+    }
+
+    #[test]
+    fn clean_document_quits_at_once() {
+        let mut editor = Editor::with(
+            Terminal::mock(80, 24),
+            Document::default(),
+            Config::default(),
+        );
+        press(&mut editor, "\x11");
+        assert!(editor.should_quit);
+    }
+
+    #[test]
+    fn horizontal_page_stops_at_the_left_edge() {
+        let mut editor = editor(&"x".repeat(200));
+        press(&mut editor, "\x1b,");
+        assert_eq!(editor.offset.x, 0);
+        assert_eq!(editor.cursor_position.x, 0);
+    }
+
+    #[test]
+    fn horizontal_page_moves_by_the_width_less_the_overlap() {
+        let mut editor = editor(&"x".repeat(200));
+        let step = 80usize.saturating_sub(HORIZONTAL_PAGE_OVERLAP);
+        press(&mut editor, "\x1b.");
+        assert_eq!(editor.offset.x, step);
+        assert_eq!(editor.cursor_position.x, step);
+        // Only as far as the column after the end of the longest line comes into view.
+        press(&mut editor, "\x1b.");
+        assert_eq!(editor.offset.x, 121);
+        assert_eq!(editor.cursor_position.x, 121);
+        press(&mut editor, "\x1b,");
+        assert_eq!(editor.offset.x, 121usize.saturating_sub(step));
+        assert_eq!(editor.cursor_position.x, 121);
+    }
+
+    #[test]
+    fn horizontal_page_on_a_short_line_keeps_the_view() {
+        let mut editor = editor(&"x".repeat(50));
+        press(&mut editor, "\x1b.");
+        assert_eq!(editor.offset.x, 0);
+    }
+
+    #[test]
+    fn cursor_shape_follows_the_mode() {
+        let mut config = Config::default();
+        config.modal = true;
+        config.cursor_shape = CursorShape::Bar;
+        let mut editor = editor_with("", config);
+        let refresh = |editor: &mut Editor| {
+            if let Err(err) = editor.refresh_screen() {
+                panic!("failed to refresh the screen: {err}");
+            }
+        };
+        refresh(&mut editor);
+        refresh(&mut editor);
+        assert_eq!(editor.terminal.output(), b"\x1b[2 q");
+        press(&mut editor, "i");
+        refresh(&mut editor);
+        assert_eq!(editor.terminal.output(), b"\x1b[2 q\x1b[6 q");
+    }
+
+    #[test]
+    fn cursor_column_counts_tabs_as_their_width() {
+        let mut editor = editor("\tab\tc");
+        press(&mut editor, "\x1b[C\x1b[C");
+        assert_eq!(editor.cursor_column(), 5);
+        press(&mut editor, "\x1b[C\x1b[C");
+        assert_eq!(editor.cursor_column(), 8);
+    }
+
+    #[test]
+    fn scrolls_to_keep_the_visual_column_in_view() {
+        let mut editor = editor(&"\t".repeat(30));
+        press(&mut editor, "\x1b[F");
+        assert_eq!(editor.cursor_column(), 120);
+        let screen_column = editor.cursor_column().saturating_sub(editor.offset.x);
+        assert!(screen_column < editor.text_width());
+        assert!(editor.offset.x > 0);
+    }
+
+    #[test]
+    fn literal_tab_is_inserted_despite_soft_tabs() {
+        let mut config = Config::default();
+        config.soft_tabs = true;
+        let mut editor = editor_with("ab", config);
+        press(&mut editor, "\x1b[C\t");
+        assert_eq!(lines(&editor), ["a   b"]);
+        press(&mut editor, "\x16\t");
+        assert_eq!(lines(&editor), ["a   \tb"]);
+    }
+
+    #[test]
+    fn literal_control_character_is_inserted() {
+        let mut editor = editor("");
+        press(&mut editor, "\x16\x01");
+        assert_eq!(lines(&editor), ["\x01"]);
+    }
+
+    /// Answers the prompt with the keys.
+    fn answer_prompt(editor: &mut Editor, keys: &str) -> Result<String, EditorError> {
+        editor.terminal.push_input(keys.as_bytes());
+        editor.prompt("Input: ", None)
+    }
+
+    #[test]
+    fn prompt_inserts_at_the_caret() {
+        let mut editor = editor("");
+        let input = answer_prompt(&mut editor, "ac\x1b[Db\x1b[Hx\r");
+        assert_eq!(input.ok().as_deref(), Some("xabc"));
+    }
+
+    #[test]
+    fn prompt_deletes_at_the_caret() {
+        let mut editor = editor("");
+        let input = answer_prompt(&mut editor, "abcd\x1b[D\x1b[D\x7f\x1b[3~\r");
+        assert_eq!(input.ok().as_deref(), Some("ad"));
+        let input = answer_prompt(&mut editor, "ab\x1b[H\x1b[3~\x1b[F\x1b[C\x7f\r");
+        assert!(matches!(input, Err(EditorError::Cancelled)));
+    }
+
+    #[test]
+    fn left_and_right_go_through_the_search_matches() {
+        let mut editor = editor("foo\nfoo\nfoo");
+        press(&mut editor, "\x06foo\x1b[C\x1b[C\x1b[D\r");
+        assert!(editor.cursor_position == Position { x: 0, y: 1 });
+    }
+
+    #[test]
+    fn prompt_callback_sees_the_evolving_input() {
+        let mut editor = editor("");
+        let mut seen = Vec::new();
+        let mut record = |_: &mut Editor, _: Key, input: &str| {
+            seen.push(input.to_owned());
+            None
+        };
+        editor.terminal.push_input(b"ab\x7fc\r");
+        let input = editor.prompt("Input: ", Some(&mut record));
+        assert_eq!(input.ok().as_deref(), Some("ac"));
+        assert_eq!(seen, ["a", "ab", "a", "ac"]);
+    }
+
+    #[test]
+    fn prompt_callback_replaces_the_input() {
+        let mut editor = editor("");
+        let mut upcase = |_: &mut Editor, key: Key, input: &str| {
+            (key == Key::Char('\t')).then(|| input.to_uppercase())
+        };
+        editor.terminal.push_input(b"ab\tc\r");
+        let input = editor.prompt("Input: ", Some(&mut upcase));
+        assert_eq!(input.ok().as_deref(), Some("ABc"));
+    }
+
+    #[test]
+    fn paste_into_a_prompt_takes_the_first_line() {
+        let mut editor = editor("");
+        let input = answer_prompt(&mut editor, "x\x1b[200~ab\rcd\x1b[201~y\r");
+        assert_eq!(input.ok().as_deref(), Some("xaby"));
+    }
+
+    #[test]
+    fn paste_into_the_document_is_inserted_as_is() {
+        let mut editor = editor("");
+        press(&mut editor, "\x1b[200~ab\rcd\x1b[201~");
+        assert_eq!(lines(&editor), ["ab", "cd"]);
+        assert!(editor.cursor_position == Position { x: 2, y: 1 });
+    }
+
+    #[test]
+    fn force_quit_ignores_unsaved_changes() {
+        let mut editor = editor("unsaved");
+        editor.run_command("q");
+        assert!(!editor.should_quit);
+        editor.run_command("q!");
+        assert!(editor.should_quit);
+    }
+
+    #[test]
+    fn save_quit_does_not_quit_if_saving_fails() {
+        let mut editor = editor("unsaved");
+        editor.document.set_filename("/nonexistent/hecto/file.txt");
+        editor.run_command("wq");
+        assert!(!editor.should_quit);
+        assert!(editor.document.is_dirty());
+    }
+
+    #[test]
+    fn save_quit_does_not_quit_if_the_filename_prompt_is_cancelled() {
+        let mut editor = editor("unsaved");
+        editor.terminal.push_input(b"\x1b");
+        editor.run_command("x");
+        assert!(!editor.should_quit);
+    }
+
+    #[test]
+    fn duplicate_moves_the_selection_onto_the_copy() {
+        let mut editor = editor("a\nb\nc");
+        editor.selection_anchor = Some(Position::default());
+        press(&mut editor, "\x1b[B");
+        editor.run_command("duplicate");
+        assert_eq!(lines(&editor), ["a", "b", "a", "b", "c"]);
+        assert!(editor.selection_anchor == Some(Position { x: 0, y: 2 }));
+        assert_eq!(editor.cursor_position.y, 3);
+    }
+
+    #[test]
+    fn duplicate_past_the_end_keeps_the_cursor() {
+        let mut editor = editor("");
+        editor.run_command("duplicate");
+        assert!(editor.cursor_position == Position::default());
+        assert!(editor.document.is_empty());
+    }
+
+    #[test]
+    fn tab_at_the_start_indents_and_shift_tab_dedents_the_line() {
+        let mut editor = editor("foo\nbar");
+        press(&mut editor, "\t");
+        assert_eq!(lines(&editor), ["\tfoo", "bar"]);
+        assert_eq!(editor.cursor_position.x, 1);
+        press(&mut editor, "\x1b[Z");
+        assert_eq!(lines(&editor), ["foo", "bar"]);
+        assert_eq!(editor.cursor_position.x, 0);
+    }
+
+    #[test]
+    fn tab_indents_the_selected_lines_but_empty_ones() {
+        let mut config = Config::default();
+        config.soft_tabs = true;
+        config.tab_width = 2;
+        let mut editor = editor_with("a\n\nb\nc", config);
+        press(&mut editor, "\x00\x1b[B\x1b[B\t");
+        assert_eq!(lines(&editor), ["  a", "", "  b", "c"]);
+        press(&mut editor, "\x1b[Z\x1b[Z");
+        assert_eq!(lines(&editor), ["a", "", "b", "c"]);
+    }
+
+    /// The text of the numbered lines, e.g., `1\n2\n3` for 3.
+    fn numbered_lines(count: usize) -> String {
+        (1..=count)
+            .map(|n| n.to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn toggling_the_gutter_keeps_the_cursor_line_and_screen_row() {
+        let mut editor = editor(&numbered_lines(100));
+        press(&mut editor, &"\x1b[B".repeat(50));
+        let screen_row = editor.screen_row(editor.cursor_position.y);
+        for _ in 0..3usize {
+            editor.run_command("line_numbers");
+            assert_eq!(editor.cursor_position.y, 50);
+            assert_eq!(editor.screen_row(editor.cursor_position.y), screen_row);
+        }
+        assert!(editor.gutter_mode == GutterMode::Off);
+    }
+
+    #[test]
+    fn toggling_the_clean_view_keeps_the_cursor_line() {
+        let mut editor = editor(&numbered_lines(100));
+        press(&mut editor, &"\x1b[B".repeat(70));
+        let offset = editor.offset.clone();
+        editor.run_command("clean_view");
+        assert_eq!(editor.cursor_position.y, 70);
+        assert!(editor.offset == offset);
+    }
+
+    #[test]
+    #[cfg(feature = "datetime")]
+    fn inserts_the_date_of_the_clock_at_the_cursor() {
+        use chrono::TimeZone;
+        let Some(now) = chrono::Local
+            .with_ymd_and_hms(2024, 2, 29, 13, 5, 0)
+            .single()
+        else {
+            panic!("the time is ambiguous");
+        };
+        let mut editor = editor("Date: ");
+        press(&mut editor, "\x1b[F");
+        editor.insert_date(&now);
+        assert_eq!(lines(&editor), ["Date: 2024-02-29 13:05"]);
+        editor.config.date_format = "%d/%m".to_owned();
+        editor.insert_date(&now);
+        assert_eq!(lines(&editor), ["Date: 2024-02-29 13:0529/02"]);
+    }
+
+    #[test]
+    #[cfg(feature = "datetime")]
+    fn invalid_date_format_inserts_nothing() {
+        let mut editor = editor("");
+        editor.config.date_format = "%Q".to_owned();
+        editor.insert_date(&chrono::Local::now());
+        assert!(editor.document.lines().all(str::is_empty));
+        assert_eq!(editor.status_message.text, "ERR: Invalid date_format.");
+    }
+
+    /// Makes the message look as if it were shown the seconds ago.
+    fn age_message(editor: &mut Editor, secs: u64) {
+        let Some(time) = Instant::now().checked_sub(Duration::from_secs(secs)) else {
+            panic!("the clock is too early");
+        };
+        editor.status_message.time = time;
+    }
+
+    #[test]
+    fn message_expires_after_the_timeout() {
+        let mut editor = editor("");
+        editor.config.message_timeout_secs = 5;
+        editor.status_message = StatusMessage::from("hello".to_owned());
+        assert_eq!(editor.message_bar(), "hello");
+        age_message(&mut editor, 6);
+        assert_eq!(editor.message_bar(), "");
+    }
+
+    #[test]
+    fn message_persists_without_a_timeout() {
+        let mut editor = editor("");
+        editor.config.message_timeout_secs = 0;
+        editor.status_message = StatusMessage::from("hello".to_owned());
+        age_message(&mut editor, 3600);
+        assert_eq!(editor.message_bar(), "hello");
+    }
+
+    #[test]
+    fn welcome_hints_follow_the_keymap() {
+        let mut config = Config::default();
+        config.keymap.save = KeyBinding(Key::Ctrl('w'));
+        config.keymap.quit = KeyBinding(Key::F(10));
+        let editor = editor_with("", config);
+        let lines = editor.welcome_lines();
+        assert_eq!(
+            lines.get(2..),
+            Some(
+                ["Ctrl-W to save", "F10 to quit", "Ctrl-F to find"]
+                    .map(str::to_owned)
+                    .as_slice()
+            )
+        );
+    }
+
+    #[test]
+    fn formats_elapsed_time_coarsely() {
+        assert_eq!(format_elapsed(Duration::from_secs(0)), "just now");
+        assert_eq!(format_elapsed(Duration::from_secs(4)), "just now");
+        assert_eq!(format_elapsed(Duration::from_secs(42)), "42s ago");
+        assert_eq!(format_elapsed(Duration::from_mins(1)), "1m ago");
+        assert_eq!(format_elapsed(Duration::from_secs(150)), "2m ago");
+        assert_eq!(format_elapsed(Duration::from_hours(1)), "1h ago");
+        assert_eq!(format_elapsed(Duration::from_mins(239)), "3h ago");
+    }
+
+    #[test]
+    fn types_at_three_cursors_on_different_rows() {
+        let mut editor = editor("one\ntwo\nthree");
+        press(&mut editor, "\x1b[C");
+        editor.add_cursor_below();
+        editor.add_cursor_below();
+        press(&mut editor, "xy");
+        assert_eq!(lines(&editor), ["oxyne", "txywo", "txyhree"]);
+        assert!(editor.cursor_position == Position { x: 3, y: 0 });
+        assert_eq!(editor.secondary_cursors.len(), 2);
+        press(&mut editor, "\x7f");
+        assert_eq!(lines(&editor), ["oxne", "txwo", "txhree"]);
+    }
+
+    #[test]
+    fn enter_at_cursors_keeps_them_on_their_lines() {
+        let mut editor = editor("ab\ncd\nef");
+        press(&mut editor, "\x1b[C");
+        editor.add_cursor_below();
+        editor.add_cursor_below();
+        press(&mut editor, "\r-");
+        assert_eq!(lines(&editor), ["a", "-b", "c", "-d", "e", "-f"]);
+    }
+
+    #[test]
+    fn moving_goes_back_to_a_single_cursor() {
+        let mut editor = editor("one\ntwo");
+        editor.add_cursor_below();
+        press(&mut editor, "\x1b[C");
+        assert!(editor.secondary_cursors.is_empty());
+        press(&mut editor, "x");
+        assert_eq!(lines(&editor), ["oxne", "two"]);
+    }
+
+    #[test]
+    fn selects_the_word_in_the_middle_of_a_line() {
+        let mut editor = editor("let some_value = 1;");
+        editor.cursor_position = Position { x: 7, y: 0 };
+        editor.select_word();
+        assert!(editor.selection_anchor == Some(Position { x: 4, y: 0 }));
+        assert!(editor.cursor_position == Position { x: 14, y: 0 });
+    }
+
+    #[test]
+    fn selects_an_empty_line_but_no_word_on_it() {
+        let mut editor = editor("one\n\nthree");
+        editor.cursor_position = Position { x: 0, y: 1 };
+        editor.select_word();
+        assert!(editor.selection_anchor.is_none());
+        assert_eq!(editor.status_message.text, "No word under the cursor.");
+        editor.select_line();
+        assert!(editor.selection_anchor == Some(Position { x: 0, y: 1 }));
+        assert!(editor.cursor_position == Position { x: 0, y: 1 });
+    }
+
+    #[test]
+    fn selects_the_whole_line() {
+        let mut editor = editor("one\ntwo words");
+        editor.cursor_position = Position { x: 5, y: 1 };
+        editor.select_line();
+        assert!(editor.selection_anchor == Some(Position { x: 0, y: 1 }));
+        assert!(editor.cursor_position == Position { x: 9, y: 1 });
+    }
+
+    #[test]
+    fn replays_a_recorded_macro_twice() {
+        let mut editor = modal_editor("a\nb\nc\nd");
+        press(&mut editor, "qai-");
+        press(&mut editor, "\x1b");
+        press(&mut editor, "j\x1b[Hq");
+        assert_eq!(lines(&editor), ["-a", "b", "c", "d"]);
+        press(&mut editor, ":replay a 2\r");
+        assert_eq!(lines(&editor), ["-a", "-b", "-c", "d"]);
+        assert!(editor.cursor_position.y == 3);
+        assert!(editor.replaying.is_empty());
+    }
+
+    #[test]
+    fn macro_cannot_replay_itself() {
+        let mut editor = modal_editor("a\nb\nc");
+        press(&mut editor, "qax@aq");
+        assert_eq!(lines(&editor), ["", "b", "c"]);
+        press(&mut editor, "j@a");
+        assert_eq!(lines(&editor), ["", "", "c"]);
+        assert_eq!(editor.status_message.text, "ERR: @a can't replay itself.");
+    }
+
+    #[test]
+    fn replays_a_recorded_paste_as_a_paste() {
+        let mut config = Config::default();
+        config.modal = true;
+        config.auto_indent = true;
+        config.soft_tabs = true;
+        config.tab_width = 4;
+        let mut editor = editor_with("", config);
+        editor.document.set_filename("main.rs");
+        press(&mut editor, "qa\x1b[200~if x {\r    y();\r}\r\x1b[201~q");
+        assert_eq!(lines(&editor), ["if x {", "    y();", "}", ""]);
+        press(&mut editor, "@a");
+        assert_eq!(
+            lines(&editor),
+            ["if x {", "    y();", "}", "if x {", "    y();", "}", ""]
+        );
+    }
+
+    #[test]
+    fn each_opened_file_is_indented_as_its_file_type() {
+        let python = TempPath::with_content("indent.py", "x = 1\n");
+        let go = TempPath::with_content("indent.go", "package main\n");
+        let mut editor = editor("");
+        editor.run_command(&format!("e {python}"));
+        assert!(editor.config.soft_tabs);
+        assert_eq!(editor.indent_unit(), "    ");
+        editor.run_command(&format!("e {go}"));
+        assert!(!editor.config.soft_tabs);
+        assert_eq!(editor.indent_unit(), "\t");
+        editor.run_command("bn");
+        assert_eq!(editor.indent_unit(), "    ");
+        editor.run_command("new");
+        assert!(!editor.config.soft_tabs);
+    }
+
+    #[test]
+    fn editing_the_same_file_spelled_differently_goes_to_its_buffer() {
+        let file = TempPath::with_content("edit-spelled.txt", "precious\n");
+        let path = Path::new(&*file);
+        let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+            panic!("no directory or name in {file}");
+        };
+        let spelled = format!("{}/./{}", dir.display(), name.to_string_lossy());
+        let mut editor = editor("");
+        editor.run_command(&format!("e {spelled}"));
+        assert_eq!(lines(&editor), ["precious"]);
+        editor.run_command("new");
+        editor.run_command(&format!("e {file}"));
+        editor.run_command(&format!("e {spelled}"));
+        assert_eq!(editor.buffers.len(), 1);
+        press(&mut editor, "x");
+        if let Err(err) = editor.save() {
+            panic!("failed to save {file}: {err}");
+        }
+        assert_eq!(
+            fs::read_to_string(&file).ok().as_deref(),
+            Some("xprecious\n")
+        );
+    }
+
+    #[test]
+    fn undoes_a_typed_word_as_a_whole() {
+        let mut editor = editor("");
+        press(&mut editor, "abc def");
+        press(&mut editor, "\x1a");
+        assert_eq!(lines(&editor), ["abc "]);
+        press(&mut editor, "\x1a");
+        assert_eq!(lines(&editor), ["abc"]);
+        press(&mut editor, "\x1a");
+        assert!(editor.document.is_empty());
+    }
+
+    #[test]
+    fn undo_steps_are_capped_at_undo_coalesce_max() {
+        let mut config = Config::default();
+        config.undo_coalesce_max = 3;
+        let mut editor = editor_with("", config);
+        press(&mut editor, "abcdefg");
+        press(&mut editor, "\x1a");
+        assert_eq!(lines(&editor), ["abcdef"]);
+        press(&mut editor, "\x1a");
+        assert_eq!(lines(&editor), ["abc"]);
+        press(&mut editor, "\x1a");
+        assert!(editor.document.is_empty());
+    }
+
+    #[test]
+    fn moving_the_cursor_ends_the_undo_step() {
+        let mut editor = editor("");
+        press(&mut editor, "ab\x1b[Dc");
+        assert_eq!(lines(&editor), ["acb"]);
+        press(&mut editor, "\x1a");
+        assert_eq!(lines(&editor), ["ab"]);
+    }
+
+    #[test]
+    fn fills_the_progress_bar_in_proportion() {
+        assert_eq!(progress_bar(0, 100, 10), "[----------]");
+        assert_eq!(progress_bar(25, 100, 10), "[##--------]");
+        assert_eq!(progress_bar(99, 100, 10), "[#########-]");
+        assert_eq!(progress_bar(100, 100, 10), "[##########]");
+        assert_eq!(progress_bar(250, 100, 10), "[##########]");
+        assert_eq!(progress_bar(3, 0, 4), "[####]");
+    }
+
+    #[test]
+    fn status_bar_fits_the_width_with_the_goal_met() {
+        let mut config = Config::default();
+        config.word_goal = 2;
+        let editor = editor_with("two words", config);
+        let status = editor.status_bar(Instant::now());
+        assert!(status.contains('\u{2713}'));
+        assert_eq!(status.width(), 80);
+    }
+
+    #[test]
+    fn truncates_to_the_width_on_character_boundaries() {
+        let mut text = "caf\u{e9}\u{4e2d}\u{6587}".to_owned();
+        truncate_to_width(&mut text, 6);
+        assert_eq!(text, "caf\u{e9}\u{4e2d}");
+        truncate_to_width(&mut text, 5);
+        assert_eq!(text, "caf\u{e9}");
+        truncate_to_width(&mut text, 10);
+        assert_eq!(text, "caf\u{e9}");
+    }
+
+    #[test]
+    fn inserts_a_file_in_the_middle_of_a_row() {
+        let path = TempPath::with_content("insert.txt", "one\r\ntwo");
+        let mut editor = editor("abcd\nend");
+        editor.cursor_position = Position { x: 2, y: 0 };
+        editor.insert_file(&path);
+        assert_eq!(lines(&editor), ["abone", "twocd", "end"]);
+        assert!(editor.cursor_position == Position { x: 3, y: 1 });
+        assert!(editor.document.is_dirty());
+        press(&mut editor, "\x1a");
+        assert_eq!(lines(&editor), ["abcd", "end"]);
+    }
+
+    #[test]
+    fn inserting_a_missing_file_reports_it() {
+        let path = TempPath::new("missing.txt");
+        let mut editor = editor("abcd");
+        editor.insert_file(&path);
+        assert_eq!(lines(&editor), ["abcd"]);
+        assert!(editor
+            .status_message
+            .text
+            .starts_with(&format!("ERR: Could not read {path}")));
+    }
+
+    #[test]
+    fn writes_the_selected_rows_to_a_file() {
+        let path = TempPath::new("selection.txt");
+        let mut editor = editor("one\ntwo\nthree\nfour");
+        editor.cursor_position = Position { x: 1, y: 1 };
+        editor.selection_anchor = Some(Position { x: 2, y: 2 });
+        editor.write_selection(&path);
+        assert_eq!(
+            fs::read_to_string(&path).ok().as_deref(),
+            Some("two\nthree\n")
+        );
+        assert_eq!(lines(&editor), ["one", "two", "three", "four"]);
+    }
+
+    #[test]
+    fn writes_the_current_row_without_a_selection() {
+        let path = TempPath::new("current-row.txt");
+        let mut editor = editor("one\ntwo");
+        editor.cursor_position = Position { x: 0, y: 1 };
+        editor.write_selection(&path);
+        assert_eq!(fs::read_to_string(&path).ok().as_deref(), Some("two\n"));
+        editor.selection_anchor = Some(Position { x: 0, y: 0 });
+        editor.terminal.push_input(b"n\r");
+        editor.write_selection(&path);
+        assert_eq!(editor.status_message.text, "Write aborted.");
+        assert_eq!(fs::read_to_string(&path).ok().as_deref(), Some("two\n"));
+    }
+
+    #[test]
+    fn clean_document_needs_the_quit_sequence_with_confirm_quit_always() {
+        let mut config = Config::default();
+        config.confirm_quit_always = true;
+        let mut editor = Editor::with(Terminal::mock(80, 24), Document::default(), config);
+        for _ in 0..QUIT_TIMES {
+            press(&mut editor, "\x11");
+            assert!(!editor.should_quit);
+        }
+        press(&mut editor, "\x11");
+        assert!(editor.should_quit);
+    }
+
+    #[test]
+    fn screen_row_stays_put_while_scrolling_down() {
+        let mut config = Config::default();
+        config.scroll_off = 3;
+        let mut editor = editor_with(&numbered_lines(100), config);
+        let height = editor.view().len();
+        press(&mut editor, &"\x1b[B".repeat(height));
+        let screen_row = editor.screen_row(editor.cursor_position.y);
+        assert_eq!(screen_row, height.saturating_sub(4));
+        for _ in 0..40usize {
+            press(&mut editor, "\x1b[B");
+            assert_eq!(editor.screen_row(editor.cursor_position.y), screen_row);
+        }
+        press(&mut editor, &"\x1b[A".repeat(height));
+        assert_eq!(editor.screen_row(editor.cursor_position.y), 3);
+    }
+
+    #[test]
+    fn new_buffer_starts_an_empty_unnamed_document() {
+        let path = TempPath::with_content("new-buffer.txt", "one\ntwo\n");
+        let mut editor = editor("");
+        editor.run_command(&format!("e {path}"));
+        press(&mut editor, "\x1b[Bx");
+        editor.run_command("new");
+        assert!(editor.document.is_empty());
+        assert!(editor.document.filename().is_none());
+        assert!(!editor.document.is_dirty());
+        assert!(editor.cursor_position == Position::default());
+        assert!(editor.offset == Position::default());
+    }
+
+    #[test]
+    fn new_buffer_keeps_the_unsaved_changes_open() {
+        let mut editor = editor("");
+        press(&mut editor, "x");
+        editor.run_command("new");
+        assert!(editor.document.is_empty());
+        assert_eq!(editor.buffers.len(), 1);
+        editor.run_command("new");
+        assert_eq!(editor.buffers.len(), 1);
+        editor.switch_to_buffer(0);
+        assert_eq!(lines(&editor), ["x"]);
+    }
+
+    /// Whether the screen has been flashed by the visual bell.
+    fn has_flashed(editor: &Editor) -> bool {
+        editor
+            .terminal
+            .output()
+            .windows(5)
+            .any(|window| window == b"\x1b[?5h")
+    }
+
+    #[test]
+    fn search_without_a_match_rings_the_bell() {
+        let mut config = Config::default();
+        config.visual_bell = true;
+        let mut editor = editor_with("one two", config);
+        press(&mut editor, "\x06two\r");
+        assert!(!has_flashed(&editor));
+        press(&mut editor, "\x06three\r");
+        assert!(has_flashed(&editor));
+        assert_eq!(editor.status_message.text, "Not found: three");
+    }
+
+    #[test]
+    fn bell_is_silent_without_visual_bell() {
+        let mut editor = editor("one");
+        press(&mut editor, "\x06three\r");
+        assert!(!has_flashed(&editor));
+    }
+
+    #[test]
+    fn goes_back_and_forward_from_a_goto_line() {
+        let mut editor = modal_editor(&numbered_lines(50));
+        press(&mut editor, "jj");
+        editor.run_command("goto 40");
+        assert!(editor.cursor_position == Position { x: 0, y: 39 });
+        press(&mut editor, "\x0f");
+        assert!(editor.cursor_position == Position { x: 0, y: 2 });
+        press(&mut editor, "\t");
+        assert!(editor.cursor_position == Position { x: 0, y: 39 });
+    }
+
+    #[test]
+    fn small_movements_are_not_jumps() {
+        let mut editor = modal_editor(&numbered_lines(10));
+        press(&mut editor, "jjj");
+        press(&mut editor, "\x0f");
+        assert!(editor.cursor_position == Position { x: 0, y: 3 });
+    }
+
+    /// An editor of a Rust file of the text, with `auto_indent` and soft tabs of 4 spaces.
+    fn code_editor(text: &str) -> Editor {
+        let mut config = Config::default();
+        config.auto_indent = true;
+        config.soft_tabs = true;
+        config.tab_width = 4;
+        let mut editor = editor_with(text, config);
+        editor.document.set_filename("main.rs");
+        editor
+    }
+
+    #[test]
+    fn enter_after_an_opening_brace_indents_one_more_level() {
+        let mut editor = code_editor("    if x {");
+        press(&mut editor, "\x1b[F\ry();");
+        assert_eq!(lines(&editor), ["    if x {", "        y();"]);
+    }
+
+    #[test]
+    fn enter_between_braces_puts_the_closing_one_on_its_own_line() {
+        let mut editor = code_editor("fn f() {}");
+        press(&mut editor, "\x1b[F\x1b[D\r");
+        assert_eq!(lines(&editor), ["fn f() {", "    ", "}"]);
+        assert!(editor.cursor_position == Position { x: 4, y: 1 });
+    }
+
+    #[test]
+    fn enter_in_prose_keeps_the_indentation() {
+        let mut config = Config::default();
+        config.auto_indent = true;
+        let mut editor = editor_with("  note {", config);
+        editor.document.set_filename("notes.txt");
+        press(&mut editor, "\x1b[F\r");
+        assert_eq!(lines(&editor), ["  note {", "  "]);
+    }
+
+    #[test]
+    fn diffs_against_the_saved_file() {
+        let path = TempPath::with_content("diff.txt", "one\ntwo\n");
+        let mut editor = editor("");
+        editor.run_command(&format!("e {path}"));
+        editor.run_command("diff");
+        assert_eq!(
+            editor.status_message.text,
+            format!("No changes against {path}.")
+        );
+        press(&mut editor, "\x1b[Bx\r");
+        editor.run_command("diff");
+        assert_eq!(editor.status_message.text, format!("+1 -0 against {path}"));
+    }
+
+    #[test]
+    #[cfg(feature = "encoding")]
+    fn diffs_in_the_encoding_of_the_document() {
+        let path = TempPath::new("diff-latin1.txt");
+        if let Err(err) = fs::write(&path, b"caf\xe9\n") {
+            panic!("failed to write {path}: {err}");
+        }
+        let Ok(document) = Document::open_with_encoding(&path, Some("latin1")) else {
+            panic!("failed to open {path}");
+        };
+        let mut editor = Editor::with(Terminal::mock(80, 24), document, Config::default());
+        editor.run_command("diff");
+        assert_eq!(
+            editor.status_message.text,
+            format!("No changes against {path}.")
+        );
+    }
+
+    #[test]
+    fn formats_the_codepoints_of_a_grapheme() {
+        assert_eq!(format_codepoints("a"), "U+0061");
+        assert_eq!(format_codepoints("\u{e9}"), "U+00E9");
+        assert_eq!(format_codepoints("e\u{301}"), "U+0065 U+0301");
+        assert_eq!(format_codepoints("\u{1f600}"), "U+1F600");
+        assert_eq!(format_codepoints("\u{1f44d}\u{1f3fd}"), "U+1F44D U+1F3FD");
+    }
+
+    #[test]
+    fn saves_without_the_final_newline_just_once() {
+        let path = TempPath::with_content("noeol-once.txt", "one\ntwo\n");
+        let mut editor = editor("");
+        editor.run_command(&format!("e {path}"));
+        press(&mut editor, "x");
+        editor.run_command("save_without_final_newline");
+        assert_eq!(fs::read_to_string(&path).ok().as_deref(), Some("xone\ntwo"));
+        press(&mut editor, "y");
+        editor.run_command("w");
+        assert_eq!(
+            fs::read_to_string(&path).ok().as_deref(),
+            Some("xyone\ntwo\n")
+        );
+    }
+
+    #[test]
+    fn moving_down_and_up_steps_over_a_fold() {
+        let mut editor = editor(&numbered_lines(10));
+        editor.folds.fold(3..=6, editor.document.len());
+        press(&mut editor, "\x1b[B\x1b[B\x1b[B");
+        assert_eq!(editor.cursor_position.y, 3);
+        press(&mut editor, "\x1b[B");
+        assert_eq!(editor.cursor_position.y, 7);
+        press(&mut editor, "\x1b[A");
+        assert_eq!(editor.cursor_position.y, 3);
+        press(&mut editor, "\x1b[A");
+        assert_eq!(editor.cursor_position.y, 2);
+    }
+
+    #[test]
+    fn going_to_a_folded_line_unfolds_it() {
+        let mut editor = editor(&numbered_lines(10));
+        editor.folds.fold(3..=6, editor.document.len());
+        editor.run_command("goto 6");
+        assert_eq!(editor.cursor_position.y, 5);
+        assert!(editor.folds.fold_at(5).is_none());
+        assert_eq!(editor.screen_row(5), 5);
+    }
+
+    #[test]
+    fn folds_the_section_around_the_cursor() {
+        let mut editor = editor("a\n\nb\nc\nd\n\ne");
+        editor.cursor_position = Position { x: 0, y: 3 };
+        editor.run_command("fold");
+        assert!(editor.folds.fold_at(3) == Some(&(2..=4)));
+        assert_eq!(editor.cursor_position.y, 2);
+        press(&mut editor, "\x1b[B");
+        assert_eq!(editor.cursor_position.y, 5);
+    }
+
+    fn pairing_editor(text: &str, modal: bool) -> Editor {
+        let mut config = Config::default();
+        config.auto_pair = true;
+        config.modal = modal;
+        editor_with(text, config)
+    }
+
+    #[test]
+    fn pairs_brackets_and_types_over_the_closing_one() {
+        let mut editor = pairing_editor("", false);
+        press(&mut editor, "f(x");
+        assert_eq!(lines(&editor), ["f(x)"]);
+        press(&mut editor, ")!");
+        assert_eq!(lines(&editor), ["f(x)!"]);
+    }
+
+    #[test]
+    fn apostrophe_after_a_word_is_not_paired() {
+        let mut editor = pairing_editor("", false);
+        press(&mut editor, "don't say 'hi");
+        assert_eq!(lines(&editor), ["don't say 'hi'"]);
+    }
+
+    #[test]
+    fn dot_repeats_the_whole_auto_pair() {
+        let mut editor = pairing_editor("a\nb", true);
+        press(&mut editor, "i(");
+        press(&mut editor, "\x1b");
+        assert_eq!(lines(&editor), ["()a", "b"]);
+        press(&mut editor, "j\x1b[H.");
+        assert_eq!(lines(&editor), ["()a", "()b"]);
+    }
+
+    #[test]
+    fn dot_repeats_text_typed_inside_an_auto_pair() {
+        let mut editor = pairing_editor("a\nb", true);
+        press(&mut editor, "i[(x\x7fy");
+        press(&mut editor, "\x1b");
+        assert_eq!(lines(&editor), ["[(y)]a", "b"]);
+        press(&mut editor, "j\x1b[H.");
+        assert_eq!(lines(&editor), ["[(y)]a", "[(y)]b"]);
+    }
+
+    #[test]
+    fn saves_all_buffers_and_reports_the_failures() {
+        let first = TempPath::with_content("save-all-1.txt", "one\n");
+        let second = TempPath::with_content("save-all-2.txt", "two\n");
+        let unwritable = format!("{}/file.txt", TempPath::new("save-all-missing-dir"));
+        let mut editor = editor("");
+        for path in [&*first, &*second, &unwritable] {
+            editor.run_command(&format!("e {path}"));
+            press(&mut editor, "x");
+        }
+        editor.run_command("new");
+        press(&mut editor, "x");
+        editor.run_command("save_all");
+        assert_eq!(
+            editor.status_message.text,
+            format!("2 saved, 1 failed: {unwritable}, 1 unnamed skipped")
+        );
+        assert_eq!(fs::read_to_string(&first).ok().as_deref(), Some("xone\n"));
+        assert_eq!(fs::read_to_string(&second).ok().as_deref(), Some("xtwo\n"));
+        editor.run_command("save_all");
+        assert_eq!(
+            editor.status_message.text,
+            format!("0 saved, 1 failed: {unwritable}, 1 unnamed skipped")
+        );
+    }
+
+    #[test]
+    fn save_all_without_changes_says_so() {
+        let mut editor = editor("");
+        editor.run_command("save_all");
+        assert_eq!(editor.status_message.text, "No unsaved changes.");
+    }
+
+    fn nearest_indent_editor(text: &str) -> Editor {
+        let mut config = Config::default();
+        config.modal = true;
+        config.auto_indent = true;
+        config.auto_indent_from_nearest = true;
+        editor_with(text, config)
+    }
+
+    #[test]
+    fn opening_a_line_below_a_blank_one_copies_the_nearest_indentation() {
+        let mut editor = nearest_indent_editor("fn f() {\n    a();\n\n}");
+        press(&mut editor, "jjox");
+        assert_eq!(lines(&editor), ["fn f() {", "    a();", "", "    x", "}"]);
+    }
+
+    #[test]
+    fn opening_a_line_above_a_blank_one_copies_the_nearest_indentation() {
+        let mut editor = nearest_indent_editor("\tif x {\n\n}");
+        press(&mut editor, "jOy");
+        assert_eq!(lines(&editor), ["\tif x {", "\ty", "", "}"]);
+    }
+
+    #[test]
+    fn opening_a_line_below_a_blank_one_stays_unindented_without_the_option() {
+        let mut config = Config::default();
+        config.modal = true;
+        config.auto_indent = true;
+        let mut editor = editor_with("    a();\n\n}", config);
+        press(&mut editor, "jox");
+        assert_eq!(lines(&editor), ["    a();", "", "x", "}"]);
+    }
+
+    #[test]
+    fn filter_replaces_the_document_with_the_output() {
+        let mut editor = editor("b\na\nc");
+        editor.run_command("filter sort");
+        assert_eq!(lines(&editor), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn keys_typed_while_filtering_are_processed_after() {
+        let mut editor = modal_editor("b\na\nc");
+        press(&mut editor, ":filter sleep 0.2; sort\rjjx");
+        assert_eq!(lines(&editor), ["a", "b", ""]);
+    }
+
+    #[test]
+    fn failing_filter_leaves_the_document_and_shows_the_error() {
+        let mut editor = editor("foo");
+        editor.run_command("filter echo \u{e9}\u{e9}\u{e9}\u{e9} >&2; exit 1");
+        assert_eq!(lines(&editor), ["foo"]);
+        assert!(editor
+            .status_message
+            .text
+            .contains("\u{e9}\u{e9}\u{e9}\u{e9}"));
+    }
+
+    #[test]
+    fn message_bar_cuts_wide_text_by_width() {
+        let mut editor = editor("");
+        editor.terminal = Terminal::mock(5, 24);
+        editor.status_message = StatusMessage::from("\u{e9}\u{4e2d}\u{4e2d}\u{e9}".to_owned());
+        assert_eq!(editor.message_bar(), "\u{e9}\u{4e2d}\u{4e2d}");
+    }
+
+    #[test]
+    fn split_layout_leaves_a_row_between_the_panes() {
+        assert_eq!(split_layout(22), (0..10, 11..22));
+        assert_eq!(split_layout(3), (0..1, 2..3));
+        assert_eq!(split_layout(2), (0..0, 1..2));
+    }
+
+    #[test]
+    fn focuses_either_pane_of_a_split() {
+        let mut editor = editor(&numbered_lines(100));
+        editor.run_command("split");
+        assert_eq!(editor.view(), 0..10);
+        editor.run_command("goto 50");
+        editor.run_command("other_pane");
+        assert_eq!(editor.view(), 11..22);
+        assert_eq!(editor.cursor_position.y, 0);
+        editor.run_command("other_pane");
+        assert_eq!(editor.view(), 0..10);
+        assert_eq!(editor.cursor_position.y, 49);
+        editor.run_command("close_pane");
+        assert!(editor.split.is_none());
+        assert_eq!(editor.view(), 0..22);
+        assert_eq!(editor.cursor_position.y, 0);
+    }
+
+    #[test]
+    fn refuses_to_split_too_few_rows() {
+        let mut editor = editor("foo");
+        editor.terminal = Terminal::mock(80, 4);
+        editor.run_command("split");
+        assert!(editor.split.is_none());
+        assert!(editor.status_message.text.starts_with("ERR:"));
+    }
+
+    #[test]
+    fn drawing_the_other_pane_leaves_its_folds_alone() {
+        let mut editor = editor(&numbered_lines(10));
+        editor.cursor_position = Position { x: 0, y: 4 };
+        editor.run_command("split");
+        editor.folds.fold(3..=6, editor.document.len());
+        editor.cursor_position = Position { x: 0, y: 0 };
+        editor.draw_other_pane();
+        assert!(editor.folds.fold_at(4) == Some(&(3..=6)));
+    }
+
+    #[test]
+    fn other_pane_is_clamped_into_a_shrunk_document() {
+        let mut editor = editor(&numbered_lines(10));
+        editor.cursor_position = Position { x: 1, y: 9 };
+        editor.run_command("split");
+        editor.document.replace_rows(0..=9, vec!["x".to_owned()]);
+        editor.cursor_position = Position { x: 0, y: 0 };
+        editor.draw_other_pane();
+        editor.run_command("other_pane");
+        assert!(editor.cursor_position == Position { x: 0, y: 1 });
+    }
+
+    #[test]
+    fn going_to_an_error_opens_its_file_at_its_location() {
+        let path = TempPath::with_content("error-target.c", "int a;\nint b;\nint c;\n");
+        let errors = TempPath::with_content(
+            "errors.txt",
+            format!("{path}:2:5: error: b\n{path}:9:1: c\n"),
+        );
+        let mut editor = editor("");
+        editor.run_command(&format!("errors {errors}"));
+        editor.run_command("next_error");
+        assert_eq!(editor.document.filename(), Some(&*path));
+        assert!(editor.cursor_position == Position { x: 4, y: 1 });
+        assert!(editor.status_message.text.ends_with("error: b"));
+        // Past the end of the file, it's clamped into it.
+        editor.run_command("next_error");
+        assert!(editor.cursor_position == Position { x: 0, y: 3 });
+    }
+
+    #[test]
+    fn going_to_an_error_in_a_missing_file_does_not_create_it() {
+        let path = TempPath::new("missing.c");
+        let errors = TempPath::with_content("missing-errors.txt", format!("{path}:1:1: error\n"));
+        let mut editor = editor("foo");
+        editor.run_command(&format!("errors {errors}"));
+        editor.run_command("next_error");
+        assert_eq!(lines(&editor), ["foo"]);
+        assert!(editor.buffers.is_empty());
+        assert!(editor
+            .status_message
+            .text
+            .starts_with("ERR: Could not open file"));
+    }
+
+    #[test]
+    fn refuses_to_load_errors_from_the_standard_input_after_startup() {
+        let mut editor = editor("");
+        editor.run_command("errors -");
+        assert!(editor.errors.is_empty());
+        assert!(editor.status_message.text.starts_with("ERR:"));
+    }
+
+    #[test]
+    fn typing_new_lines_at_the_bottom_keeps_the_scroll_off_margin() {
+        let mut config = Config::default();
+        config.scroll_off = 3;
+        let mut editor = editor_with("", config);
+        let height = editor.view().len();
+        press(&mut editor, &"x\r".repeat(height.saturating_mul(2)));
+        assert_eq!(editor.cursor_position.y, height.saturating_mul(2));
+        assert_eq!(
+            editor.screen_row(editor.cursor_position.y),
+            height.saturating_sub(4)
+        );
+    }
+
+    #[test]
+    fn typing_new_lines_at_the_bottom_without_scroll_off_reaches_the_edge() {
+        let mut editor = editor("");
+        let height = editor.view().len();
+        press(&mut editor, &"x\r".repeat(height.saturating_mul(2)));
+        assert_eq!(
+            editor.screen_row(editor.cursor_position.y),
+            height.saturating_sub(1)
+        );
+    }
+
+    #[test]
+    fn converts_the_case_of_the_selection_only() {
+        let mut editor = editor("foo bar baz\nqux quux");
+        editor.selection_anchor = Some(Position { x: 4, y: 0 });
+        editor.cursor_position = Position { x: 3, y: 1 };
+        editor.run_command("upper");
+        assert_eq!(lines(&editor), ["foo BAR BAZ", "QUX quux"]);
+        // The anchor may be after the cursor.
+        editor.selection_anchor = Some(Position { x: 7, y: 0 });
+        editor.cursor_position = Position { x: 5, y: 0 };
+        editor.run_command("lower");
+        assert_eq!(lines(&editor), ["foo Bar BAZ", "QUX quux"]);
+    }
+
+    #[test]
+    fn converts_the_case_of_the_word_at_the_cursor() {
+        let mut editor = editor("hello w\u{f6}rld");
+        editor.cursor_position = Position { x: 8, y: 0 };
+        editor.run_command("title");
+        assert_eq!(lines(&editor), ["hello W\u{f6}rld"]);
+        editor.run_command("upper");
+        assert_eq!(lines(&editor), ["hello W\u{d6}RLD"]);
+    }
+
+    /// Whether the status bar on the screen is no longer as it would be drawn now.
+    fn is_status_bar_stale(editor: &Editor) -> bool {
+        editor.drawn_status_bar.as_ref() != Some(&editor.status_bar(Instant::now()))
+    }
+
+    #[test]
+    fn status_bar_is_not_redrawn_for_moving_within_a_line() {
+        let mut editor = editor("foo bar\nbaz");
+        editor.draw_status_bar();
+        assert!(!is_status_bar_stale(&editor));
+        press(&mut editor, "\x1b[C\x1b[C\x1b[F");
+        assert!(!is_status_bar_stale(&editor));
+        press(&mut editor, "\x1b[B");
+        assert!(is_status_bar_stale(&editor));
+        editor.draw_status_bar();
+        assert!(!is_status_bar_stale(&editor));
+    }
+
+    #[test]
+    fn status_bar_is_redrawn_once_the_line_count_changes() {
+        let mut editor = editor("foo");
+        editor.draw_status_bar();
+        press(&mut editor, "x");
+        assert!(!is_status_bar_stale(&editor));
+        press(&mut editor, "\r");
+        assert!(is_status_bar_stale(&editor));
+    }
+
+    #[test]
+    fn reopens_the_closed_file_where_the_cursor_was() {
+        let path = TempPath::with_content("reopen.txt", "one\ntwo\nthree\n");
+        let mut editor = editor("");
+        editor.run_command(&format!("e {path}"));
+        editor.cursor_position = Position { x: 2, y: 2 };
+        editor.run_command("close_buffer");
+        assert!(editor.document.filename().is_none());
+        editor.run_command("reopen_buffer");
+        assert_eq!(editor.document.filename(), Some(&*path));
+        assert!(editor.cursor_position == Position { x: 2, y: 2 });
+        editor.run_command("reopen_buffer");
+        assert!(editor.status_message.text.starts_with("No closed files"));
+    }
+
+    #[test]
+    fn reopening_a_shrunk_file_clamps_the_cursor_into_it() {
+        let path = TempPath::with_content("reopen-shrunk.txt", "one\ntwo\nthree\n");
+        let mut editor = editor("");
+        editor.run_command(&format!("e {path}"));
+        editor.cursor_position = Position { x: 4, y: 2 };
+        editor.run_command("close_buffer");
+        if let Err(err) = fs::write(&path, "uno\n") {
+            panic!("failed to write {path}: {err}");
+        }
+        editor.run_command("reopen_buffer");
+        assert!(editor.cursor_position == Position { x: 0, y: 1 });
+    }
+
+    #[test]
+    fn reopening_a_deleted_file_does_not_create_it() {
+        let path = TempPath::with_content("reopen-deleted.txt", "one\n");
+        let mut editor = editor("");
+        editor.run_command(&format!("e {path}"));
+        editor.run_command("close_buffer");
+        drop(fs::remove_file(&path));
+        editor.run_command("reopen_buffer");
+        assert!(editor.document.filename().is_none());
+        assert!(editor
+            .status_message
+            .text
+            .starts_with("ERR: Could not open file"));
+    }
+
+    #[test]
+    fn can_delete_backward_anywhere_but_the_very_start() {
+        let mut editor = editor("abc\ndef\n\n\n");
+        assert!(!editor.can_delete_backward());
+        editor.cursor_position = Position { x: 1, y: 0 };
+        assert!(editor.can_delete_backward());
+        editor.cursor_position = Position { x: 0, y: 3 };
+        assert!(editor.can_delete_backward());
+    }
+
+    fn backspace_bell_editor(visual_bell: bool) -> Editor {
+        let mut config = Config::default();
+        config.bell_on_backspace_at_start = true;
+        config.visual_bell = visual_bell;
+        editor_with("abc", config)
+    }
+
+    #[test]
+    fn backspace_at_the_start_rings_the_audible_bell() {
+        let mut editor = backspace_bell_editor(false);
+        press(&mut editor, "\x7f");
+        assert!(editor.terminal.output().contains(&b'\x07'));
+        assert!(!has_flashed(&editor));
+        assert_eq!(lines(&editor), ["abc"]);
+    }
+
+    #[test]
+    fn backspace_at_the_start_flashes_with_visual_bell() {
+        let mut editor = backspace_bell_editor(true);
+        press(&mut editor, "\x7f");
+        assert!(has_flashed(&editor));
+        assert!(!editor.terminal.output().contains(&b'\x07'));
+    }
+
+    #[test]
+    fn backspace_at_the_start_is_silent_by_default() {
+        let mut editor = editor("abc");
+        press(&mut editor, "\x7f");
+        assert!(editor.terminal.output().is_empty());
+        press(&mut editor, "\x1b[C\x7f");
+        assert_eq!(lines(&editor), ["bc"]);
+    }
+
+    #[test]
+    fn gutter_mode_cycles_off_absolute_relative() {
+        assert!(GutterMode::Off.next() == GutterMode::Absolute);
+        assert!(GutterMode::Absolute.next() == GutterMode::Relative);
+        assert!(GutterMode::Relative.next() == GutterMode::Off);
+    }
+
+    #[test]
+    fn cycling_line_numbers_recomputes_the_text_width_and_says_the_mode() {
+        let mut editor = editor(&numbered_lines(100));
+        assert_eq!(editor.text_width(), 80);
+        editor.run_command("line_numbers");
+        assert_eq!(editor.text_width(), 76);
+        assert_eq!(editor.status_message.text, "Line numbers: absolute.");
+        editor.run_command("line_numbers");
+        assert_eq!(editor.text_width(), 76);
+        assert_eq!(editor.status_message.text, "Line numbers: relative.");
+        editor.run_command("line_numbers");
+        assert_eq!(editor.text_width(), 80);
+        assert_eq!(editor.status_message.text, "Line numbers: off.");
+    }
+
+    #[test]
+    fn relative_line_numbers_count_a_fold_as_one_line() {
+        let mut editor = editor(&numbered_lines(20));
+        editor.gutter_mode = GutterMode::Relative;
+        editor.folds.fold(3..=6, editor.document.len());
+        editor.cursor_position = Position { x: 0, y: 1 };
+        assert_eq!(editor.line_number(1), 2);
+        assert_eq!(editor.line_number(0), 1);
+        assert_eq!(editor.line_number(3), 2);
+        assert_eq!(editor.line_number(7), 3);
+        editor.gutter_mode = GutterMode::Absolute;
+        assert_eq!(editor.line_number(7), 8);
+    }
+
+    #[test]
+    fn enter_copies_a_tab_indentation_despite_soft_tabs() {
+        let mut editor = code_editor("\tfoo");
+        press(&mut editor, "\x1b[F\rbar");
+        assert_eq!(lines(&editor), ["\tfoo", "\tbar"]);
+    }
+
+    #[test]
+    fn enter_copies_a_space_indentation() {
+        let mut editor = code_editor("  foo");
+        editor.config.soft_tabs = false;
+        press(&mut editor, "\x1b[F\rbar");
+        assert_eq!(lines(&editor), ["  foo", "  bar"]);
+    }
+
+    #[test]
+    fn enter_copies_a_mixed_indentation_as_is() {
+        let mut editor = code_editor(" \t foo");
+        press(&mut editor, "\x1b[F\rbar");
+        assert_eq!(lines(&editor), [" \t foo", " \t bar"]);
+    }
+
+    #[test]
+    fn idle_editor_has_nothing_to_redraw() {
+        let mut editor = editor("foo");
+        editor.status_message = StatusMessage::from("Hello.".to_owned());
+        let now = Instant::now();
+        let state = editor.timed_state(now);
+        assert!(editor.timed_state(now + Duration::from_secs(1)) == state);
+        age_message(&mut editor, 60);
+        assert!(editor.timed_state(now) != state);
+    }
+
+    #[test]
+    fn saved_ago_changes_the_timed_state() {
+        let path = TempPath::with_content("timed-state.txt", "foo\n");
+        let mut config = Config::default();
+        config.show_last_saved = true;
+        let mut editor = editor_with("", config);
+        editor.run_command(&format!("e {path}"));
+        editor.run_command("w");
+        let now = Instant::now();
+        let state = editor.timed_state(now);
+        assert!(editor.timed_state(now + Duration::from_secs(1)) == state);
+        assert!(editor.timed_state(now + Duration::from_mins(2)) != state);
+    }
+
+    #[test]
+    fn waiting_returns_at_once_with_input_pending() {
+        let mut editor = editor("");
+        editor.terminal.push_input(b"x");
+        let started = Instant::now();
+        if let Err(err) = editor.wait_for_input() {
+            panic!("failed to wait for input: {err}");
+        }
+        assert!(started.elapsed() < FRAME_INTERVAL);
+    }
+
+    #[test]
+    fn status_bar_shows_the_selected_counts() {
+        let mut editor = editor("abc\ndefg\nhi");
+        assert!(!editor.status_bar(Instant::now()).contains("selected"));
+        editor.selection_anchor = Some(Position { x: 2, y: 2 });
+        editor.cursor_position = Position { x: 1, y: 0 };
+        assert!(editor
+            .status_bar(Instant::now())
+            .contains("(9 chars, 3 lines selected)"));
+    }
+
+    #[test]
+    fn welcome_line_starts_with_the_marker() {
+        let editor = editor("");
+        let line = editor.welcome_line("Hello");
+        assert!(line.starts_with("~ "));
+        assert_eq!(line.trim_start_matches(['~', ' ']), "Hello");
+        assert_eq!(line.width(), 44);
+    }
+
+    #[test]
+    fn welcome_line_with_a_blank_marker_has_no_tilde() {
+        let mut config = Config::default();
+        config.empty_row_marker = String::new();
+        let editor = editor_with("", config);
+        let line = editor.welcome_line("Hello");
+        assert!(!line.contains('~'));
+        // Still centered, as with a marker.
+        assert_eq!(line.trim_start(), "Hello");
+        assert_eq!(line.width(), 44);
+    }
+
+    #[test]
+    fn welcome_line_with_a_wide_marker_is_cut_by_width() {
+        let mut config = Config::default();
+        config.empty_row_marker = "\u{4e2d}".to_owned();
+        let mut editor = editor_with("", config);
+        editor.terminal = Terminal::mock(4, 24);
+        assert_eq!(editor.welcome_line("Hello"), "\u{4e2d}He");
+    }
+
+    #[test]
+    fn typing_on_the_line_past_the_end_then_enter_starts_a_fresh_line() {
+        let mut editor = editor("one");
+        assert_eq!(editor.document.len(), 1);
+        press(&mut editor, "\x1b[Bab\r");
+        assert_eq!(lines(&editor), ["one", "ab", ""]);
+        assert!(editor.cursor_position == Position { x: 0, y: 2 });
+        press(&mut editor, "c");
+        assert_eq!(lines(&editor), ["one", "ab", "c"]);
+    }
+
+    #[test]
+    fn enter_on_the_line_past_the_end_adds_an_empty_line() {
+        let mut editor = editor("one");
+        press(&mut editor, "\x1b[B\r");
+        assert_eq!(lines(&editor), ["one", ""]);
+        assert!(editor.cursor_position == Position { x: 0, y: 2 });
+        press(&mut editor, "x");
+        assert_eq!(lines(&editor), ["one", "", "x"]);
+    }
+
+    #[test]
+    fn typing_into_an_empty_document_then_enter() {
+        let mut editor = editor("");
+        press(&mut editor, "a\rb");
+        assert_eq!(lines(&editor), ["a", "b"]);
+        assert!(editor.cursor_position == Position { x: 1, y: 1 });
+    }
+
+    #[test]
+    fn kills_to_the_end_of_the_line_from_the_middle() {
+        let mut editor = editor("hello world\nnext");
+        editor.cursor_position = Position { x: 5, y: 0 };
+        press(&mut editor, "\x0b");
+        assert_eq!(lines(&editor), ["hello", "next"]);
+        assert_eq!(editor.clipboard, " world");
+        assert!(editor.cursor_position == Position { x: 5, y: 0 });
+    }
+
+    #[test]
+    fn killing_at_the_end_of_the_line_joins_the_next() {
+        let mut editor = editor("hello\nworld");
+        editor.cursor_position = Position { x: 5, y: 0 };
+        press(&mut editor, "\x0b");
+        assert_eq!(lines(&editor), ["helloworld"]);
+        assert_eq!(editor.clipboard, "\n");
+    }
+
+    #[test]
+    fn consecutive_kills_add_to_the_clipboard() {
+        let mut editor = editor("one\ntwo\nthree");
+        press(&mut editor, "\x0b\x0b\x0b");
+        assert_eq!(lines(&editor), ["", "three"]);
+        assert_eq!(editor.clipboard, "one\ntwo");
+        // A key in between starts the clipboard anew.
+        press(&mut editor, "\x1b[B\x0b");
+        assert_eq!(editor.clipboard, "three");
+    }
+
+    #[test]
+    fn consecutive_backward_kills_add_before_the_clipboard() {
+        let mut editor = editor("foo bar");
+        editor.cursor_position = Position { x: 4, y: 0 };
+        press(&mut editor, "\x0b\x15");
+        assert_eq!(lines(&editor), [""]);
+        assert_eq!(editor.clipboard, "foo bar");
+    }
+
+    #[test]
+    fn killing_is_undone_as_one_edit() {
+        let mut editor = editor("hello world");
+        editor.cursor_position = Position { x: 5, y: 0 };
+        press(&mut editor, "\x0b\x1a");
+        assert_eq!(lines(&editor), ["hello world"]);
+    }
 }
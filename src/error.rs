@@ -0,0 +1,108 @@
+use core::fmt;
+use std::error;
+use std::io;
+
+/// The failures of editor operations, so that a cancelled prompt can be told from a disk error.
+#[derive(Debug)]
+pub enum EditorError {
+    /// Reading the input, or reading or writing a file, failed.
+    Io(io::Error),
+    /// The user cancelled the prompt, e.g., by pressing Esc or entering nothing.
+    Cancelled,
+    /// The input can't be accepted, e.g., an unknown encoding or text that the encoding can't hold.
+    InvalidInput(String),
+    /// The file can't be written for lack of permission.
+    ReadOnly,
+    /// An external command exited unsuccessfully, with the first line of its error output.
+    CommandFailed(String),
+}
+
+impl fmt::Display for EditorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{err}"),
+            Self::Cancelled => write!(f, "cancelled"),
+            Self::InvalidInput(reason) | Self::CommandFailed(reason) => write!(f, "{reason}"),
+            Self::ReadOnly => write!(f, "permission denied"),
+        }
+    }
+}
+
+#[allow(clippy::missing_trait_methods)] // The rest are deprecated or unstable.
+impl error::Error for EditorError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for EditorError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter;
+    use crate::Document;
+    use crate::Position;
+    use crate::TempPath;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+
+    #[test]
+    fn missing_file_is_an_io_error() {
+        let opened = Document::open("/nonexistent/hecto/file.txt");
+        assert!(
+            matches!(opened, Err(EditorError::Io(err)) if err.kind() == io::ErrorKind::NotFound)
+        );
+    }
+
+    #[test]
+    fn failing_command_is_command_failed_with_its_error_output() {
+        let filtered = filter("echo oops >&2; exit 1", "");
+        assert!(matches!(filtered, Err(EditorError::CommandFailed(reason)) if reason == "oops"));
+    }
+
+    #[test]
+    fn non_utf8_output_is_invalid_input() {
+        let filtered = filter("printf '\\377'", "");
+        assert!(matches!(filtered, Err(EditorError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn saving_without_permission_is_read_only() {
+        let dir = TempPath::new("read-only");
+        let path = Path::new(&*dir).join("file.txt");
+        let created = fs::create_dir_all(&dir)
+            .and_then(|()| fs::write(&path, "foo\n"))
+            .and_then(|()| fs::set_permissions(&dir, fs::Permissions::from_mode(0o555)));
+        if let Err(err) = created {
+            panic!("failed to create {}: {err}", path.display());
+        }
+        // The superuser can write anyway, which leaves nothing to test.
+        let is_writable = fs::write(Path::new(&*dir).join("probe"), "").is_ok();
+        let Ok(mut doc) = Document::open(&path.to_string_lossy()) else {
+            panic!("failed to open {}", path.display());
+        };
+        doc.insert(&Position::default(), 'x');
+        let saved = doc.save();
+        drop(fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)));
+        if !is_writable {
+            assert!(matches!(saved, Err(EditorError::ReadOnly)));
+        }
+    }
+
+    #[test]
+    fn displays_the_reason() {
+        assert_eq!(EditorError::Cancelled.to_string(), "cancelled");
+        assert_eq!(EditorError::ReadOnly.to_string(), "permission denied");
+        let invalid = EditorError::InvalidInput("unknown encoding: foo".to_owned());
+        assert_eq!(invalid.to_string(), "unknown encoding: foo");
+    }
+}
@@ -1,9 +1,19 @@
 use crate::HighlightingOptions;
 use std::path::Path;
 
+/// How a level of indentation is written.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum IndentStyle {
+    Tabs,
+    /// The number of spaces per level.
+    Spaces(usize),
+}
+
 pub struct FileType {
     name: String,
     hl_opts: HighlightingOptions,
+    /// The conventional indentation of the language, if there's one.
+    indent: Option<IndentStyle>,
 }
 
 impl Default for FileType {
@@ -11,6 +21,7 @@ impl Default for FileType {
         Self {
             name: String::from("No filetype"),
             hl_opts: HighlightingOptions::default(),
+            indent: None,
         }
     }
 }
@@ -21,6 +32,18 @@ impl FileType {
         self.name.clone()
     }
 
+    /// The token that starts a line comment, if the file type has line comments.
+    #[must_use]
+    pub fn comment_prefix(&self) -> Option<&'static str> {
+        // NOTE: `//` is the only kind of line comment being highlighted.
+        self.hl_opts.comments.then_some("//")
+    }
+
+    #[must_use]
+    pub fn indent(&self) -> Option<IndentStyle> {
+        self.indent
+    }
+
     #[must_use]
     pub fn highlight_options(&self) -> &HighlightingOptions {
         &self.hl_opts
@@ -31,103 +54,151 @@ impl FileType {
         let filename = Path::new(filename);
         if filename
             .extension()
-            .map_or(false, |ext| ext.eq_ignore_ascii_case("rs"))
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("rs"))
         {
-            return Self {
-                name: String::from("Rust"),
-                hl_opts: HighlightingOptions {
-                    numbers: true,
-                    strings: true,
-                    characters: true,
-                    comments: true,
-                    multiline_comments: true,
-                    // The currently in used keywords in Rust:
-                    // https://doc.rust-lang.org/book/appendix-01-keywords.html#keywords-currently-in-use
-                    keywords: vec![
-                        "as".to_owned(),
-                        "async".to_owned(),
-                        "await".to_owned(),
-                        "break".to_owned(),
-                        "const".to_owned(),
-                        "continue".to_owned(),
-                        "crate".to_owned(),
-                        "dyn".to_owned(),
-                        "else".to_owned(),
-                        "enum".to_owned(),
-                        "extern".to_owned(),
-                        "false".to_owned(),
-                        "fn".to_owned(),
-                        "for".to_owned(),
-                        "if".to_owned(),
-                        "impl".to_owned(),
-                        "in".to_owned(),
-                        "let".to_owned(),
-                        "loop".to_owned(),
-                        "match".to_owned(),
-                        "mod".to_owned(),
-                        "move".to_owned(),
-                        "mut".to_owned(),
-                        "pub".to_owned(),
-                        "ref".to_owned(),
-                        "return".to_owned(),
-                        "Self".to_owned(),
-                        "self".to_owned(),
-                        "static".to_owned(),
-                        "struct".to_owned(),
-                        "super".to_owned(),
-                        "trait".to_owned(),
-                        "true".to_owned(),
-                        "type".to_owned(),
-                        "union".to_owned(),
-                        "unsafe".to_owned(),
-                        "use".to_owned(),
-                        "where".to_owned(),
-                        "while".to_owned(),
-                    ],
-                    // The data types in Rust.
-                    data_types: vec![
-                        "i8".to_owned(),
-                        "i16".to_owned(),
-                        "i32".to_owned(),
-                        "i64".to_owned(),
-                        "i128".to_owned(),
-                        "u8".to_owned(),
-                        "u16".to_owned(),
-                        "u32".to_owned(),
-                        "u64".to_owned(),
-                        "u128".to_owned(),
-                        "f32".to_owned(),
-                        "f64".to_owned(),
-                        "isize".to_owned(),
-                        "usize".to_owned(),
-                        "bool".to_owned(),
-                        "char".to_owned(),
-                        "str".to_owned(),
-                        "String".to_owned(),
-                        "Box".to_owned(),
-                        "Rc".to_owned(),
-                        "Arc".to_owned(),
-                        "Vec".to_owned(),
-                        "HashMap".to_owned(),
-                        "BTreeMap".to_owned(),
-                        "HashSet".to_owned(),
-                        "BTreeSet".to_owned(),
-                        "Option".to_owned(),
-                        "Result".to_owned(),
-                        "Some".to_owned(),
-                        "None".to_owned(),
-                        "Ok".to_owned(),
-                        "Err".to_owned(),
-                        "true".to_owned(),
-                        "false".to_owned(),
-                    ],
-                    punctuations: vec![
-                        ':', ';', ',', '.', '#', '!', '&', '|', '+', '-', '*', '/', '=', '?', '<',
-                        '>',
-                    ],
-                },
-            };
+            return Self::rust();
         }
-        Self::default()
+        Self::indented_from(filename)
+            .or_else(|| Self::prose_from(filename))
+            .unwrap_or_default()
+    }
+
+    fn rust() -> Self {
+        Self {
+            name: String::from("Rust"),
+            hl_opts: HighlightingOptions {
+                numbers: true,
+                strings: true,
+                characters: true,
+                comments: true,
+                multiline_comments: true,
+                // The currently in used keywords in Rust:
+                // https://doc.rust-lang.org/book/appendix-01-keywords.html#keywords-currently-in-use
+                keywords: vec![
+                    "as".to_owned(),
+                    "async".to_owned(),
+                    "await".to_owned(),
+                    "break".to_owned(),
+                    "const".to_owned(),
+                    "continue".to_owned(),
+                    "crate".to_owned(),
+                    "dyn".to_owned(),
+                    "else".to_owned(),
+                    "enum".to_owned(),
+                    "extern".to_owned(),
+                    "false".to_owned(),
+                    "fn".to_owned(),
+                    "for".to_owned(),
+                    "if".to_owned(),
+                    "impl".to_owned(),
+                    "in".to_owned(),
+                    "let".to_owned(),
+                    "loop".to_owned(),
+                    "match".to_owned(),
+                    "mod".to_owned(),
+                    "move".to_owned(),
+                    "mut".to_owned(),
+                    "pub".to_owned(),
+                    "ref".to_owned(),
+                    "return".to_owned(),
+                    "Self".to_owned(),
+                    "self".to_owned(),
+                    "static".to_owned(),
+                    "struct".to_owned(),
+                    "super".to_owned(),
+                    "trait".to_owned(),
+                    "true".to_owned(),
+                    "type".to_owned(),
+                    "union".to_owned(),
+                    "unsafe".to_owned(),
+                    "use".to_owned(),
+                    "where".to_owned(),
+                    "while".to_owned(),
+                ],
+                // The data types in Rust.
+                data_types: vec![
+                    "i8".to_owned(),
+                    "i16".to_owned(),
+                    "i32".to_owned(),
+                    "i64".to_owned(),
+                    "i128".to_owned(),
+                    "u8".to_owned(),
+                    "u16".to_owned(),
+                    "u32".to_owned(),
+                    "u64".to_owned(),
+                    "u128".to_owned(),
+                    "f32".to_owned(),
+                    "f64".to_owned(),
+                    "isize".to_owned(),
+                    "usize".to_owned(),
+                    "bool".to_owned(),
+                    "char".to_owned(),
+                    "str".to_owned(),
+                    "String".to_owned(),
+                    "Box".to_owned(),
+                    "Rc".to_owned(),
+                    "Arc".to_owned(),
+                    "Vec".to_owned(),
+                    "HashMap".to_owned(),
+                    "BTreeMap".to_owned(),
+                    "HashSet".to_owned(),
+                    "BTreeSet".to_owned(),
+                    "Option".to_owned(),
+                    "Result".to_owned(),
+                    "Some".to_owned(),
+                    "None".to_owned(),
+                    "Ok".to_owned(),
+                    "Err".to_owned(),
+                    "true".to_owned(),
+                    "false".to_owned(),
+                ],
+                punctuations: vec![
+                    ':', ';', ',', '.', '#', '!', '&', '|', '+', '-', '*', '/', '=', '?', '<', '>',
+                ],
+            },
+            indent: Some(IndentStyle::Spaces(4)),
+        }
+    }
+
+    /// The file types known only for their indentation, which aren't highlighted.
+    fn indented_from(filename: &Path) -> Option<Self> {
+        let extension = filename.extension()?.to_ascii_lowercase();
+        let (name, indent) = match extension.to_str()? {
+            "go" => ("Go", IndentStyle::Tabs),
+            "py" => ("Python", IndentStyle::Spaces(4)),
+            _ => return None,
+        };
+        Some(Self {
+            name: String::from(name),
+            hl_opts: HighlightingOptions::default(),
+            indent: Some(indent),
+        })
+    }
+
+    /// The file type of plain text or Markdown files.
+    fn prose_from(filename: &Path) -> Option<Self> {
+        let is_extension = |extension: &str| {
+            filename
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case(extension))
+        };
+        let name = if is_extension("txt") {
+            "Plain Text"
+        } else if is_extension("md") || is_extension("markdown") {
+            "Markdown"
+        } else {
+            return None;
+        };
+        Some(Self {
+            name: String::from(name),
+            hl_opts: HighlightingOptions::default(),
+            indent: None,
+        })
+    }
+
+    /// Whether the file type is prose rather than code, e.g., for spell checking.
+    #[must_use]
+    pub fn is_prose(&self) -> bool {
+        matches!(self.name.as_str(), "Plain Text" | "Markdown")
     }
 }
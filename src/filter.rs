@@ -0,0 +1,163 @@
+use crate::EditorError;
+use std::io::{self, Read, Write};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+/// The most output taken from a command in MiB, past which it's killed rather than filling the
+/// memory, e.g., with `yes`.
+const MAX_OUTPUT_MIB: u64 = 64;
+const MAX_OUTPUT_LEN: u64 = MAX_OUTPUT_MIB * 1024 * 1024;
+/// The most error output kept, of which only the first line is shown anyway.
+const MAX_ERROR_LEN: u64 = 64 * 1024;
+/// How often whether to cancel the command is checked while it runs.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Runs the shell command with the input on its standard input, returning its standard output.
+/// # Errors
+/// As `filter_cancellable`, but never `Cancelled`.
+pub fn filter(command: &str, input: &str) -> Result<String, EditorError> {
+    filter_cancellable(command, input, || false)
+}
+
+/// Runs the shell command as `filter`, polling `is_cancelled` while it runs, which kills the
+/// command when true.
+/// # Errors
+/// Returns an error if the command can't be run, exits unsuccessfully, in which case the error
+/// holds its standard error, outputs more than `MAX_OUTPUT_LEN` bytes or anything but UTF-8, or
+/// `Cancelled` if cancelled.
+pub fn filter_cancellable<F: FnMut() -> bool>(
+    command: &str,
+    input: &str,
+    mut is_cancelled: F,
+) -> Result<String, EditorError> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // Written from another thread, so that a command that outputs before reading all of its
+    // input doesn't block on a full pipe while we block on writing.
+    if let Some(mut stdin) = child.stdin.take() {
+        let input = input.to_owned();
+        // A command that exits without reading its input closes the pipe, which is fine.
+        thread::spawn(move || drop(stdin.write_all(input.as_bytes())));
+    }
+    // Both outputs are read from other threads, so that neither fills its pipe and blocks the
+    // command, and so that the command can be given up on while they're being read.
+    let stdout = child
+        .stdout
+        .take()
+        .map(|out| read_in_background(out, MAX_OUTPUT_LEN));
+    let stderr = child
+        .stderr
+        .take()
+        .map(|err| read_in_background(err, MAX_ERROR_LEN));
+    let mut output = Vec::new();
+    if let Some(stdout) = stdout {
+        loop {
+            match stdout.recv_timeout(POLL_INTERVAL) {
+                Ok(read) => {
+                    output = read?;
+                    break;
+                }
+                Err(RecvTimeoutError::Timeout) if is_cancelled() => {
+                    kill(&mut child);
+                    return Err(EditorError::Cancelled);
+                }
+                Err(RecvTimeoutError::Timeout) => (),
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+    }
+    if output.len() as u64 > MAX_OUTPUT_LEN {
+        kill(&mut child);
+        return Err(EditorError::InvalidInput(format!(
+            "the output is over {MAX_OUTPUT_MIB} MiB"
+        )));
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        let stderr = stderr
+            .and_then(|stderr| stderr.recv().ok())
+            .and_then(Result::ok)
+            .unwrap_or_default();
+        let stderr = String::from_utf8_lossy(&stderr);
+        let reason = stderr.lines().find(|line| !line.trim().is_empty());
+        return Err(EditorError::CommandFailed(
+            reason.map_or_else(|| status.to_string(), str::to_owned),
+        ));
+    }
+    String::from_utf8(output)
+        .map_err(|err| EditorError::InvalidInput(format!("the output isn't valid UTF-8: {err}")))
+}
+
+/// Reads from another thread until the end or one byte past `limit`, whichever comes first, and
+/// sends what's read once done.
+fn read_in_background<R: Read + Send + 'static>(
+    reader: R,
+    limit: u64,
+) -> mpsc::Receiver<io::Result<Vec<u8>>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut bytes = Vec::new();
+        let read = reader
+            .take(limit.saturating_add(1))
+            .read_to_end(&mut bytes)
+            .map(|_| bytes);
+        // The command may have been given up on, with no one to receive it.
+        drop(sender.send(read));
+    });
+    receiver
+}
+
+/// Kills the command and reaps it. It may have exited already, which is fine.
+fn kill(child: &mut Child) {
+    drop(child.kill());
+    drop(child.wait());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn echoes_the_input_through_cat() {
+        let filtered = filter("cat", "foo\nbar\n");
+        assert_eq!(filtered.ok().as_deref(), Some("foo\nbar\n"));
+    }
+
+    #[test]
+    fn outputs_more_than_the_pipe_holds_before_reading_all_the_input() {
+        let input = "x".repeat(1024 * 1024);
+        let filtered = filter("cat", &input);
+        assert_eq!(filtered.ok().map(|output| output.len()), Some(input.len()));
+    }
+
+    #[test]
+    fn output_past_the_cap_is_refused() {
+        let filtered = filter("yes", "");
+        assert!(matches!(filtered, Err(EditorError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn cancelling_kills_the_command() {
+        let mut polls = 0usize;
+        let filtered = filter_cancellable("sleep 10", "", || {
+            polls = polls.saturating_add(1);
+            polls > 1
+        });
+        assert!(matches!(filtered, Err(EditorError::Cancelled)));
+    }
+
+    #[test]
+    fn failing_without_error_output_reports_the_exit_status() {
+        let filtered = filter("exit 3", "");
+        assert!(
+            matches!(filtered, Err(EditorError::CommandFailed(reason)) if reason.contains('3'))
+        );
+    }
+}
@@ -0,0 +1,137 @@
+use core::cmp;
+use core::ops::RangeInclusive;
+
+/// The ranges of rows collapsed into a single line each, which shows a marker in place of the
+/// rows. The first row of a fold stands for the whole fold; the others are hidden.
+#[derive(Default)]
+pub struct Folds {
+    /// Sorted and not overlapping.
+    ranges: Vec<RangeInclusive<usize>>,
+    /// The number of rows of the document when the folds were made. The folds are forgotten once
+    /// it changes, since the rows they cover may have moved.
+    row_count: usize,
+}
+
+impl Folds {
+    /// Folds the rows, merging with the folds they overlap.
+    pub fn fold(&mut self, rows: RangeInclusive<usize>, row_count: usize) {
+        self.forget_if_changed(row_count);
+        self.row_count = row_count;
+        let (mut start, mut end) = rows.into_inner();
+        self.ranges.retain(|range| {
+            let overlaps = *range.start() <= end && start <= *range.end();
+            if overlaps {
+                start = cmp::min(start, *range.start());
+                end = cmp::max(end, *range.end());
+            }
+            !overlaps
+        });
+        let index = self.ranges.partition_point(|range| *range.start() < start);
+        self.ranges.insert(index, start..=end);
+    }
+
+    /// Unfolds the fold containing the row, returning whether there's one.
+    pub fn unfold_at(&mut self, y: usize) -> bool {
+        let len = self.ranges.len();
+        self.ranges.retain(|range| !range.contains(&y));
+        self.ranges.len() != len
+    }
+
+    pub fn clear(&mut self) {
+        self.ranges.clear();
+    }
+
+    /// Forgets the folds if the number of rows of the document has changed since they were made.
+    pub fn forget_if_changed(&mut self, row_count: usize) {
+        if row_count != self.row_count {
+            self.clear();
+        }
+    }
+
+    /// The fold containing the row, if any.
+    #[must_use]
+    pub fn fold_at(&self, y: usize) -> Option<&RangeInclusive<usize>> {
+        self.ranges.iter().find(|range| range.contains(&y))
+    }
+
+    /// Whether the row is folded into the line of the first row of its fold.
+    #[must_use]
+    pub fn is_hidden(&self, y: usize) -> bool {
+        self.fold_at(y).is_some_and(|range| *range.start() != y)
+    }
+
+    /// The line on the screen, counted from the top of the document, at which the row is shown;
+    /// a hidden row is on the line of its fold.
+    #[must_use]
+    pub fn visual_line(&self, y: usize) -> usize {
+        let mut hidden_before = 0usize;
+        for range in &self.ranges {
+            if *range.start() >= y {
+                break;
+            }
+            if range.contains(&y) {
+                return self.visual_line(*range.start());
+            }
+            hidden_before =
+                hidden_before.saturating_add(range.end().saturating_sub(*range.start()));
+        }
+        y.saturating_sub(hidden_before)
+    }
+
+    /// The row shown at the line on the screen, counted from the top of the document; the inverse
+    /// of `visual_line` for the rows that aren't hidden.
+    #[must_use]
+    pub fn row_at_visual_line(&self, line: usize) -> usize {
+        let mut y = line;
+        for range in &self.ranges {
+            if *range.start() >= y {
+                break;
+            }
+            y = y.saturating_add(range.end().saturating_sub(*range.start()));
+        }
+        y
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_rows_to_visual_lines_around_a_fold() {
+        let mut folds = Folds::default();
+        folds.fold(2..=5, 10);
+        let lines: Vec<usize> = (0..10usize).map(|y| folds.visual_line(y)).collect();
+        assert_eq!(lines, [0, 1, 2, 2, 2, 2, 3, 4, 5, 6]);
+        let rows: Vec<usize> = (0..7usize)
+            .map(|line| folds.row_at_visual_line(line))
+            .collect();
+        assert_eq!(rows, [0, 1, 2, 6, 7, 8, 9]);
+        assert!(!folds.is_hidden(2));
+        assert!(folds.is_hidden(5));
+        assert!(!folds.is_hidden(6));
+    }
+
+    #[test]
+    fn merges_overlapping_folds() {
+        let mut folds = Folds::default();
+        folds.fold(6..=8, 10);
+        folds.fold(1..=2, 10);
+        folds.fold(2..=6, 10);
+        assert_eq!(folds.fold_at(4), Some(&(1..=8)));
+        assert_eq!(folds.visual_line(9), 2);
+        assert!(folds.unfold_at(3));
+        assert_eq!(folds.visual_line(9), 9);
+        assert!(!folds.unfold_at(3));
+    }
+
+    #[test]
+    fn forgets_the_folds_once_the_rows_change() {
+        let mut folds = Folds::default();
+        folds.fold(0..=3, 10);
+        folds.forget_if_changed(10);
+        assert!(folds.fold_at(1).is_some());
+        folds.forget_if_changed(11);
+        assert!(folds.fold_at(1).is_none());
+    }
+}
@@ -27,6 +27,9 @@ pub enum Type {
     Keyword,
     DataType,
     Punctuation,
+    /// A word not in the spelling dictionary, underlined rather than colored.
+    #[cfg(feature = "spell")]
+    Misspelled,
 }
 
 impl Type {
@@ -41,6 +44,8 @@ impl Type {
             Type::DataType => &color::LightMagenta,
             Type::Punctuation => &color::Cyan,
             Type::None => &color::Reset,
+            #[cfg(feature = "spell")]
+            Type::Misspelled => &color::Reset,
         }
     }
 }
@@ -0,0 +1,51 @@
+use crate::Edit;
+
+/// The edits to undo and redo, grouped into steps that are undone as a whole, e.g., a word typed
+/// or a paste. Each step holds the edits undoing it, in the order they were recorded.
+#[derive(Default)]
+pub struct History {
+    undo_steps: Vec<Vec<Edit>>,
+    redo_steps: Vec<Vec<Edit>>,
+    /// Whether the recorded edits go into the last undo step rather than a new one.
+    is_step_open: bool,
+}
+
+impl History {
+    /// Records the edit undoing a new edit, which makes the undone steps no longer redoable.
+    pub fn record(&mut self, inverse: Edit) {
+        self.redo_steps.clear();
+        match self.undo_steps.last_mut() {
+            Some(step) if self.is_step_open => step.push(inverse),
+            _ => self.undo_steps.push(vec![inverse]),
+        }
+        self.is_step_open = true;
+    }
+
+    /// Makes the next recorded edit start a new undo step.
+    pub fn end_step(&mut self) {
+        self.is_step_open = false;
+    }
+
+    pub fn pop_undo(&mut self) -> Option<Vec<Edit>> {
+        self.end_step();
+        self.undo_steps.pop()
+    }
+
+    pub fn push_undo(&mut self, step: Vec<Edit>) {
+        self.undo_steps.push(step);
+    }
+
+    pub fn pop_redo(&mut self) -> Option<Vec<Edit>> {
+        self.end_step();
+        self.redo_steps.pop()
+    }
+
+    pub fn push_redo(&mut self, step: Vec<Edit>) {
+        self.redo_steps.push(step);
+    }
+
+    /// Forgets all the steps, e.g., after a change that the recorded edits no longer fit.
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
@@ -0,0 +1,99 @@
+use crate::Position;
+
+/// The number of positions kept; the oldest ones are dropped beyond it.
+const CAPACITY: usize = 100;
+
+/// The positions the cursor jumped from, e.g., by going to a line or searching, to go back and
+/// forth through.
+#[derive(Default)]
+pub struct JumpList {
+    positions: Vec<Position>,
+    /// The position gone back to; the length of the list if not going back.
+    index: usize,
+}
+
+impl JumpList {
+    /// Records a jump from the position, which drops the positions gone back from.
+    pub fn push(&mut self, from: Position) {
+        self.positions.truncate(self.index);
+        if self.positions.last() != Some(&from) {
+            self.positions.push(from);
+        }
+        if self.positions.len() > CAPACITY {
+            self.positions.remove(0);
+        }
+        self.index = self.positions.len();
+    }
+
+    /// The position jumped from before the current one, if any. Going back from the latest jump
+    /// keeps the current position to go forward to.
+    pub fn back(&mut self, current: Position) -> Option<Position> {
+        let index = self.index.checked_sub(1)?;
+        if self.index == self.positions.len() {
+            self.positions.push(current);
+        }
+        self.index = index;
+        self.positions.get(index).cloned()
+    }
+
+    /// The position gone back from, if any.
+    pub fn forward(&mut self) -> Option<Position> {
+        let index = self.index.saturating_add(1);
+        let position = self.positions.get(index).cloned()?;
+        self.index = index;
+        Some(position)
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(y: usize) -> Position {
+        Position { x: 0, y }
+    }
+
+    #[test]
+    fn goes_back_and_forth_through_the_jumps() {
+        let mut jumps = JumpList::default();
+        jumps.push(at(1));
+        jumps.push(at(2));
+        assert!(jumps.back(at(3)) == Some(at(2)));
+        assert!(jumps.back(at(2)) == Some(at(1)));
+        assert!(jumps.back(at(1)).is_none());
+        assert!(jumps.forward() == Some(at(2)));
+        assert!(jumps.forward() == Some(at(3)));
+        assert!(jumps.forward().is_none());
+    }
+
+    #[test]
+    fn jumping_drops_the_positions_gone_back_from() {
+        let mut jumps = JumpList::default();
+        jumps.push(at(1));
+        jumps.push(at(2));
+        assert!(jumps.back(at(3)) == Some(at(2)));
+        jumps.push(at(2));
+        assert!(jumps.forward().is_none());
+        assert!(jumps.back(at(5)) == Some(at(2)));
+        assert!(jumps.back(at(2)) == Some(at(1)));
+    }
+
+    #[test]
+    fn keeps_the_latest_jumps() {
+        let mut jumps = JumpList::default();
+        for y in 0..CAPACITY.saturating_add(10) {
+            jumps.push(at(y));
+        }
+        let mut oldest = None;
+        let mut current = at(usize::MAX);
+        while let Some(position) = jumps.back(current) {
+            current = position.clone();
+            oldest = Some(position);
+        }
+        assert!(oldest == Some(at(10)));
+    }
+}
@@ -0,0 +1,86 @@
+use core::fmt;
+use core::str::FromStr;
+use termion::event::Key;
+
+/// A key that triggers a command, written like `ctrl-s`, `alt-x`, `f2`, or a single character in
+/// the config.
+#[derive(Clone, Copy, PartialEq)]
+pub struct KeyBinding(pub Key);
+
+impl FromStr for KeyBinding {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // The prefixes are case-insensitive, while the characters of Alt and plain keys aren't.
+        let has_prefix = |prefix: &str| {
+            s.get(..prefix.len())
+                .is_some_and(|head| head.eq_ignore_ascii_case(prefix))
+        };
+        let rest = |prefix: &str| s.get(prefix.len()..).unwrap_or_default();
+        let single_char = |s: &str| {
+            let mut chars = s.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Ok(c),
+                _ => Err(()),
+            }
+        };
+        if has_prefix("ctrl-") {
+            // Terminals don't tell Ctrl-S from Ctrl-Shift-S.
+            return single_char(rest("ctrl-")).map(|c| Self(Key::Ctrl(c.to_ascii_lowercase())));
+        }
+        if has_prefix("alt-") {
+            return single_char(rest("alt-")).map(|c| Self(Key::Alt(c)));
+        }
+        if has_prefix("f") && s.len() > 1 {
+            return rest("f").parse().ok().map(|n| Self(Key::F(n))).ok_or(());
+        }
+        single_char(s).map(|c| Self(Key::Char(c)))
+    }
+}
+
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Key::Ctrl(c) => write!(f, "Ctrl-{}", c.to_ascii_uppercase()),
+            Key::Alt(c) => write!(f, "Alt-{c}"),
+            Key::F(n) => write!(f, "F{n}"),
+            Key::Char(c) => write!(f, "{c}"),
+            _ => write!(f, "?"),
+        }
+    }
+}
+
+/// The keys of the commands that can be rebound in the config.
+pub struct Keymap {
+    pub save: KeyBinding,
+    pub quit: KeyBinding,
+    pub find: KeyBinding,
+    pub command_palette: KeyBinding,
+    pub undo: KeyBinding,
+    pub redo: KeyBinding,
+    /// Focuses the other pane of a split.
+    pub other_pane: KeyBinding,
+    /// Closes the focused pane of a split.
+    pub close_pane: KeyBinding,
+    /// Deletes from the cursor to the end of the line, or joins the next line at the end.
+    pub kill_line: KeyBinding,
+    /// Deletes from the start of the line to the cursor.
+    pub kill_line_backward: KeyBinding,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self {
+            save: KeyBinding(Key::Ctrl('s')),
+            quit: KeyBinding(Key::Ctrl('q')),
+            find: KeyBinding(Key::Ctrl('f')),
+            command_palette: KeyBinding(Key::Ctrl('p')),
+            undo: KeyBinding(Key::Ctrl('z')),
+            redo: KeyBinding(Key::Ctrl('y')),
+            other_pane: KeyBinding(Key::Ctrl('w')),
+            close_pane: KeyBinding(Key::Alt('w')),
+            kill_line: KeyBinding(Key::Ctrl('k')),
+            kill_line_backward: KeyBinding(Key::Ctrl('u')),
+        }
+    }
+}
@@ -14,30 +14,84 @@
     clippy::question_mark_used,
     clippy::pub_use,
     clippy::std_instead_of_core,
+    clippy::std_instead_of_alloc,
     clippy::as_conversions,
     clippy::partial_pub_fields,
     clippy::exhaustive_structs,
     clippy::exhaustive_enums,
     clippy::pattern_type_mismatch,
     clippy::panic,
-    clippy::unseparated_literal_suffix
+    clippy::unseparated_literal_suffix,
+    clippy::arbitrary_source_item_ordering,
+    clippy::missing_inline_in_public_items,
+    clippy::allow_attributes,
+    clippy::allow_attributes_without_reason,
+    clippy::unused_trait_names,
+    clippy::integer_division_remainder_used,
+    clippy::single_call_fn
 )]
+mod args;
+mod completion;
+mod config;
+mod diff;
 mod document;
+mod edit;
 mod editor;
+mod error;
 mod filetype;
+mod filter;
+mod fold;
 mod highlight;
+mod history;
+mod jumps;
+mod keymap;
+mod quickfix;
 mod row;
+mod search;
+#[cfg(feature = "spell")]
+mod spell;
+mod tail;
+#[cfg(test)]
+mod temp;
 mod terminal;
-pub use document::Document;
+pub use args::Args;
+pub use completion::PathCompleter;
+pub use config::Config;
+pub use diff::{diff, DiffSummary};
+pub use document::{normalize_path, Case, Document, LineEnding, SaveOptions};
+pub use edit::{Edit, EditOutcome};
 pub use editor::Position;
-pub use filetype::FileType;
+pub use error::EditorError;
+pub use filetype::{FileType, IndentStyle};
+pub use filter::{filter, filter_cancellable};
+pub use fold::Folds;
 pub use highlight::HighlightingOptions;
+pub use history::History;
+pub use jumps::JumpList;
+pub use keymap::{KeyBinding, Keymap};
+pub use quickfix::{parse_error_line, ErrorList, ErrorLocation};
 pub use row::Row;
-pub use terminal::Terminal;
+pub use search::SearchOptions;
+#[cfg(feature = "spell")]
+pub use spell::Dictionary;
+pub use tail::Tail;
+#[cfg(test)]
+pub use temp::TempPath;
+pub use terminal::{CursorShape, Input, Terminal};
 
 use editor::Editor;
+use std::env;
 
 fn main() {
-    let mut editor = Editor::default();
+    let args = Args::parse(env::args().skip(1));
+    if args.show_help {
+        print!("{}", args::USAGE);
+        return;
+    }
+    if args.show_version {
+        println!("hecto {}", env!("CARGO_PKG_VERSION"));
+        return;
+    }
+    let mut editor = Editor::new(args);
     editor.run();
 }
@@ -0,0 +1,179 @@
+/// A location reported by a compiler or a linter, e.g., `src/main.rs:3:5: error: ...`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ErrorLocation {
+    pub filename: String,
+    /// 1-based, as reported.
+    pub line: usize,
+    /// 1-based, as reported; the first column if none is.
+    pub column: usize,
+    pub message: String,
+}
+
+/// Parses a line of the form `file:line:col: message`, as gcc and `rustc --error-format=short`
+/// report, or `file:line: message`; `None` if the line isn't one.
+#[must_use]
+pub fn parse_error_line(line: &str) -> Option<ErrorLocation> {
+    let mut parts = line.trim().splitn(4, ':');
+    let filename = parts.next()?.trim();
+    if filename.is_empty() {
+        return None;
+    }
+    let line = parts.next()?.trim().parse::<usize>().ok()?;
+    let rest: Vec<&str> = parts.collect();
+    let (column, message) = match rest.split_first() {
+        Some((column, message)) if column.trim().parse::<usize>().is_ok() => {
+            (column.trim().parse::<usize>().ok()?, message.join(":"))
+        }
+        _ => (1, rest.join(":")),
+    };
+    Some(ErrorLocation {
+        filename: filename.to_owned(),
+        line,
+        column,
+        message: message.trim().to_owned(),
+    })
+}
+
+/// The locations of errors to go through in turn, e.g., loaded from the output of a build.
+#[derive(Default)]
+pub struct ErrorList {
+    locations: Vec<ErrorLocation>,
+    /// The location gone to last, if any.
+    index: Option<usize>,
+}
+
+impl ErrorList {
+    /// Collects the locations in the text, skipping the lines that aren't any. The location lines
+    /// of the default format of rustc, e.g., ` --> src/main.rs:3:5`, take the message of the line
+    /// heading them, e.g., `error[E0425]: cannot find value`.
+    #[must_use]
+    pub fn parse(text: &str) -> Self {
+        let mut locations = Vec::new();
+        let mut heading = "";
+        for line in text.lines() {
+            if let Some(location) = line.trim_start().strip_prefix("--> ") {
+                if let Some(mut location) = parse_error_line(location) {
+                    heading.clone_into(&mut location.message);
+                    locations.push(location);
+                }
+            } else if let Some(location) = parse_error_line(line) {
+                locations.push(location);
+            } else if line.starts_with("error") || line.starts_with("warning") {
+                heading = line.trim();
+            }
+        }
+        Self {
+            locations,
+            index: None,
+        }
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.locations.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.locations.is_empty()
+    }
+
+    /// The 1-based number of the location gone to last, if any.
+    #[must_use]
+    pub fn current(&self) -> Option<usize> {
+        self.index.map(|index| index.saturating_add(1))
+    }
+
+    /// The location after the one gone to last, or the first one if none is; `None` past the
+    /// last one.
+    pub fn forward(&mut self) -> Option<&ErrorLocation> {
+        let index = self.index.map_or(0, |index| index.saturating_add(1));
+        let location = self.locations.get(index)?;
+        self.index = Some(index);
+        Some(location)
+    }
+
+    /// The location before the one gone to last; `None` before the first one.
+    pub fn back(&mut self) -> Option<&ErrorLocation> {
+        let index = self.index?.checked_sub(1)?;
+        let location = self.locations.get(index)?;
+        self.index = Some(index);
+        Some(location)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn location(filename: &str, line: usize, column: usize, message: &str) -> ErrorLocation {
+        ErrorLocation {
+            filename: filename.to_owned(),
+            line,
+            column,
+            message: message.to_owned(),
+        }
+    }
+
+    #[test]
+    fn parses_a_gcc_style_line() {
+        assert_eq!(
+            parse_error_line("src/main.c:12:5: error: expected ';' before '}'"),
+            Some(location(
+                "src/main.c",
+                12,
+                5,
+                "error: expected ';' before '}'"
+            ))
+        );
+    }
+
+    #[test]
+    fn parses_a_line_without_a_column() {
+        assert_eq!(
+            parse_error_line("Makefile:3: *** missing separator."),
+            Some(location("Makefile", 3, 1, "*** missing separator."))
+        );
+    }
+
+    #[test]
+    fn skips_lines_that_are_not_locations() {
+        assert_eq!(parse_error_line("error: aborting due to 2 errors"), None);
+        assert_eq!(parse_error_line(":3:4: no file"), None);
+        assert_eq!(parse_error_line(""), None);
+    }
+
+    #[test]
+    fn rustc_locations_take_the_message_of_their_heading() {
+        let errors = ErrorList::parse(
+            "error[E0425]: cannot find value `x` in this scope\n \
+             --> src/main.rs:3:5\n  |\n3 |     x\n  |     ^ not found\n",
+        );
+        assert_eq!(
+            errors.locations,
+            [location(
+                "src/main.rs",
+                3,
+                5,
+                "error[E0425]: cannot find value `x` in this scope"
+            )]
+        );
+    }
+
+    #[test]
+    fn goes_forward_and_back_through_the_list() {
+        let mut errors = ErrorList::parse("a.c:1:1: one\nnoise\nb.c:2:2: two\nc.c:3:3: three\n");
+        assert_eq!(errors.len(), 3);
+        assert_eq!(errors.current(), None);
+        assert!(errors.back().is_none());
+        assert_eq!(errors.forward().map(|l| l.filename.as_str()), Some("a.c"));
+        assert_eq!(errors.forward().map(|l| l.filename.as_str()), Some("b.c"));
+        assert_eq!(errors.forward().map(|l| l.filename.as_str()), Some("c.c"));
+        assert!(errors.forward().is_none());
+        assert_eq!(errors.current(), Some(3));
+        assert_eq!(errors.back().map(|l| l.filename.as_str()), Some("b.c"));
+        assert_eq!(errors.back().map(|l| l.filename.as_str()), Some("a.c"));
+        assert!(errors.back().is_none());
+        assert_eq!(errors.current(), Some(1));
+    }
+}
@@ -1,15 +1,48 @@
 use crate::highlight;
+#[cfg(feature = "spell")]
+use crate::Dictionary;
 use crate::HighlightingOptions;
+use crate::SearchOptions;
+#[cfg(test)]
+use core::cell::Cell;
+use core::cell::{Ref, RefCell};
 use core::cmp;
+use core::ops::Range;
+use std::borrow::Cow;
 
 use termion::color;
+#[cfg(feature = "spell")]
+use termion::style;
+#[cfg(feature = "nfc")]
+use unicode_normalization::{is_nfc, UnicodeNormalization};
 use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// The background of trailing whitespace when it's marked.
+const TRAILING_WHITESPACE_BG_COLOR: color::Rgb = color::Rgb(191, 0, 0);
+/// The background of the part of a row past the maximum line length when it's marked.
+const LONG_LINE_BG_COLOR: color::Rgb = color::Rgb(127, 79, 0);
+/// The color of control characters, which are drawn in caret notation.
+const CONTROL_CHAR_FG_COLOR: color::Rgb = color::Rgb(0, 127, 127);
+/// How many columns back from the width a word wrap looks for whitespace to break after; a longer
+/// word is broken at the width.
+const WORD_WRAP_LOOKBACK: usize = 16;
 
 #[derive(Default)]
 pub struct Row {
     string: String,
     highlight: Vec<highlight::Type>,
     len: usize,
+    /// The byte index at which each grapheme starts, followed by the length of the string, so that
+    /// a grapheme can be located without walking the string from the beginning.
+    boundaries: Vec<usize>,
+    /// The visual column at which each grapheme starts, followed by the width of the row, along
+    /// with the tab width they're computed for; empty if outdated. Computed on demand, as rendering
+    /// only takes `&self`.
+    columns: RefCell<(usize, Vec<usize>)>,
+    /// The number of times the columns were computed, so that the tests can tell when they aren't.
+    #[cfg(test)]
+    column_computations: Cell<usize>,
 }
 
 /// The information after the row that is highlighted; may affect the highlighting of the next row.
@@ -24,8 +57,7 @@ impl From<&str> for Row {
     fn from(s: &str) -> Self {
         let mut row = Self {
             string: String::from(s),
-            highlight: Vec::new(),
-            len: 0,
+            ..Self::default()
         };
         row.update_len();
         row
@@ -33,43 +65,276 @@ impl From<&str> for Row {
 }
 
 impl Row {
+    /// Renders the visual columns from `start` until `end` (exclusive), with tabs expanded to the
+    /// next multiple of `tab_width` columns. The other control characters are drawn in caret
+    /// notation, e.g., `^A` for 0x01, and the C1 ones in hex, e.g., `<9B>`, rather than passed to the
+    /// terminal.
+    #[must_use]
+    pub fn render(&self, start: usize, end: usize, tab_width: usize) -> String {
+        self.render_marking(start, end, tab_width, false, None)
+    }
+
+    /// Like `render`, but with the trailing whitespace, if `marks_trailing_whitespace`, and the
+    /// graphemes past `max_line_length` visual columns, if any, on warning backgrounds, over
+    /// whichever highlighting they have. Trailing whitespace past the length is marked as such.
     #[must_use]
-    pub fn render(&self, start: usize, end: usize) -> String {
-        // Get the actual end of such row.
-        let end = cmp::min(end, self.string.len());
-        // In case that `start` is greater than `end`, we want to return an empty string.
-        let start = cmp::min(start, end);
+    pub fn render_marking(
+        &self,
+        start: usize,
+        end: usize,
+        tab_width: usize,
+        marks_trailing_whitespace: bool,
+        max_line_length: Option<usize>,
+    ) -> String {
+        // Nothing is visible, e.g., a short row scrolled to the right. Past the widest the row can
+        // be, this doesn't walk the graphemes at all; otherwise the columns tell, which rendering
+        // needs anyway.
+        if start >= end
+            || start >= self.max_width(tab_width)
+            || start >= self.column_of_grapheme(self.len, tab_width)
+        {
+            return String::new();
+        }
         let mut result = String::new();
+        let trailing_start = if marks_trailing_whitespace {
+            self.string.trim_end().graphemes(true).count()
+        } else {
+            usize::MAX
+        };
+        let long_start = max_line_length
+            .and_then(|max| self.overflow_start(max, tab_width))
+            .unwrap_or(usize::MAX);
+        let mut curr_mark = None;
         let mut curr_highlight = &highlight::Type::None;
-        #[allow(clippy::arithmetic_side_effects)]
-        for (index, grapheme) in self
-            .string
-            .as_str()
-            .graphemes(true)
-            .enumerate()
-            .skip(start /* the ones to the left of the screen */)
-            .take(end - start /* the visible portion of the row */)
-        {
-            // A tab is converted to a single space.
-            if let Some(c) = grapheme.chars().next() {
+        // Seek to the first grapheme that can be visible, instead of walking from the beginning.
+        let first = {
+            let columns = self.columns(tab_width);
+            let first_ending_after_start = columns
+                .get(1..)
+                .map_or(0, |ends| ends.partition_point(|end| *end <= start));
+            let first_starting_from_start = columns.partition_point(|column| *column < start);
+            cmp::min(first_ending_after_start, first_starting_from_start)
+        };
+        let mut column = self.column_of_grapheme(first, tab_width);
+        let (before, rest) = self.string.split_at(self.byte_index(first));
+        // The highlight is per character rather than per grapheme.
+        let mut char_index = before.chars().count();
+        for (index, grapheme) in (first..).zip(rest.graphemes(true)) {
+            if column >= end {
+                break;
+            }
+            let width = Self::grapheme_width(grapheme, column, tab_width);
+            let next_column = column.saturating_add(width);
+            // Skip the ones to the left of the screen.
+            if next_column > start || (width == 0 && column >= start) {
                 // NOTE: In case some internal error occurs, we want to keep from crashing.
-                let highlight_type = self.highlight.get(index).unwrap_or(&highlight::Type::None);
+                let highlight_type = self
+                    .highlight
+                    .get(char_index)
+                    .unwrap_or(&highlight::Type::None);
                 // Insert a new color sequence only if the color has changed.
                 if highlight_type != curr_highlight {
+                    #[cfg(feature = "spell")]
+                    if *curr_highlight == highlight::Type::Misspelled {
+                        result.push_str(style::NoUnderline.as_ref());
+                    } else if *highlight_type == highlight::Type::Misspelled {
+                        result.push_str(style::Underline.as_ref());
+                    }
                     curr_highlight = highlight_type;
                     let start_highlight = format!("{}", color::Fg(highlight_type.as_color()));
                     result.push_str(&start_highlight);
                 }
-                // NOTE: If converting to multiple spaces, special care would be needed to
-                // maintain the cursor position, as well as leaving it as it is.
-                result.push(if c == '\t' { ' ' } else { c });
+                let mark = if index >= trailing_start {
+                    Some(TRAILING_WHITESPACE_BG_COLOR)
+                } else if index >= long_start {
+                    Some(LONG_LINE_BG_COLOR)
+                } else {
+                    None
+                };
+                // The marks only start further along the row, never end.
+                if mark != curr_mark {
+                    if let Some(bg_color) = mark {
+                        let start_mark = format!("{}", color::Bg(bg_color));
+                        result.push_str(&start_mark);
+                    }
+                    curr_mark = mark;
+                }
+                if grapheme == "\t" || column < start || next_column > end {
+                    // A tab, or a wide character cut by the edge of the screen, is drawn as spaces
+                    // to keep the following columns in place.
+                    let visible_width =
+                        cmp::min(next_column, end).saturating_sub(cmp::max(column, start));
+                    result.push_str(&" ".repeat(visible_width));
+                } else if let Some(caret) = caret_notation(grapheme) {
+                    let control_char = format!(
+                        "{}{caret}{}",
+                        color::Fg(CONTROL_CHAR_FG_COLOR),
+                        color::Fg(curr_highlight.as_color())
+                    );
+                    result.push_str(&control_char);
+                } else {
+                    result.push_str(grapheme);
+                }
             }
+            column = next_column;
+            char_index = char_index.saturating_add(grapheme.chars().count());
+        }
+        #[cfg(feature = "spell")]
+        if *curr_highlight == highlight::Type::Misspelled {
+            result.push_str(style::NoUnderline.as_ref());
+        }
+        if curr_mark.is_some() {
+            let end_mark = format!("{}", color::Bg(color::Reset));
+            result.push_str(&end_mark);
         }
         let end_highlight = format!("{}", color::Fg(color::Reset));
         result.push_str(&end_highlight);
         result
     }
 
+    /// The index of the first grapheme that doesn't fit in `max_line_length` visual columns, a wide
+    /// one that sticks out included; `None` if the row fits.
+    #[must_use]
+    pub fn overflow_start(&self, max_line_length: usize, tab_width: usize) -> Option<usize> {
+        let index = self.grapheme_index_at_column(max_line_length, tab_width);
+        (index < self.len).then_some(index)
+    }
+
+    /// Like `render`, but stops at the last non-whitespace grapheme, so that trailing whitespace
+    /// isn't drawn. The row itself is left untouched.
+    #[must_use]
+    pub fn render_trimmed(&self, start: usize, end: usize, tab_width: usize) -> String {
+        let trimmed_len = self.string.trim_end().graphemes(true).count();
+        let trimmed_end = self.column_of_grapheme(trimmed_len, tab_width);
+        self.render(start, cmp::min(end, trimmed_end), tab_width)
+    }
+
+    /// The entire row with each tab expanded to the next multiple of `tab_width` columns, and the
+    /// other control characters in caret notation, as `render` draws them. Unlike `render`, no
+    /// highlighting is applied.
+    #[must_use]
+    pub fn expand_tabs(&self, tab_width: usize) -> String {
+        let mut result = String::new();
+        let mut column = 0usize;
+        for grapheme in self.string.as_str().graphemes(true) {
+            let width = Self::grapheme_width(grapheme, column, tab_width);
+            if grapheme == "\t" {
+                result.push_str(&" ".repeat(width));
+            } else if let Some(caret) = caret_notation(grapheme) {
+                result.push_str(&caret);
+            } else {
+                result.push_str(grapheme);
+            }
+            column = column.saturating_add(width);
+        }
+        result
+    }
+
+    /// The visual column at which the grapheme at `index` starts, with tabs expanded and wide
+    /// characters taking two columns. An index past the end gives the column after the last grapheme.
+    #[must_use]
+    pub fn column_of_grapheme(&self, index: usize, tab_width: usize) -> usize {
+        let columns = self.columns(tab_width);
+        columns
+            .get(cmp::min(index, self.len))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// The index of the grapheme that covers the visual column, the inverse of `column_of_grapheme`.
+    /// A column in the middle of a tab or a wide character maps to that grapheme; a column past the
+    /// end gives the length of the row.
+    #[must_use]
+    pub fn grapheme_index_at_column(&self, column: usize, tab_width: usize) -> usize {
+        // The first grapheme that ends after the column.
+        self.columns(tab_width)
+            .get(1..)
+            .map_or(0, |ends| ends.partition_point(|end| *end <= column))
+    }
+
+    /// The index of the grapheme at which to wrap the row to fit it in `width` columns: the first
+    /// one that doesn't fit, though at least one grapheme is kept on the line. By word, right after
+    /// the last whitespace instead, if it's within the last `WORD_WRAP_LOOKBACK` columns. The
+    /// length of the row if it fits.
+    #[must_use]
+    pub fn break_point_at_width(&self, width: usize, tab_width: usize, by_word: bool) -> usize {
+        let hard_break = self.grapheme_index_at_column(width, tab_width);
+        if hard_break >= self.len {
+            return self.len;
+        }
+        let hard_break = cmp::max(hard_break, 1);
+        if !by_word {
+            return hard_break;
+        }
+        let min_column = width.saturating_sub(WORD_WRAP_LOOKBACK);
+        (1..=hard_break)
+            .rev()
+            .take_while(|index| self.column_of_grapheme(*index, tab_width) >= min_column)
+            .find(|index| {
+                self.grapheme_at(index.saturating_sub(1))
+                    .and_then(|grapheme| grapheme.chars().next())
+                    .is_some_and(char::is_whitespace)
+            })
+            .unwrap_or(hard_break)
+    }
+
+    /// The cached columns of the graphemes for the tab width; see `Row::columns`.
+    fn columns(&self, tab_width: usize) -> Ref<'_, Vec<usize>> {
+        let is_outdated = {
+            let (cached_tab_width, columns) = &*self.columns.borrow();
+            columns.is_empty() || *cached_tab_width != tab_width
+        };
+        if is_outdated {
+            let mut columns = Vec::with_capacity(self.len.saturating_add(1));
+            let mut column = 0usize;
+            columns.push(column);
+            for grapheme in self.string.as_str().graphemes(true) {
+                column = column.saturating_add(Self::grapheme_width(grapheme, column, tab_width));
+                columns.push(column);
+            }
+            *self.columns.borrow_mut() = (tab_width, columns);
+            #[cfg(test)]
+            self.column_computations
+                .set(self.column_computations.get().saturating_add(1));
+        }
+        Ref::map(self.columns.borrow(), |(_, columns)| columns)
+    }
+
+    /// The most columns the row can take, found without walking its graphemes: a tab takes at most
+    /// `tab_width` columns, and any other byte at most two, e.g., `^A` for 0x01.
+    fn max_width(&self, tab_width: usize) -> usize {
+        let tabs = self.string.bytes().filter(|byte| *byte == b'\t').count();
+        self.string
+            .len()
+            .saturating_sub(tabs)
+            .saturating_mul(2)
+            .saturating_add(tabs.saturating_mul(cmp::max(tab_width, 1)))
+    }
+
+    /// The byte index at which the grapheme at `index` starts; the length of the string if the
+    /// index is past the end.
+    fn byte_index(&self, index: usize) -> usize {
+        self.boundaries
+            .get(index)
+            .copied()
+            .unwrap_or(self.string.len())
+    }
+
+    /// The number of columns the grapheme takes when it starts at `column`.
+    /// A tab extends to the next multiple of `tab_width`, and another control character takes the
+    /// columns of its caret notation.
+    fn grapheme_width(grapheme: &str, column: usize, tab_width: usize) -> usize {
+        if grapheme == "\t" {
+            let tab_width = cmp::max(tab_width, 1);
+            #[allow(clippy::arithmetic_side_effects)] // `tab_width` is non-zero.
+            return tab_width - column % tab_width;
+        }
+        if let Some(caret) = caret_notation(grapheme) {
+            return caret.len();
+        }
+        grapheme.width()
+    }
+
     #[must_use]
     pub fn len(&self) -> usize {
         self.len
@@ -80,23 +345,29 @@ impl Row {
         self.len == 0
     }
 
-    /// To avoid recomputing the length of the row every time we need it.
+    /// Whether the row is empty or only whitespace.
+    #[must_use]
+    pub fn is_blank(&self) -> bool {
+        self.string.trim().is_empty()
+    }
+
+    /// To avoid recomputing the length and the grapheme boundaries of the row every time we need
+    /// them. Must be called whenever the string changes.
     fn update_len(&mut self) {
-        self.len = self.string.as_str().graphemes(true).count();
+        self.boundaries = self
+            .string
+            .as_str()
+            .grapheme_indices(true)
+            .map(|(byte_index, _)| byte_index)
+            .collect();
+        self.len = self.boundaries.len();
+        self.boundaries.push(self.string.len());
+        self.columns.get_mut().1.clear();
     }
 
     pub fn insert(&mut self, at: usize, c: char) {
-        if at >= self.len() {
-            self.string.push(c);
-        } else {
-            // Splits the string into two half, inserts the character after the
-            // first part, and then append the second part.
-            let mut result: String = self.string.as_str().graphemes(true).take(at).collect();
-            let reminder: String = self.string.as_str().graphemes(true).skip(at).collect();
-            result.push(c);
-            result.push_str(&reminder);
-            self.string = result;
-        }
+        // Past the end, this appends.
+        self.string.insert(self.byte_index(at), c);
         self.update_len();
     }
 
@@ -105,15 +376,31 @@ impl Row {
         if at >= self.len() {
             return;
         }
-        let mut result: String = self.string.as_str().graphemes(true).take(at).collect();
-        let remainder: String = self.string.as_str().graphemes(true).skip(at + 1).collect();
-        result.push_str(&remainder);
-        self.string = result;
+        self.string
+            .replace_range(self.byte_index(at)..self.byte_index(at + 1), "");
         self.update_len();
     }
 
+    /// Replaces the graphemes from `start` until `end` (exclusive) with the string, clamped to the
+    /// row; nothing is replaced if `end` isn't after `start`, though the string is still inserted.
+    pub fn replace_range(&mut self, start: usize, end: usize, with: &str) {
+        let start = cmp::min(start, self.len());
+        let end = end.clamp(start, self.len());
+        self.string
+            .replace_range(self.byte_index(start)..self.byte_index(end), with);
+        self.update_len();
+    }
+
+    /// Appends the other row, whose first grapheme may combine with the last one of this row.
     pub fn append(&mut self, new: &Self) {
-        self.string = format!("{}{}", self.string, new.string);
+        if new.string.is_empty() {
+            return;
+        }
+        if self.string.is_empty() {
+            self.string.clone_from(&new.string);
+        } else {
+            self.string.push_str(&new.string);
+        }
         self.update_len();
     }
 
@@ -121,80 +408,179 @@ impl Row {
     /// everything behind that index.
     #[must_use]
     pub fn split(&mut self, at: usize) -> Self {
-        let beginning: String = self.string.as_str().graphemes(true).take(at).collect();
-        let remainder: String = self.string.as_str().graphemes(true).skip(at).collect();
-        self.string = beginning;
+        let remainder = self.string.split_off(self.byte_index(at));
         self.update_len();
         Self::from(&*remainder)
     }
 
+    /// Normalizes the row to NFC (canonical composition), returning whether anything changed.
+    #[cfg(feature = "nfc")]
+    pub fn normalize_nfc(&mut self) -> bool {
+        if is_nfc(&self.string) {
+            return false;
+        }
+        self.string = self.string.nfc().collect();
+        self.update_len();
+        true
+    }
+
+    /// The grapheme at `index`, or `None` if it's past the end.
+    #[must_use]
+    pub fn grapheme_at(&self, index: usize) -> Option<&str> {
+        let end = *self.boundaries.get(index.checked_add(1)?)?;
+        self.string.get(self.byte_index(index)..end)
+    }
+
+    /// The text of the graphemes from `start` until `end` (exclusive), clamped to the row.
+    #[must_use]
+    pub fn slice(&self, start: usize, end: usize) -> &str {
+        let start = cmp::min(start, self.len());
+        let end = end.clamp(start, self.len());
+        self.string
+            .get(self.byte_index(start)..self.byte_index(end))
+            .unwrap_or_default()
+    }
+
+    /// Whether the grapheme at `index` is highlighted as part of a string or a character literal.
+    #[must_use]
+    pub fn is_in_string(&self, index: usize) -> bool {
+        // The highlight is per character rather than per grapheme.
+        let char_index = self
+            .string
+            .get(..self.byte_index(index))
+            .map_or(0, |before| before.chars().count());
+        matches!(
+            self.highlight.get(char_index),
+            Some(highlight::Type::String | highlight::Type::Character)
+        )
+    }
+
+    /// The graphemes of the word at `index`, or of the word right before it, so that a cursor at the
+    /// end of a word is still on it. A word is a run of graphemes that aren't separators.
+    #[must_use]
+    pub fn word_at(&self, index: usize) -> Option<Range<usize>> {
+        let is_word = |index: usize| {
+            self.grapheme_at(index)
+                .and_then(|grapheme| grapheme.chars().next())
+                .is_some_and(|c| !Self::is_separator(c))
+        };
+        let index = if is_word(index) {
+            index
+        } else {
+            index.checked_sub(1).filter(|index| is_word(*index))?
+        };
+        let start = (0..index)
+            .rev()
+            .find(|index| !is_word(*index))
+            .map_or(0, |index| index.saturating_add(1));
+        let end = (index..self.len())
+            .find(|index| !is_word(*index))
+            .unwrap_or(self.len());
+        Some(start..end)
+    }
+
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.string
+    }
+
+    #[must_use]
+    pub fn into_string(self) -> String {
+        self.string
+    }
+
+    /// The indentation of the row: the spaces and tabs it starts with.
+    #[must_use]
+    pub fn leading_whitespace(&self) -> &str {
+        let content = self.string.trim_start_matches([' ', '\t']);
+        self.string
+            .get(..self.string.len().saturating_sub(content.len()))
+            .unwrap_or_default()
+    }
+
     #[must_use]
     pub fn as_bytes(&self) -> &[u8] {
         self.string.as_bytes()
     }
 
-    /// Finds the index of the first occurrence of a query string after a given index.
-    /// An empty query string will return `None`.
+    /// Finds the grapheme index of the first occurrence of a query string at or after a given
+    /// index. An empty query string will return `None`.
     #[must_use]
-    pub fn find_after(&self, query: &str, after: usize) -> Option<usize> {
+    pub fn find(&self, query: &str, after: usize, opts: &SearchOptions) -> Option<usize> {
         if after >= self.len() || query.is_empty() {
             return None;
         }
-        let substring: String = self.string.as_str().graphemes(true).skip(after).collect();
-        let match_byte_index = substring.find(query);
-        if let Some(match_byte_index) = match_byte_index {
-            for (grapheme_index, (byte_index, _)) in
-                substring.as_str().grapheme_indices(true).enumerate()
-            {
-                if byte_index == match_byte_index {
-                    #[allow(clippy::arithmetic_side_effects)]
-                    return Some(after + grapheme_index);
-                }
-            }
-        }
-        None
+        let (text, boundaries, query) = self.searchable(query, opts);
+        let start = boundaries.get(after).copied()?;
+        let match_byte_index = text.get(start..)?.find(&*query)?.saturating_add(start);
+        // A match that doesn't start at a grapheme boundary isn't a match of graphemes.
+        boundaries.binary_search(&match_byte_index).ok()
     }
 
-    /// Finds the index of the last occurrence of a query string before a given index. `before` is
-    /// excluded from the search. An empty query string will return `None`.
+    /// Finds the grapheme index of the last occurrence of a query string before a given index.
+    /// `before` is excluded from the search. An empty query string will return `None`.
     #[must_use]
-    pub fn rfind_before(&self, query: &str, before: usize) -> Option<usize> {
+    pub fn rfind(&self, query: &str, before: usize, opts: &SearchOptions) -> Option<usize> {
         if before == 0 || query.is_empty() {
             return None;
         }
+        let (text, boundaries, query) = self.searchable(query, opts);
         // NOTE: Since a before exceeding the length of the row doesn't affect the result,
         // we permit it.
-        let substring: String = self.string.as_str().graphemes(true).take(before).collect();
-        let match_byte_index = substring.rfind(query);
-        if let Some(match_byte_index) = match_byte_index {
-            for (grapheme_index, (byte_index, _)) in
-                substring.as_str().grapheme_indices(true).enumerate()
-            {
-                if byte_index == match_byte_index {
-                    return Some(grapheme_index);
-                }
-            }
+        let end = boundaries
+            .get(cmp::min(before, self.len))
+            .copied()
+            .unwrap_or_default();
+        let match_byte_index = text.get(..end)?.rfind(&*query)?;
+        boundaries.binary_search(&match_byte_index).ok()
+    }
+
+    /// The text to search in with its grapheme boundaries, and the query to search for, as
+    /// matched under the options. Ignoring case lowercases both, which may change the byte
+    /// length of the graphemes, so the boundaries are recomputed.
+    fn searchable<'query>(
+        &self,
+        query: &'query str,
+        opts: &SearchOptions,
+    ) -> (Cow<'_, str>, Cow<'_, [usize]>, Cow<'query, str>) {
+        if !opts.ignores_case(query) {
+            return (
+                Cow::Borrowed(&self.string),
+                Cow::Borrowed(&self.boundaries),
+                Cow::Borrowed(query),
+            );
         }
-        None
+        let mut text = String::with_capacity(self.string.len());
+        let mut boundaries = Vec::with_capacity(self.boundaries.len());
+        for grapheme in self.string.as_str().graphemes(true) {
+            boundaries.push(text.len());
+            text.push_str(&grapheme.to_lowercase());
+        }
+        boundaries.push(text.len());
+        (
+            Cow::Owned(text),
+            Cow::Owned(boundaries),
+            Cow::Owned(query.to_lowercase()),
+        )
     }
 
     /// Assuming that the character before `from` is not a backslash.
     #[must_use]
     #[allow(clippy::arithmetic_side_effects)] // Overflow checked by `checked_add`.
     fn forms_character_from(&self, from: usize) -> bool {
-        if let Some(c) = self.string.as_str().graphemes(true).nth(from) {
+        if let Some(c) = self.grapheme_at(from) {
             if c == "'" && from.checked_add(1).is_some() {
                 // There are two forms:
                 // - '.' (single character)
                 // - '\.' (escaped character)
                 // where '.' is any character except for a backslash.
-                if let Some(c) = self.string.as_str().graphemes(true).nth(from + 1) {
+                if let Some(c) = self.grapheme_at(from + 1) {
                     return (c != "\\"
                         && from.checked_add(2).is_some()
-                        && self.string.as_str().graphemes(true).nth(from + 2) == Some("'"))
+                        && self.grapheme_at(from + 2) == Some("'"))
                         || (c == "\\"
                             && from.checked_add(3).is_some()
-                            && self.string.as_str().graphemes(true).nth(from + 3) == Some("'"));
+                            && self.grapheme_at(from + 3) == Some("'"));
                 }
             }
         }
@@ -218,7 +604,7 @@ impl Row {
             if self
                 .string
                 .get(from..)
-                .map_or(false, |s| s.starts_with(keyword))
+                .is_some_and(|s| s.starts_with(keyword))
             {
                 if let Some(next_index) = from.checked_add(keyword.len()) {
                     // The separater is either the end of the row (line) or an actual separator.
@@ -226,8 +612,8 @@ impl Row {
                         *keyword_len = keyword.len();
                         return keyword.len();
                     }
-                    if let Some(c) = self.string.as_str().graphemes(true).nth(next_index) {
-                        if c.chars().next().map_or(false, Self::is_separator) {
+                    if let Some(c) = self.grapheme_at(next_index) {
+                        if c.chars().next().is_some_and(Self::is_separator) {
                             *keyword_len = keyword.len();
                             return keyword.len();
                         }
@@ -256,7 +642,7 @@ impl Row {
             if self
                 .string
                 .get(from..)
-                .map_or(false, |s| s.starts_with(data_type))
+                .is_some_and(|s| s.starts_with(data_type))
             {
                 if let Some(next_index) = from.checked_add(data_type.len()) {
                     // The separater is either the end of the row or a punctuation or whitespace.
@@ -264,8 +650,8 @@ impl Row {
                         *data_type_len = data_type.len();
                         return data_type.len();
                     }
-                    if let Some(c) = self.string.as_str().graphemes(true).nth(next_index) {
-                        if c.chars().next().map_or(false, Self::is_separator) {
+                    if let Some(c) = self.grapheme_at(next_index) {
+                        if c.chars().next().is_some_and(Self::is_separator) {
                             *data_type_len = data_type.len();
                             return data_type.len();
                         }
@@ -296,28 +682,26 @@ impl Row {
         let mut is_in_string = false;
         let mut is_escaped = false;
         let mut prev_highlight = highlight::Type::None;
-        self.highlight = self
-            .string
-            .chars()
+        // Collected once, since looking up a neighbor with `chars().nth` is linear.
+        let chars: Vec<char> = self.string.chars().collect();
+        self.highlight = chars
+            .iter()
+            .copied()
             .enumerate()
             .map(|(i, c)| {
                 prev_highlight = if opts.comments && is_in_comment
-                    || (c == '/'
-                        && i.checked_add(1).is_some()
-                        && self.string.chars().nth(i + 1) == Some('/'))
+                    || (c == '/' && i.checked_add(1).is_some() && chars.get(i + 1) == Some(&'/'))
                 {
                     // The rest of the line is a comment; not going to end.
                     is_in_comment = true;
                     highlight::Type::Comment
                 } else if opts.multiline_comments && is_in_multiline_comment
-                    || (c == '/'
-                        && i.checked_add(1).is_some()
-                        && self.string.chars().nth(i + 1) == Some('*'))
+                    || (c == '/' && i.checked_add(1).is_some() && chars.get(i + 1) == Some(&'*'))
                 {
                     if is_in_multiline_comment
                         && c == '/'
                         && i.checked_sub(1).is_some()
-                        && self.string.chars().nth(i - 1) == Some('*')
+                        && chars.get(i - 1) == Some(&'*')
                     {
                         is_in_multiline_comment = false;
                     } else if !is_in_multiline_comment {
@@ -329,11 +713,7 @@ impl Row {
                     && (c.is_ascii_digit()
                         || (c == '.'
                             && i.checked_add(1).is_some()
-                            && self
-                                .string
-                                .chars()
-                                .nth(i + 1)
-                                .map_or(false, |c| c.is_ascii_digit())))
+                            && chars.get(i + 1).is_some_and(char::is_ascii_digit)))
                     && (prev_is_separator || prev_highlight == highlight::Type::Number)
                 {
                     highlight::Type::Number
@@ -366,7 +746,7 @@ impl Row {
                 {
                     remaining_data_type_len = remaining_data_type_len.saturating_sub(1);
                     highlight::Type::DataType
-                } else if opts.punctuations.iter().any(|&p| p == c) {
+                } else if opts.punctuations.contains(&c) {
                     highlight::Type::Punctuation
                 } else {
                     highlight::Type::None
@@ -381,27 +761,58 @@ impl Row {
         }
     }
 
+    /// Marks the words not in the dictionary as misspelled, over the existing highlight.
+    #[cfg(feature = "spell")]
+    pub fn highlight_misspelled(&mut self, dictionary: &Dictionary) {
+        let ranges = dictionary.misspelled_ranges(&self.string);
+        self.highlight_graphemes(&ranges, highlight::Type::Misspelled);
+    }
+
     /// Highlights all occurrences of a query string in the row with other words untouched.
-    pub fn highlight_query(&mut self, query: &str) {
-        // Find the index of all occurrences of the query string.
+    pub fn highlight_query(&mut self, query: &str, opts: &SearchOptions) {
+        if query.is_empty() {
+            return;
+        }
+        let query_len = query.graphemes(true).count();
         let mut matches = Vec::new();
-        let mut start = 0;
-        while let Some(index) = self.find_after(query, start) {
-            matches.push(index);
-            if let Some(next_index) = index.checked_add(query.graphemes(true).count()) {
-                start = next_index;
-            } else {
-                break;
+        {
+            // Prepared once for all the matches, as ignoring case lowercases the whole row.
+            let (text, boundaries, query) = self.searchable(query, opts);
+            let mut start = 0usize;
+            while let Some(found) = text.get(start..).and_then(|rest| rest.find(&*query)) {
+                let match_byte_index = start.saturating_add(found);
+                // A match that doesn't start at a grapheme boundary isn't a match of graphemes, so
+                // the search goes on from the next grapheme.
+                let next = match boundaries.binary_search(&match_byte_index) {
+                    Ok(index) => {
+                        matches.push(index..index.saturating_add(query_len));
+                        index.saturating_add(query_len)
+                    }
+                    Err(index) => index,
+                };
+                let Some(next_start) = boundaries.get(next).copied() else {
+                    break;
+                };
+                start = next_start;
             }
         }
+        self.highlight_graphemes(&matches, highlight::Type::Search);
+    }
 
-        // Highlight the matches.
-        while let Some(index) = matches.pop() {
-            for i in index..index.saturating_add(query.graphemes(true).count()) {
-                if let Some(highlight) = self.highlight.get_mut(i) {
-                    *highlight = highlight::Type::Search;
+    /// Highlights the graphemes in the ranges, which are sorted, as the type. The highlight is per
+    /// character, so each grapheme is highlighted along with its combining characters.
+    fn highlight_graphemes(&mut self, ranges: &[Range<usize>], highlight_type: highlight::Type) {
+        let mut ranges = ranges.iter().peekable();
+        let mut char_index = 0usize;
+        for (index, grapheme) in self.string.graphemes(true).enumerate() {
+            while ranges.next_if(|range| range.end <= index).is_some() {}
+            let char_count = grapheme.chars().count();
+            if ranges.peek().is_some_and(|range| range.contains(&index)) {
+                for highlight in self.highlight.iter_mut().skip(char_index).take(char_count) {
+                    *highlight = highlight_type;
                 }
             }
+            char_index = char_index.saturating_add(char_count);
         }
     }
 
@@ -410,3 +821,384 @@ impl Row {
         (c.is_ascii_punctuation() && c != '_') || c.is_ascii_whitespace()
     }
 }
+
+/// The caret notation of an ASCII control character other than tab, e.g., `^A` for 0x01 and `^?`
+/// for DEL, or the code in hex of a C1 control character, which has none, e.g., `<9B>`; `None` if
+/// the grapheme isn't one.
+fn caret_notation(grapheme: &str) -> Option<String> {
+    let mut chars = grapheme.chars();
+    let c = chars.next()?;
+    if chars.next().is_some() || !c.is_control() || c == '\t' {
+        return None;
+    }
+    if !c.is_ascii() {
+        // Terminals may act on these, e.g., 0x9B starts an escape sequence like `ESC [`.
+        return Some(format!("<{:02X}>", u32::from(c)));
+    }
+    // Flipping the bit maps 0x00-0x1F to `@` through `_`, and 0x7F to `?`.
+    Some(format!("^{}", char::from((c as u8) ^ 0x40)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The rendered text without the escape sequences of the colors and styles.
+    fn visible(rendered: &str) -> String {
+        let mut text = String::new();
+        let mut is_in_sequence = false;
+        for c in rendered.chars() {
+            match c {
+                '\x1b' => is_in_sequence = true,
+                'm' if is_in_sequence => is_in_sequence = false,
+                _ if is_in_sequence => (),
+                _ => text.push(c),
+            }
+        }
+        text
+    }
+
+    #[test]
+    fn columns_and_grapheme_indices_round_trip() {
+        // A tab to the next stop of 4 and a wide character taking 2 columns.
+        let row = Row::from("a\tb\u{4e2d}c");
+        let columns: Vec<usize> = (0..=row.len())
+            .map(|index| row.column_of_grapheme(index, 4))
+            .collect();
+        assert_eq!(columns, [0, 1, 4, 5, 7, 8]);
+        for index in 0..row.len() {
+            let column = row.column_of_grapheme(index, 4);
+            assert_eq!(row.grapheme_index_at_column(column, 4), index);
+        }
+    }
+
+    #[test]
+    fn column_inside_a_tab_or_wide_character_maps_to_it() {
+        let row = Row::from("a\tb\u{4e2d}c");
+        assert_eq!(row.grapheme_index_at_column(2, 4), 1);
+        assert_eq!(row.grapheme_index_at_column(3, 4), 1);
+        assert_eq!(row.grapheme_index_at_column(6, 4), 3);
+        assert_eq!(row.grapheme_index_at_column(100, 4), row.len());
+        assert_eq!(row.column_of_grapheme(100, 4), 8);
+    }
+
+    #[test]
+    fn render_trimmed_leaves_out_trailing_whitespace() {
+        let row = Row::from("foo bar \t ");
+        assert_eq!(visible(&row.render(0, 80, 4)), "foo bar      ");
+        assert_eq!(visible(&row.render_trimmed(0, 80, 4)), "foo bar");
+        assert_eq!(visible(&row.render_trimmed(4, 80, 4)), "bar");
+        assert_eq!(row.as_str(), "foo bar \t ");
+    }
+
+    #[test]
+    fn render_trimmed_of_a_blank_row_is_empty() {
+        let row = Row::from("   ");
+        assert_eq!(visible(&row.render_trimmed(0, 80, 4)), "");
+    }
+
+    #[test]
+    fn renders_a_window_of_a_very_long_row() {
+        let mut row = Row::from("0123456789".repeat(10_000).as_str());
+        assert_eq!(visible(&row.render(50_000, 50_010, 4)), "0123456789");
+        row.insert(50_000, '-');
+        row.delete(0);
+        assert_eq!(row.len(), 100_000);
+        assert_eq!(visible(&row.render(49_998, 50_002, 4)), "9-01");
+        assert_eq!(visible(&row.render(100_000, 100_080, 4)), "");
+    }
+
+    #[test]
+    fn finds_matches_in_multibyte_text_near_the_edges() {
+        let row = Row::from("\u{e9}t\u{e9} caf\u{e9}");
+        let opts = SearchOptions::default();
+        assert_eq!(row.find("\u{e9}t", 0, &opts), Some(0));
+        assert_eq!(row.find("\u{e9}", 1, &opts), Some(2));
+        assert_eq!(row.find("caf\u{e9}", 0, &opts), Some(4));
+        assert_eq!(row.find("\u{e9}", 8, &opts), None);
+        assert_eq!(row.rfind("\u{e9}", row.len(), &opts), Some(7));
+        assert_eq!(row.rfind("\u{e9}", 7, &opts), Some(2));
+        assert_eq!(row.rfind("\u{e9}t", 0, &opts), None);
+        assert_eq!(row.find("", 0, &opts), None);
+    }
+
+    #[test]
+    fn finds_regardless_of_case_when_ignoring_it() {
+        let row = Row::from("\u{c9}COLE \u{e9}cole");
+        let opts = SearchOptions {
+            ignore_case: true,
+            smart_case: false,
+        };
+        assert_eq!(row.find("\u{e9}cole", 0, &opts), Some(0));
+        assert_eq!(row.find("\u{e9}cole", 1, &opts), Some(6));
+        assert_eq!(
+            row.find("\u{e9}cole", 1, &SearchOptions::default()),
+            Some(6)
+        );
+        assert_eq!(
+            row.find("\u{e9}cole", 0, &SearchOptions::default()),
+            Some(6)
+        );
+    }
+
+    #[test]
+    fn highlights_every_match_of_the_query() {
+        let mut row = Row::from("e\u{301}b ab ab");
+        row.highlight(
+            &HighlightingOptions::default(),
+            &HighlightContext::default(),
+        );
+        row.highlight_query("b", &SearchOptions::default());
+        let searched: Vec<bool> = row
+            .highlight
+            .iter()
+            .map(|highlight| *highlight == highlight::Type::Search)
+            .collect();
+        assert_eq!(
+            searched,
+            [false, false, true, false, false, true, false, false, true]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "spell")]
+    fn underlines_misspelled_words_after_multibyte_graphemes() {
+        let mut row = Row::from("e\u{301} teh");
+        row.highlight(
+            &HighlightingOptions::default(),
+            &HighlightContext::default(),
+        );
+        row.highlight_misspelled(&Dictionary::from_words(["the"]));
+        let misspelled: Vec<bool> = row
+            .highlight
+            .iter()
+            .map(|highlight| *highlight == highlight::Type::Misspelled)
+            .collect();
+        assert_eq!(misspelled, [true, true, false, true, true, true]);
+    }
+
+    #[test]
+    fn marks_only_the_trailing_whitespace() {
+        let row = Row::from("a b \t ");
+        let mark = format!("{}", color::Bg(TRAILING_WHITESPACE_BG_COLOR));
+        let rendered = row.render_marking(0, 80, 4, true, None);
+        let Some((before, after)) = rendered.split_once(&mark) else {
+            panic!("no trailing whitespace mark in {rendered:?}");
+        };
+        assert_eq!(visible(before), "a b");
+        assert_eq!(visible(after), "      ");
+        assert!(!after.contains(&mark));
+        assert!(!row.render_marking(0, 80, 4, false, None).contains(&mark));
+        assert!(!Row::from("a b")
+            .render_marking(0, 80, 4, true, None)
+            .contains(&mark));
+    }
+
+    #[test]
+    fn replaces_a_range_with_a_longer_or_shorter_string() {
+        let mut row = Row::from("hello world");
+        row.replace_range(6, 11, "wide world");
+        assert_eq!(row.as_str(), "hello wide world");
+        assert_eq!(row.len(), 16);
+        row.replace_range(0, 5, "a");
+        assert_eq!(row.as_str(), "a wide world");
+        assert_eq!(row.len(), 12);
+    }
+
+    #[test]
+    fn replace_range_clamps_to_the_row() {
+        let mut row = Row::from("caf\u{e9}");
+        row.replace_range(3, 10, "e!");
+        assert_eq!(row.as_str(), "cafe!");
+        row.replace_range(9, 12, "?");
+        assert_eq!(row.as_str(), "cafe!?");
+        assert_eq!(row.len(), 6);
+    }
+
+    #[test]
+    fn renders_nothing_from_past_the_end() {
+        let row = Row::from("a\tb");
+        assert_eq!(row.render(5, 20, 4), "");
+        assert_eq!(visible(&row.render(4, 20, 4)), "b");
+        assert_eq!(Row::from("").render(0, 20, 4), "");
+        assert_eq!(Row::from("abc").render(2, 2, 4), "");
+    }
+
+    #[test]
+    fn rendering_from_past_the_widest_the_row_can_be_walks_no_graphemes() {
+        let row = Row::from("a\tb\u{4e2d}");
+        assert_eq!(row.render(14, 94, 4), "");
+        assert_eq!(row.column_computations.get(), 0);
+        // Within the bound, the columns tell, and are kept for rendering.
+        assert_eq!(row.render(8, 88, 4), "");
+        assert_eq!(visible(&row.render(4, 84, 4)), "b\u{4e2d}");
+        assert_eq!(row.column_computations.get(), 1);
+    }
+
+    #[test]
+    fn leading_whitespace_is_the_indentation() {
+        assert_eq!(Row::from("    let x;").leading_whitespace(), "    ");
+        assert_eq!(Row::from("\t\tlet x;").leading_whitespace(), "\t\t");
+        assert_eq!(Row::from("\t  \tx y").leading_whitespace(), "\t  \t");
+        assert_eq!(Row::from(" \t ").leading_whitespace(), " \t ");
+        assert_eq!(Row::from("x  ").leading_whitespace(), "");
+    }
+
+    #[test]
+    fn blank_rows_are_empty_or_only_whitespace() {
+        assert!(Row::from("").is_blank());
+        assert!(Row::from("    ").is_blank());
+        assert!(Row::from("\t\t").is_blank());
+        assert!(Row::from(" \t\u{3000}").is_blank());
+        assert!(!Row::from("  x ").is_blank());
+        assert!(!Row::from("\t.").is_blank());
+    }
+
+    #[test]
+    fn tells_strings_by_grapheme_index_after_combining_marks() {
+        let mut row = Row::from("\"e\u{301}e\u{301}\" x");
+        let options = HighlightingOptions {
+            strings: true,
+            ..HighlightingOptions::default()
+        };
+        row.highlight(&options, &HighlightContext::default());
+        assert!(row.is_in_string(0));
+        assert!(row.is_in_string(2));
+        assert!(row.is_in_string(3));
+        assert!(!row.is_in_string(4));
+        assert!(!row.is_in_string(5));
+    }
+
+    #[test]
+    fn draws_control_characters_in_caret_notation() {
+        let row = Row::from("a\u{1}b\u{7f}c");
+        assert_eq!(visible(&row.render(0, 80, 4)), "a^Ab^?c");
+        assert_eq!(row.column_of_grapheme(2, 4), 3);
+        assert_eq!(row.column_of_grapheme(row.len(), 4), 7);
+    }
+
+    #[test]
+    fn draws_c1_control_characters_in_hex() {
+        let row = Row::from("a\u{9b}b\u{85}");
+        assert_eq!(visible(&row.render(0, 80, 4)), "a<9B>b<85>");
+        assert_eq!(row.column_of_grapheme(2, 4), 5);
+        assert_eq!(row.column_of_grapheme(row.len(), 4), 10);
+    }
+
+    #[test]
+    fn leaves_tabs_and_printable_characters_alone() {
+        let row = Row::from("\t\u{e9}\u{a0}~");
+        assert_eq!(visible(&row.render(0, 80, 4)), "    \u{e9}\u{a0}~");
+    }
+
+    #[test]
+    fn appending_to_an_empty_row_takes_the_other() {
+        let mut row = Row::from("");
+        row.append(&Row::from("\u{e9}\u{4e2d}"));
+        assert_eq!(row.as_str(), "\u{e9}\u{4e2d}");
+        assert_eq!(row.len(), 2);
+    }
+
+    #[test]
+    fn appending_an_empty_row_changes_nothing() {
+        let mut row = Row::from("foo");
+        row.append(&Row::from(""));
+        assert_eq!(row.as_str(), "foo");
+        assert_eq!(row.len(), 3);
+    }
+
+    #[test]
+    fn appending_multibyte_text_counts_graphemes() {
+        let mut row = Row::from("caf");
+        // An `e` with a combining acute accent, which makes one grapheme of two characters.
+        row.append(&Row::from("e\u{301} \u{4e2d}"));
+        assert_eq!(row.as_str(), "cafe\u{301} \u{4e2d}");
+        assert_eq!(row.len(), 6);
+    }
+
+    #[test]
+    fn hard_wrap_breaks_at_exactly_the_width() {
+        let row = Row::from("abcdefghij");
+        assert_eq!(row.break_point_at_width(4, 4, false), 4);
+        assert_eq!(row.break_point_at_width(10, 4, false), 10);
+        assert_eq!(row.break_point_at_width(20, 4, false), 10);
+    }
+
+    #[test]
+    fn hard_wrap_keeps_a_wide_character_that_does_not_fit_whole_for_the_next_line() {
+        let row = Row::from("ab\u{4e2d}cd");
+        assert_eq!(row.break_point_at_width(3, 4, false), 2);
+        // At least one grapheme is kept on the line, even if it doesn't fit.
+        assert_eq!(row.break_point_at_width(1, 4, false), 1);
+        assert_eq!(Row::from("\u{4e2d}x").break_point_at_width(1, 4, false), 1);
+    }
+
+    #[test]
+    fn word_wrap_breaks_after_the_last_space() {
+        let row = Row::from("the quick brown fox");
+        assert_eq!(row.break_point_at_width(12, 4, true), 10);
+        assert_eq!(row.break_point_at_width(10, 4, true), 10);
+    }
+
+    #[test]
+    fn word_wrap_without_a_space_nearby_breaks_hard() {
+        let row = Row::from(format!("a {}", "x".repeat(40)).as_str());
+        assert_eq!(row.break_point_at_width(30, 4, true), 30);
+        let row = Row::from("x".repeat(40).as_str());
+        assert_eq!(row.break_point_at_width(8, 4, true), 8);
+    }
+
+    #[test]
+    fn smart_case_finds_regardless_of_case_unless_the_query_has_uppercase() {
+        let row = Row::from("FOO Foo foo");
+        let opts = SearchOptions {
+            ignore_case: false,
+            smart_case: true,
+        };
+        assert_eq!(row.find("foo", 0, &opts), Some(0));
+        assert_eq!(row.find("Foo", 0, &opts), Some(4));
+        assert_eq!(row.find("FOO", 1, &opts), None);
+    }
+
+    #[test]
+    fn overflows_at_the_first_grapheme_past_the_length() {
+        assert_eq!(Row::from("abcdef").overflow_start(4, 4), Some(4));
+        assert_eq!(Row::from("abcd").overflow_start(4, 4), None);
+        assert_eq!(Row::from("").overflow_start(0, 4), None);
+        // A wide one that sticks out overflows too.
+        assert_eq!(Row::from("ab\u{4e2d}d").overflow_start(3, 4), Some(2));
+        assert_eq!(Row::from("\tab").overflow_start(5, 4), Some(2));
+    }
+
+    #[test]
+    fn marks_only_the_graphemes_past_the_line_length() {
+        let row = Row::from("abc\u{4e2d}de");
+        let mark = format!("{}", color::Bg(LONG_LINE_BG_COLOR));
+        let rendered = row.render_marking(0, 80, 4, false, Some(4));
+        let Some((before, after)) = rendered.split_once(&mark) else {
+            panic!("no long line mark in {rendered:?}");
+        };
+        assert_eq!(visible(before), "abc");
+        assert_eq!(visible(after), "\u{4e2d}de");
+        assert!(!row.render_marking(0, 80, 4, false, Some(7)).contains(&mark));
+        assert!(!row.render_marking(0, 80, 4, false, None).contains(&mark));
+        // Scrolled past the start of the mark, it's still drawn.
+        assert!(row.render_marking(6, 80, 4, false, Some(4)).contains(&mark));
+    }
+
+    #[test]
+    fn trailing_whitespace_past_the_line_length_is_marked_as_such() {
+        let row = Row::from("abcd  ");
+        let long = format!("{}", color::Bg(LONG_LINE_BG_COLOR));
+        let trailing = format!("{}", color::Bg(TRAILING_WHITESPACE_BG_COLOR));
+        let rendered = row.render_marking(0, 80, 4, true, Some(2));
+        let Some((_, after)) = rendered.split_once(&long) else {
+            panic!("no long line mark in {rendered:?}");
+        };
+        let Some((long_part, trailing_part)) = after.split_once(&trailing) else {
+            panic!("no trailing whitespace mark in {rendered:?}");
+        };
+        assert_eq!(visible(long_part), "cd");
+        assert_eq!(visible(trailing_part), "  ");
+    }
+}
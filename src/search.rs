@@ -0,0 +1,49 @@
+/// How a query is matched against the text.
+#[derive(Default, Clone)]
+pub struct SearchOptions {
+    /// Matches regardless of case, e.g., `foo` matches `FOO`.
+    pub ignore_case: bool,
+    /// Matches regardless of case unless the query has an uppercase letter, e.g., `foo` matches
+    /// `FOO` but `Foo` doesn't; takes the place of `ignore_case`.
+    pub smart_case: bool,
+}
+
+impl SearchOptions {
+    /// Whether the query is matched regardless of case.
+    #[must_use]
+    pub fn ignores_case(&self, query: &str) -> bool {
+        if self.smart_case {
+            !query.chars().any(char::is_uppercase)
+        } else {
+            self.ignore_case
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SMART_CASE: SearchOptions = SearchOptions {
+        ignore_case: false,
+        smart_case: true,
+    };
+
+    #[test]
+    fn smart_case_ignores_case_only_for_a_lowercase_query() {
+        assert!(SMART_CASE.ignores_case("foo"));
+        assert!(SMART_CASE.ignores_case("foo-42"));
+        assert!(!SMART_CASE.ignores_case("Foo"));
+        assert!(!SMART_CASE.ignores_case("\u{c9}cole"));
+    }
+
+    #[test]
+    fn smart_case_takes_the_place_of_ignore_case() {
+        let opts = SearchOptions {
+            ignore_case: true,
+            smart_case: true,
+        };
+        assert!(!opts.ignores_case("Foo"));
+        assert!(!SearchOptions::default().ignores_case("foo"));
+    }
+}
@@ -0,0 +1,115 @@
+use core::iter;
+use core::ops::Range;
+use std::collections::HashSet;
+use std::fs;
+use std::io::Error;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// The known words to spell-check prose against, e.g., `/usr/share/dict/words`.
+#[derive(Default)]
+pub struct Dictionary {
+    /// Lowercased, so that capitalized words at the start of a sentence are known.
+    words: HashSet<String>,
+}
+
+impl Dictionary {
+    /// Loads a wordlist with one word per line.
+    /// # Errors
+    /// Returns an error if the file can't be read.
+    pub fn load(path: &str) -> Result<Self, Error> {
+        Ok(Self::from_words(fs::read_to_string(path)?.lines()))
+    }
+
+    pub fn from_words<'word, I: IntoIterator<Item = &'word str>>(words: I) -> Self {
+        Self {
+            words: words
+                .into_iter()
+                .map(str::trim)
+                .filter(|word| !word.is_empty())
+                .map(str::to_lowercase)
+                .collect(),
+        }
+    }
+
+    #[must_use]
+    pub fn contains(&self, word: &str) -> bool {
+        self.words.contains(&word.to_lowercase())
+    }
+
+    /// The grapheme index ranges of the misspelled words in the line. Words with digits, as well
+    /// as URLs, are skipped.
+    #[must_use]
+    pub fn misspelled_ranges(&self, line: &str) -> Vec<Range<usize>> {
+        let mut ranges = Vec::new();
+        // The byte index at which each grapheme starts.
+        let grapheme_indices: Vec<usize> = line.grapheme_indices(true).map(|(i, _)| i).collect();
+        let grapheme_index = |byte_index: usize| {
+            grapheme_indices
+                .binary_search(&byte_index)
+                .unwrap_or_else(|index| index)
+        };
+        let urls = url_spans(line);
+        for (token_start, token) in line.split_word_bound_indices() {
+            let is_word = token.chars().any(char::is_alphabetic);
+            let has_digit = token.chars().any(|c| c.is_ascii_digit());
+            let is_in_url = urls.iter().any(|url| url.contains(&token_start));
+            if !is_word || has_digit || is_in_url || self.contains(token) {
+                continue;
+            }
+            ranges.push(
+                grapheme_index(token_start)
+                    ..grapheme_index(token_start.saturating_add(token.len())),
+            );
+        }
+        ranges
+    }
+}
+
+/// The byte ranges of the URLs in the line, each extending to the next whitespace.
+fn url_spans(line: &str) -> Vec<Range<usize>> {
+    let mut spans = Vec::new();
+    let mut start = None;
+    for (index, c) in line.char_indices().chain(iter::once((line.len(), ' '))) {
+        if !c.is_whitespace() {
+            start = start.or(Some(index));
+            continue;
+        }
+        if let Some(start) = start.take() {
+            let word = line.get(start..index).unwrap_or_default();
+            if ["http://", "https://", "www."]
+                .iter()
+                .any(|prefix| word.starts_with(prefix))
+            {
+                spans.push(start..index);
+            }
+        }
+    }
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dictionary() -> Dictionary {
+        Dictionary::from_words(["the", "cat", "sat", "on", "caf\u{e9}"])
+    }
+
+    #[test]
+    fn finds_the_words_not_in_the_dictionary() {
+        let ranges = dictionary().misspelled_ranges("The cat szt on teh mat.");
+        assert_eq!(ranges, [8..11, 15..18, 19..22]);
+    }
+
+    #[test]
+    fn counts_graphemes_rather_than_bytes() {
+        let ranges = dictionary().misspelled_ranges("caf\u{e9} caf\u{e9}e");
+        assert_eq!(ranges, vec![5..10]);
+    }
+
+    #[test]
+    fn skips_words_with_digits_and_urls() {
+        let ranges = dictionary().misspelled_ranges("cat v2 https://exampel.org www.tset.com");
+        assert!(ranges.is_empty());
+    }
+}
@@ -0,0 +1,128 @@
+use crate::EditorError;
+use std::fs;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// Follows a file that grows while open, e.g., a log, by reading only what's appended to it.
+pub struct Tail {
+    path: PathBuf,
+    /// The number of bytes of the file read so far, up to the end of its last whole line.
+    len: u64,
+    /// When the file was modified as of the last check.
+    modified: Option<SystemTime>,
+}
+
+impl Tail {
+    /// Follows the file from its current end.
+    /// # Errors
+    /// Returns an error if the file can't be accessed.
+    pub fn new(path: &str) -> Result<Self, EditorError> {
+        let metadata = fs::metadata(path)?;
+        Ok(Self {
+            path: PathBuf::from(path),
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+
+    /// The whole lines appended to the file since the last call, each with its line ending;
+    /// `None` if there are none yet. A line being written is left until it ends.
+    /// # Errors
+    /// Returns an error if the file can't be read, or has shrunk, e.g., it was truncated or
+    /// replaced, so that there's no telling what was appended.
+    pub fn read_appended(&mut self) -> Result<Option<String>, EditorError> {
+        let metadata = fs::metadata(&self.path)?;
+        let modified = metadata.modified().ok();
+        if metadata.len() == self.len && modified == self.modified {
+            return Ok(None);
+        }
+        if metadata.len() < self.len {
+            return Err(EditorError::InvalidInput("the file has shrunk".to_owned()));
+        }
+        self.modified = modified;
+        let mut file = fs::File::open(&self.path)?;
+        file.seek(SeekFrom::Start(self.len))?;
+        let mut bytes = Vec::new();
+        #[allow(clippy::verbose_file_reads)] // Only the part after the seek is read.
+        file.read_to_end(&mut bytes)?;
+        let Some(end) = bytes.iter().rposition(|byte| *byte == b'\n') else {
+            return Ok(None);
+        };
+        let lines = bytes.get(..=end).unwrap_or_default();
+        self.len = self.len.saturating_add(lines.len() as u64);
+        Ok(Some(String::from_utf8_lossy(lines).into_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TempPath;
+    use std::fs::OpenOptions;
+    use std::io::Write;
+
+    fn append(path: &str, text: &str) {
+        let appended = OpenOptions::new()
+            .append(true)
+            .open(path)
+            .and_then(|mut file| file.write_all(text.as_bytes()));
+        if let Err(err) = appended {
+            panic!("failed to append to {path}: {err}");
+        }
+    }
+
+    fn tail(path: &str) -> Tail {
+        let Ok(tail) = Tail::new(path) else {
+            panic!("failed to follow {path}");
+        };
+        tail
+    }
+
+    #[test]
+    fn reads_only_the_appended_lines() {
+        let path = TempPath::with_content("grown.log", "old\n");
+        let mut tail = tail(&path);
+        assert!(matches!(tail.read_appended(), Ok(None)));
+        append(&path, "new\nnewer\n");
+        assert_eq!(
+            tail.read_appended().ok().flatten().as_deref(),
+            Some("new\nnewer\n")
+        );
+        assert!(matches!(tail.read_appended(), Ok(None)));
+    }
+
+    #[test]
+    fn leaves_an_unfinished_line_until_it_ends() {
+        let path = TempPath::with_content("partial.log", "");
+        let mut tail = tail(&path);
+        append(&path, "one\ntw");
+        assert_eq!(
+            tail.read_appended().ok().flatten().as_deref(),
+            Some("one\n")
+        );
+        append(&path, "o\n");
+        assert_eq!(
+            tail.read_appended().ok().flatten().as_deref(),
+            Some("two\n")
+        );
+    }
+
+    #[test]
+    fn shrunk_file_is_an_error() {
+        let path = TempPath::with_content("shrunk.log", "one\ntwo\n");
+        let mut tail = tail(&path);
+        if let Err(err) = fs::write(&path, "one\n") {
+            panic!("failed to truncate {path}: {err}");
+        }
+        assert!(matches!(
+            tail.read_appended(),
+            Err(EditorError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn missing_file_is_an_error() {
+        assert!(Tail::new("/nonexistent/hecto/file.log").is_err());
+    }
+}
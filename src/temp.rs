@@ -0,0 +1,66 @@
+use core::fmt;
+use core::ops::Deref;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+/// Tells apart the paths of the same name, e.g., of tests in different modules.
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A path under the temporary directory, unique to the test, whose file or directory is removed
+/// when dropped, so that it's cleaned up even if the test fails.
+pub struct TempPath {
+    path: String,
+}
+
+impl TempPath {
+    /// A path of the name that nothing is created at yet.
+    pub fn new(name: &str) -> Self {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let path = env::temp_dir().join(format!("hecto-{}-{id}-{name}", process::id()));
+        Self {
+            path: path.to_string_lossy().into_owned(),
+        }
+    }
+
+    /// A file of the name with the content.
+    /// # Panics
+    /// Panics if the file can't be written, which fails the test.
+    pub fn with_content<C: AsRef<[u8]>>(name: &str, content: C) -> Self {
+        let path = Self::new(name);
+        if let Err(err) = fs::write(&path, content) {
+            panic!("failed to write {path}: {err}");
+        }
+        path
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        // Whichever was created at the path, if anything.
+        drop(fs::remove_file(&self.path));
+        drop(fs::remove_dir_all(&self.path));
+    }
+}
+
+impl Deref for TempPath {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.path
+    }
+}
+
+impl AsRef<Path> for TempPath {
+    fn as_ref(&self) -> &Path {
+        Path::new(&self.path)
+    }
+}
+
+impl fmt::Display for TempPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.path)
+    }
+}
@@ -1,37 +1,180 @@
 use crate::Position;
-use std::io::{self, stdout, Error, Write};
-use termion::event::Key;
-use termion::input::TermRead;
+use core::{cmp, iter, str::FromStr};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, stdout, Error, ErrorKind, Read, Write};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use termion::event::{self, Event, Key};
 use termion::raw::{IntoRawMode, RawTerminal};
 use termion::{clear, color, cursor};
 
+/// With bracketed paste mode on, the terminal wraps pasted text with these, so that it can be told
+/// apart from typed keys.
+const PASTE_START: &[u8] = b"\x1b[200~";
+const PASTE_END: &[u8] = b"\x1b[201~";
+
+/// What the user has input.
+#[derive(Clone)]
+pub enum Input {
+    Key(Key),
+    /// Text pasted as a whole.
+    Paste(String),
+}
+
+/// How long the screen stays inverted by a flash.
+const FLASH_DURATION: Duration = Duration::from_millis(100);
+
+/// The rows at the bottom of the screen taken by the status bar and the message bar.
+const RESERVED_ROWS: u16 = 2;
+
 pub struct Size {
     pub width: u16,
     pub height: u16,
 }
 
+/// The shape of the cursor, set with the DECSCUSR escape sequence.
+#[derive(Default, PartialEq, Clone, Copy)]
+pub enum CursorShape {
+    /// Whatever the terminal is configured with.
+    #[default]
+    Default,
+    Block,
+    Underline,
+    Bar,
+}
+
+impl CursorShape {
+    /// The parameter of DECSCUSR; the shapes are steady (non-blinking).
+    fn code(self) -> u8 {
+        match self {
+            Self::Default => 0,
+            Self::Block => 2,
+            Self::Underline => 4,
+            Self::Bar => 6,
+        }
+    }
+}
+
+impl FromStr for CursorShape {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(Self::Default),
+            "block" => Ok(Self::Block),
+            "underline" => Ok(Self::Underline),
+            "bar" => Ok(Self::Bar),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Where the output goes.
+enum Output {
+    Raw {
+        // As long as this variable is alive, we are in raw mode.
+        // For information on what are terminal modes, see
+        // https://www.gnu.org/software/mit-scheme/documentation/stable/mit-scheme-ref/Terminal-Mode.html.
+        _raw_stdout: RawTerminal<io::Stdout>,
+    },
+    /// Kept in memory, so that tests can check what's output.
+    #[cfg(test)]
+    Mock(Vec<u8>),
+}
+
 pub struct Terminal {
     size: Size,
-    _raw_stdout: RawTerminal<io::Stdout>,
+    /// The bytes read from stdin but not yet parsed.
+    unread: Vec<u8>,
+    /// The keys of a paste that is read by `read_key`, to be given one by one.
+    pending_keys: VecDeque<Key>,
+    /// The input read ahead while looking for a cancel key, to be given before any newer input.
+    read_ahead: VecDeque<Input>,
+    /// The input read by a thread of its own, started once input is waited for with a timeout.
+    reader: Option<Receiver<Vec<u8>>>,
+    /// The terminal itself, which the input is read from instead of the standard input if that's
+    /// not a terminal, e.g., errors are piped into the editor.
+    tty: Option<Arc<File>>,
+    /// The shape the cursor was last set to, so that it's set again only once it changes.
+    cursor_shape: Option<CursorShape>,
+    output: Output,
 }
 
 impl Terminal {
     /// # Errors
-    /// Returns an error if the terminal size can't be obtained or if the terminal can't be put into raw mode.
+    /// Returns an error if the terminal size can't be obtained, if the terminal can't be put into raw
+    /// mode, or if it can't be opened to read the input from when the standard input isn't it.
     pub fn new() -> Result<Self, Error> {
         let size = termion::terminal_size()?;
+        let tty = if termion::is_tty(&io::stdin()) {
+            None
+        } else {
+            Some(Arc::new(termion::get_tty()?))
+        };
+        // Turn on bracketed paste mode.
+        print!("\x1b[?2004h");
         Ok(Self {
             size: Size {
                 width: size.0,
-                height: size.1.saturating_sub(2 /* status bar & message bar */),
+                height: size.1,
+            },
+            unread: Vec::new(),
+            pending_keys: VecDeque::new(),
+            read_ahead: VecDeque::new(),
+            reader: None,
+            tty,
+            cursor_shape: None,
+            output: Output::Raw {
+                _raw_stdout: stdout().into_raw_mode()?,
             },
-            // As long as this variable is alive, we are in raw mode.
-            // For information on what are terminal modes, see
-            // https://www.gnu.org/software/mit-scheme/documentation/stable/mit-scheme-ref/Terminal-Mode.html.
-            _raw_stdout: stdout().into_raw_mode()?,
         })
     }
 
+    /// A terminal of the size that reads the input pushed with `push_input` and keeps its output
+    /// in memory. Reading past the input fails rather than blocks.
+    #[cfg(test)]
+    #[must_use]
+    pub fn mock(width: u16, height: u16) -> Self {
+        // No one sends to the reader, so it's disconnected.
+        let (_, reader) = mpsc::channel();
+        Self {
+            size: Size { width, height },
+            unread: Vec::new(),
+            pending_keys: VecDeque::new(),
+            read_ahead: VecDeque::new(),
+            reader: Some(reader),
+            tty: None,
+            cursor_shape: None,
+            output: Output::Mock(Vec::new()),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn push_input(&mut self, input: &[u8]) {
+        self.unread.extend_from_slice(input);
+    }
+
+    /// Whether any of the pushed input is left to read.
+    #[cfg(test)]
+    #[must_use]
+    pub fn has_input(&self) -> bool {
+        !self.unread.is_empty() || !self.pending_keys.is_empty() || !self.read_ahead.is_empty()
+    }
+
+    /// What's written through the terminal itself, e.g., the cursor shape and the flash, as
+    /// opposed to what's printed.
+    #[cfg(test)]
+    #[must_use]
+    pub fn output(&self) -> &[u8] {
+        match &self.output {
+            Output::Raw { .. } => &[],
+            Output::Mock(output) => output,
+        }
+    }
+
     pub fn clear_screen() {
         print!("{}", clear::All);
     }
@@ -73,24 +216,346 @@ impl Terminal {
         print!("{}", cursor::Show);
     }
 
+    /// Sets the cursor shape, unless it's already the shape.
+    pub fn set_cursor_shape(&mut self, shape: CursorShape) {
+        if self.cursor_shape == Some(shape) {
+            return;
+        }
+        self.cursor_shape = Some(shape);
+        self.write(&format!("\x1b[{} q", shape.code()));
+    }
+
+    /// Inverts the colors of the screen for a moment, with the DECSCNM reverse video mode.
+    /// # Errors
+    /// Returns an error if the terminal is not flushed successfully.
+    pub fn flash(&mut self) -> Result<(), Error> {
+        self.write("\x1b[?5h");
+        Self::flush()?;
+        self.pause(FLASH_DURATION);
+        self.write("\x1b[?5l");
+        Self::flush()
+    }
+
+    /// Rings the audible bell of the terminal.
+    pub fn ring_bell(&mut self) {
+        self.write("\x07");
+    }
+
+    fn write(&mut self, s: &str) {
+        match &mut self.output {
+            Output::Raw { .. } => print!("{s}"),
+            #[cfg(test)]
+            Output::Mock(output) => output.extend_from_slice(s.as_bytes()),
+        }
+    }
+
+    /// Sleeps for the duration, which tests don't wait for.
+    fn pause(&self, duration: Duration) {
+        match self.output {
+            Output::Raw { .. } => thread::sleep(duration),
+            #[cfg(test)]
+            Output::Mock(_) => (),
+        }
+    }
+
     /// # Errors
     /// Returns an error if the terminal is not flushed successfully.
     pub fn flush() -> Result<(), Error> {
         io::stdout().flush()
     }
 
+    /// Reads a key, with pasted text given as if typed key by key.
     /// # Errors
     /// Returns an error if the key can't be read from the terminal.
-    pub fn read_key() -> Result<Key, Error> {
+    pub fn read_key(&mut self) -> Result<Key, Error> {
+        loop {
+            match self.read_input()? {
+                Input::Key(key) => return Ok(key),
+                Input::Paste(text) => self.pending_keys.extend(text.chars().map(Key::Char)),
+            }
+        }
+    }
+
+    /// Reads a key, or the text pasted as a whole. Unsupported escape sequences are skipped.
+    /// # Errors
+    /// Returns an error if the input can't be read from the terminal.
+    pub fn read_input(&mut self) -> Result<Input, Error> {
+        if let Some(key) = self.pending_keys.pop_front() {
+            return Ok(Input::Key(key));
+        }
+        if let Some(input) = self.read_ahead.pop_front() {
+            return Ok(input);
+        }
+        self.read_new_input()
+    }
+
+    /// Reads the input available without waiting, returning whether Esc or Ctrl-C is among it.
+    /// The rest of it is kept, to be read as if it was never looked at.
+    pub fn is_cancel_pressed(&mut self) -> bool {
+        while self.has_new_input(Duration::ZERO) {
+            match self.read_new_input() {
+                Ok(Input::Key(Key::Esc | Key::Ctrl('c'))) => return true,
+                Ok(input) => self.read_ahead.push_back(input),
+                Err(_) => return false,
+            }
+        }
+        false
+    }
+
+    /// Waits up to the timeout for input, returning whether there's any to read.
+    pub fn poll_input(&mut self, timeout: Duration) -> bool {
+        !self.pending_keys.is_empty() || !self.read_ahead.is_empty() || self.has_new_input(timeout)
+    }
+
+    /// Reads the input that's neither a key of a paste already read nor read ahead.
+    fn read_new_input(&mut self) -> Result<Input, Error> {
         loop {
-            if let Some(key) = io::stdin().lock().keys().next() {
-                return key;
+            if self.unread.is_empty() {
+                self.read_more()?;
+            }
+            if self.unread.starts_with(PASTE_START) {
+                self.unread.drain(..PASTE_START.len());
+                return self.read_paste().map(Input::Paste);
+            }
+            if let Some(key) = self.parse_key() {
+                return Ok(Input::Key(key));
             }
         }
     }
 
+    /// Like `poll_input`, but for the input that's not yet read.
+    fn has_new_input(&mut self, timeout: Duration) -> bool {
+        if !self.unread.is_empty() {
+            return true;
+        }
+        let tty = &self.tty;
+        let reader = self.reader.get_or_insert_with(|| spawn_reader(tty.clone()));
+        match reader.recv_timeout(timeout) {
+            Ok(bytes) => {
+                self.unread.extend(bytes);
+                true
+            }
+            Err(RecvTimeoutError::Timeout) => false,
+            // Reading fails right away.
+            Err(RecvTimeoutError::Disconnected) => true,
+        }
+    }
+
+    /// Reads whatever is available, blocking until there's at least a byte.
+    fn read_more(&mut self) -> Result<(), Error> {
+        let bytes = read_available(self.reader.as_ref(), self.tty.as_deref())?;
+        self.unread.extend(bytes);
+        Ok(())
+    }
+
+    /// Reads until the end of the paste, with the start already consumed.
+    fn read_paste(&mut self) -> Result<String, Error> {
+        loop {
+            if let Some(end) = self
+                .unread
+                .windows(PASTE_END.len())
+                .position(|window| window == PASTE_END)
+            {
+                let pasted: Vec<u8> = self.unread.drain(..end).collect();
+                self.unread.drain(..PASTE_END.len());
+                return Ok(String::from_utf8_lossy(&pasted).into_owned());
+            }
+            self.read_more()?;
+        }
+    }
+
+    /// Parses a key from the start of the unread bytes, consuming them.
+    /// `None` if they're not a key, e.g., a mouse event or an unsupported escape sequence.
+    fn parse_key(&mut self) -> Option<Key> {
+        let first = *self.unread.first()?;
+        // A lone escape is the Esc key rather than the start of an escape sequence.
+        if first == b'\x1b' && self.unread.len() == 1 {
+            self.unread.clear();
+            return Some(Key::Esc);
+        }
+        let mut consumed = 1usize;
+        let event = {
+            let reader = self.reader.as_ref();
+            let tty = self.tty.as_deref();
+            let unread = &mut self.unread;
+            // An incomplete sequence is completed by reading further into the unread bytes, so
+            // that the bytes of the following input are kept.
+            let mut bytes = iter::from_fn(|| {
+                while consumed >= unread.len() {
+                    let more = read_available(reader, tty).ok()?;
+                    if more.is_empty() {
+                        return None;
+                    }
+                    unread.extend(more);
+                }
+                let byte = unread.get(consumed).copied()?;
+                consumed = consumed.saturating_add(1);
+                Some(Ok(byte))
+            });
+            event::parse_event(first, &mut bytes)
+        };
+        self.unread.drain(..cmp::min(consumed, self.unread.len()));
+        match event {
+            Ok(Event::Key(key)) => Some(key),
+            _ => None,
+        }
+    }
+
+    /// The size of the whole screen.
     #[must_use]
     pub fn size(&self) -> &Size {
         &self.size
     }
+
+    /// The size of the screen where the text is drawn, which excludes the rows of the status bar
+    /// and the message bar.
+    #[must_use]
+    pub fn text_area_size(&self) -> Size {
+        Size {
+            width: self.size.width,
+            height: self.size.height.saturating_sub(RESERVED_ROWS),
+        }
+    }
+}
+
+/// Reads whatever input is available, blocking until there's at least a byte; from the reader
+/// thread if it's started, since it's the one reading the input then.
+fn read_available(
+    reader: Option<&Receiver<Vec<u8>>>,
+    tty: Option<&File>,
+) -> Result<Vec<u8>, Error> {
+    if let Some(reader) = reader {
+        return reader
+            .recv()
+            .map_err(|_disconnected| Error::new(ErrorKind::UnexpectedEof, "the input has ended"));
+    }
+    let mut buf = [0u8; 4096];
+    let len = read_keys(tty, &mut buf)?;
+    Ok(buf.get(..len).unwrap_or_default().to_vec())
+}
+
+/// Reads from the terminal if given, or else from the standard input.
+fn read_keys(tty: Option<&File>, buf: &mut [u8]) -> io::Result<usize> {
+    match tty {
+        Some(mut tty) => tty.read(buf),
+        None => io::stdin().lock().read(buf),
+    }
+}
+
+/// Starts a thread that reads the input as it comes, so that it can be waited for with a timeout.
+fn spawn_reader(tty: Option<Arc<File>>) -> Receiver<Vec<u8>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buf = [0u8; 4096];
+        // Stops once the input ends or fails, or no one is receiving.
+        while let Ok(len @ 1..) = read_keys(tty.as_deref(), &mut buf) {
+            if sender
+                .send(buf.get(..len).unwrap_or_default().to_vec())
+                .is_err()
+            {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+impl Drop for Terminal {
+    /// Gives the cursor back to the terminal as it was.
+    fn drop(&mut self) {
+        self.set_cursor_shape(CursorShape::Default);
+        // Turn off bracketed paste mode.
+        self.write("\x1b[?2004l");
+        // NOTE: Nothing can be done if flushing fails while dropping.
+        drop(Self::flush());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sets_each_cursor_shape_with_its_sequence() {
+        let mut terminal = Terminal::mock(80, 24);
+        terminal.set_cursor_shape(CursorShape::Block);
+        terminal.set_cursor_shape(CursorShape::Underline);
+        terminal.set_cursor_shape(CursorShape::Bar);
+        terminal.set_cursor_shape(CursorShape::Default);
+        assert_eq!(terminal.output(), b"\x1b[2 q\x1b[4 q\x1b[6 q\x1b[0 q");
+    }
+
+    #[test]
+    fn sets_the_cursor_shape_only_on_change() {
+        let mut terminal = Terminal::mock(80, 24);
+        terminal.set_cursor_shape(CursorShape::Bar);
+        terminal.set_cursor_shape(CursorShape::Bar);
+        assert_eq!(terminal.output(), b"\x1b[6 q");
+    }
+
+    #[test]
+    fn parses_cursor_shapes() {
+        assert!("bar".parse::<CursorShape>() == Ok(CursorShape::Bar));
+        assert!("underline".parse::<CursorShape>() == Ok(CursorShape::Underline));
+        assert!("beam".parse::<CursorShape>().is_err());
+    }
+
+    #[test]
+    fn reads_a_paste_between_the_markers_as_a_whole() {
+        let mut terminal = Terminal::mock(80, 24);
+        terminal.push_input(b"a\x1b[200~b\rc\x1b[Ad\x1b[201~e");
+        assert!(matches!(
+            terminal.read_input(),
+            Ok(Input::Key(Key::Char('a')))
+        ));
+        let Ok(Input::Paste(text)) = terminal.read_input() else {
+            panic!("the paste isn't read as a whole");
+        };
+        assert_eq!(text, "b\rc\x1b[Ad");
+        assert!(matches!(
+            terminal.read_input(),
+            Ok(Input::Key(Key::Char('e')))
+        ));
+        assert!(!terminal.has_input());
+    }
+
+    #[test]
+    fn reads_a_paste_key_by_key() {
+        let mut terminal = Terminal::mock(80, 24);
+        terminal.push_input(b"\x1b[200~ab\x1b[201~");
+        assert!(matches!(terminal.read_key(), Ok(Key::Char('a'))));
+        assert!(matches!(terminal.read_key(), Ok(Key::Char('b'))));
+        assert!(!terminal.has_input());
+    }
+
+    #[test]
+    fn text_area_leaves_out_the_bars() {
+        let terminal = Terminal::mock(80, 24);
+        let text_area = terminal.text_area_size();
+        assert_eq!(text_area.width, 80);
+        assert_eq!(text_area.height, 22);
+        assert_eq!(Terminal::mock(80, 1).text_area_size().height, 0);
+    }
+
+    #[test]
+    fn looking_for_a_cancel_key_keeps_the_other_input() {
+        let mut terminal = Terminal::mock(80, 24);
+        terminal.push_input(b"a\x1b[200~b\x1b[201~");
+        assert!(!terminal.is_cancel_pressed());
+        terminal.push_input(b"c");
+        assert!(matches!(terminal.read_key(), Ok(Key::Char('a'))));
+        assert!(matches!(terminal.read_key(), Ok(Key::Char('b'))));
+        assert!(matches!(terminal.read_key(), Ok(Key::Char('c'))));
+        assert!(!terminal.has_input());
+    }
+
+    #[test]
+    fn finds_a_cancel_key_after_other_input() {
+        let mut terminal = Terminal::mock(80, 24);
+        terminal.push_input(b"a\x03b");
+        assert!(terminal.is_cancel_pressed());
+        assert!(matches!(terminal.read_key(), Ok(Key::Char('a'))));
+        assert!(matches!(terminal.read_key(), Ok(Key::Char('b'))));
+        assert!(!terminal.has_input());
+    }
 }